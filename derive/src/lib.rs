@@ -0,0 +1,83 @@
+//! `#[derive(FromRow)]` for the report models.
+//!
+//! Generates an implementation of `crate::db::row::FromRow` that reads every field from the
+//! column of the same name. Field attributes:
+//!
+//! - `#[row(rename = "column")]` reads a differently named column.
+//! - `#[row(default)]` falls back to `Default::default()` when the column is NULL or missing.
+//! - `#[row(with = "path::to::fn")]` passes the value read through a function before storing it.
+//!
+//! `Option<T>` fields accept NULL as `None`; type coercion is handled by `FromValue`.
+
+use proc_macro::TokenStream;
+use quote::quote;
+use syn::{parse_macro_input, Data, DeriveInput, Fields, LitStr, Path};
+
+#[proc_macro_derive(FromRow, attributes(row))]
+pub fn derive_from_row(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    match expand(&input) {
+        Ok(tokens) => tokens.into(),
+        Err(e) => e.to_compile_error().into(),
+    }
+}
+
+fn expand(input: &DeriveInput) -> syn::Result<proc_macro2::TokenStream> {
+    let name = &input.ident;
+
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => &fields.named,
+            _ => return Err(syn::Error::new_spanned(name, "FromRow needs a struct with named fields")),
+        },
+        _ => return Err(syn::Error::new_spanned(name, "FromRow can only be derived for structs")),
+    };
+
+    let mut assignments = Vec::new();
+    for field in fields {
+        let ident = field.ident.as_ref().expect("named field");
+        let mut column = ident.to_string();
+        let mut default = false;
+        let mut with: Option<Path> = None;
+
+        for attr in field.attrs.iter().filter(|a| a.path().is_ident("row")) {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("rename") {
+                    column = meta.value()?.parse::<LitStr>()?.value();
+                    Ok(())
+                } else if meta.path.is_ident("default") {
+                    default = true;
+                    Ok(())
+                } else if meta.path.is_ident("with") {
+                    with = Some(meta.value()?.parse::<LitStr>()?.parse()?);
+                    Ok(())
+                } else {
+                    Err(meta.error("expected `rename = \"...\"`, `default` or `with = \"...\"`"))
+                }
+            })?;
+        }
+
+        let getter = if default {
+            quote!(crate::db::row::get_column_or_default)
+        } else {
+            quote!(crate::db::row::get_column)
+        };
+        let value = match with {
+            Some(with) => quote!(#with(#getter(row, #column)?)),
+            None => quote!(#getter(row, #column)?),
+        };
+        assignments.push(quote! { #ident: #value });
+    }
+
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    Ok(quote! {
+        impl #impl_generics crate::db::row::FromRow for #name #ty_generics #where_clause {
+            fn from_row<R: crate::db::row::RowSource + ?Sized>(row: &R) -> ::std::result::Result<Self, crate::db::row::MappingError> {
+                Ok(Self {
+                    #(#assignments,)*
+                })
+            }
+        }
+    })
+}
@@ -1 +1,2 @@
-pub mod mssqlapi;
\ No newline at end of file
+pub mod mssqlapi;
+pub mod streaming;
@@ -1,24 +1,86 @@
-use crate::db::database::DatabaseMSSQL;
-use actix_web::{get, web, HttpResponse, Responder};
+use crate::api::streaming::{stream_report, FormatQuery};
+use crate::db::grid::GridQuery;
+use crate::db::repository::ReportRepository;
+use crate::db::timeout::{QueryTimeout, ReportTimeouts};
+use crate::models::customerprofile::CustomerProfileQuery;
+use crate::models::employeeperformance::EmployeePerformanceQuery;
+use crate::models::gridrequest::GridRequest;
+use crate::models::ordersreport::ORDERS_GRID_COLUMNS;
+use crate::models::reportfilter::{ReportFilter, YearRange};
+use crate::models::saleschoropleth::ChoroplethQuery;
+use crate::models::shipperperformance::ShipperPerformanceQuery;
+use crate::models::productsales::ProductSalesQuery;
+use crate::models::salestimeseries::TimeSeriesQuery;
+use crate::models::rfmsegmentation::RfmQuery;
+use crate::models::cohortretention::CohortQuery;
+use crate::models::salesforecast::SalesForecastQuery;
+use crate::models::abcanalysis::AbcQuery;
+use crate::models::marketbasket::MarketBasketQuery;
+use crate::models::topperformers::TopPerformersQuery;
+use actix_web::{get, post, web, HttpResponse, Responder};
+use anyhow::Error;
+use validator::Validate;
 
+// Every report endpoint takes the ReportFilter query string, e.g.
+// ?from_date=2023-01-01&to_date=2023-06-30&country=UK&employee_id=3
+// and answers 400 with the validation errors when it does not hold up.
 
+// A report that ran past its timeout answers 504, so the dashboard can tell "slow" from "broken"
+pub fn error_response(e: Error, message: &str) -> HttpResponse {
+    match e.downcast_ref::<QueryTimeout>() {
+        Some(timeout) => HttpResponse::GatewayTimeout().body(timeout.to_string()),
+        None => HttpResponse::InternalServerError().body(message.to_string()),
+    }
+}
+
+// Streamed row by row, `?format=ndjson` gives one JSON object per line instead of an array
 #[get("/get_orders_report")]
-async fn get_orders_report(db: web::Data<DatabaseMSSQL>) -> impl Responder {
-    match db.sales_orders_report().await {
-        Ok(orders_data) => {
-            if orders_data.is_empty() {
-                HttpResponse::NotFound().body("No data available in the database")
-            } else {    
-                HttpResponse::Ok().json(orders_data)
-            }
-        }
-        Err(_) => HttpResponse::InternalServerError().body("Error retrieving Orders data"),
+async fn get_orders_report(db: web::Data<dyn ReportRepository>, timeouts: web::Data<ReportTimeouts>, filter: web::Query<ReportFilter>, query: web::Query<FormatQuery>) -> impl Responder {
+    if let Err(e) = filter.validate() {
+        return HttpResponse::BadRequest().json(e);
+    }
+    let rows = timeouts.limit_stream("orders_report", db.stream_orders_report(&filter));
+    stream_report(rows, query.format, "Error retrieving Orders data").await
+}
+
+// One page for the dashboard grid. The body is ag-Grid's block request
+// {"startRow":0,"endRow":100,"sortModel":[...],"filterModel":{...}}; the rows come back as an
+// array and the number of matching rows in the X-Total-Count header.
+#[post("/get_orders_report")]
+async fn query_orders_report(db: web::Data<dyn ReportRepository>, timeouts: web::Data<ReportTimeouts>, filter: web::Query<ReportFilter>, request: web::Json<GridRequest>) -> impl Responder {
+    if let Err(e) = filter.validate() {
+        return HttpResponse::BadRequest().json(e);
+    }
+    let query = match GridQuery::parse(&request, ORDERS_GRID_COLUMNS) {
+        Ok(query) => query,
+        Err(e) => return HttpResponse::BadRequest().body(e.to_string()),
+    };
+    match timeouts.run("orders_report", db.get_orders_report_page(&filter, &query)).await {
+        Ok(page) => HttpResponse::Ok()
+            .insert_header(("X-Total-Count", page.total.to_string()))
+            .json(page.rows),
+        Err(e) => error_response(e, "Error retrieving Orders data"),
+    }
+}
+
+// Drill-down from a row of the orders report: the order header and every line with its product
+#[get("/orders/{id}")]
+async fn get_order_detail(db: web::Data<dyn ReportRepository>, timeouts: web::Data<ReportTimeouts>, order_id: web::Path<i32>) -> impl Responder {
+    let order_id = order_id.into_inner();
+    match timeouts.run("order_detail", db.get_order_detail(order_id)).await {
+        Ok(Some(order)) => HttpResponse::Ok().json(order),
+        Ok(None) => HttpResponse::NotFound().body(format!("Order {} not found", order_id)),
+        Err(e) => error_response(e, "Error retrieving Order data"),
     }
 }
 
+// ?from_year=2021&to_year=2023 picks the year columns, by default every year with sales
 #[get("/get_customer_sales_by_year")]
-async fn get_customer_sales_by_year(db: web::Data<DatabaseMSSQL>) -> impl Responder {
-    match db.get_customer_sales_by_year().await {
+async fn get_customer_sales_by_year(db: web::Data<dyn ReportRepository>, timeouts: web::Data<ReportTimeouts>, filter: web::Query<ReportFilter>, years: web::Query<YearRange>) -> impl Responder {
+    if let Err(e) = filter.validate().and(years.validate()) {
+        return HttpResponse::BadRequest().json(e);
+    }
+    match timeouts.run("customer_sales_by_year", db.get_customer_sales_by_year(&filter, &years)).await {
         Ok(customer_data) => {
             if customer_data.is_empty() {
                 HttpResponse::NotFound().body("No data available in the database")
@@ -26,13 +88,32 @@ async fn get_customer_sales_by_year(db: web::Data<DatabaseMSSQL>) -> impl Respon
                 HttpResponse::Ok().json(customer_data)
             }
         }
-        Err(_) => HttpResponse::InternalServerError().body("Error retrieving Customer data"),
+        Err(e) => error_response(e, "Error retrieving Customer data"),
     }
 }
 
+// Customer 360: contact info, order totals, yearly trend, top products and the employees who
+// served the customer. ?top_products=5, the ReportFilter narrows the orders it is built from.
+#[get("/customers/{id}/profile")]
+async fn get_customer_profile(db: web::Data<dyn ReportRepository>, timeouts: web::Data<ReportTimeouts>, customer_id: web::Path<i32>, filter: web::Query<ReportFilter>, query: web::Query<CustomerProfileQuery>) -> impl Responder {
+    if let Err(e) = filter.validate().and(query.validate()) {
+        return HttpResponse::BadRequest().json(e);
+    }
+    let customer_id = customer_id.into_inner();
+    match timeouts.run("customer_profile", db.get_customer_profile(customer_id, &filter, &query)).await {
+        Ok(Some(profile)) => HttpResponse::Ok().json(profile),
+        Ok(None) => HttpResponse::NotFound().body(format!("Customer {} not found", customer_id)),
+        Err(e) => error_response(e, "Error retrieving Customer profile"),
+    }
+}
+
+// ?top=10&year=2023&metric=revenue|order_count|quantity, by default the top 10 by revenue in the latest year
 #[get("/get_top_performers")]
-async fn get_top_performers(db: web::Data<DatabaseMSSQL>) -> impl Responder {
-    match db.get_top_performers().await {
+async fn get_top_performers(db: web::Data<dyn ReportRepository>, timeouts: web::Data<ReportTimeouts>, filter: web::Query<ReportFilter>, query: web::Query<TopPerformersQuery>) -> impl Responder {
+    if let Err(e) = filter.validate().and(query.validate()) {
+        return HttpResponse::BadRequest().json(e);
+    }
+    match timeouts.run("top_performers", db.get_top_performers(&filter, &query)).await {
         Ok(top_performers_list) => {
             if top_performers_list.is_empty() {
                 HttpResponse::NotFound().body("No data available in the database")
@@ -40,13 +121,36 @@ async fn get_top_performers(db: web::Data<DatabaseMSSQL>) -> impl Responder {
                 HttpResponse::Ok().json(top_performers_list)
             }
         }
-        Err(_) => HttpResponse::InternalServerError().body("Error retrieving Top Performers data"),
+        Err(e) => error_response(e, "Error retrieving Top Performers data"),
+    }
+}
+
+// ?period=year|quarter|month. Each employee's own sales with the totals of everyone below them
+// in the manager hierarchy, so a manager sees the whole team.
+#[get("/get_employee_performance")]
+async fn get_employee_performance(db: web::Data<dyn ReportRepository>, timeouts: web::Data<ReportTimeouts>, filter: web::Query<ReportFilter>, query: web::Query<EmployeePerformanceQuery>) -> impl Responder {
+    if let Err(e) = filter.validate() {
+        return HttpResponse::BadRequest().json(e);
+    }
+    match timeouts.run("employee_performance", db.get_employee_performance(&filter, &query)).await {
+        Ok(employee_performance) => {
+            if employee_performance.is_empty() {
+                HttpResponse::NotFound().body("No data available in the database")
+            } else {
+                HttpResponse::Ok().json(employee_performance)
+            }
+        }
+        Err(e) => error_response(e, "Error retrieving Employee Performance data"),
     }
 }
 
+// ?year=2023&metric=revenue|order_count|freight|distinct_customers, or a from_date/to_date range
 #[get("/get_sales_choropleth")]
-async fn get_sales_choropleth(db: web::Data<DatabaseMSSQL>) -> impl Responder {
-    match db.get_sales_choropleth().await {
+async fn get_sales_choropleth(db: web::Data<dyn ReportRepository>, timeouts: web::Data<ReportTimeouts>, filter: web::Query<ReportFilter>, query: web::Query<ChoroplethQuery>) -> impl Responder {
+    if let Err(e) = filter.validate().and(query.validate()) {
+        return HttpResponse::BadRequest().json(e);
+    }
+    match timeouts.run("sales_choropleth", db.get_sales_choropleth(&filter, &query)).await {
         Ok(sales_choropleth_list) => {
             if sales_choropleth_list.is_empty() {   
                 HttpResponse::NotFound().body("No data available in the database")
@@ -54,12 +158,246 @@ async fn get_sales_choropleth(db: web::Data<DatabaseMSSQL>) -> impl Responder {
                 HttpResponse::Ok().json(sales_choropleth_list)
             }
         }
-        Err(_) => HttpResponse::InternalServerError().body("Error retrieving Sales Choropleth data"),
+        Err(e) => error_response(e, "Error retrieving Sales Choropleth data"),
     }
 }
 
+// ?group_by=shipper|country|shipper_country&period=year|quarter|month, over the from_date/to_date range
+#[get("/get_shipper_performance")]
+async fn get_shipper_performance(db: web::Data<dyn ReportRepository>, timeouts: web::Data<ReportTimeouts>, filter: web::Query<ReportFilter>, query: web::Query<ShipperPerformanceQuery>) -> impl Responder {
+    if let Err(e) = filter.validate() {
+        return HttpResponse::BadRequest().json(e);
+    }
+    match timeouts.run("shipper_performance", db.get_shipper_performance(&filter, &query)).await {
+        Ok(shipper_performance) => {
+            if shipper_performance.is_empty() {
+                HttpResponse::NotFound().body("No data available in the database")
+            } else {
+                HttpResponse::Ok().json(shipper_performance)
+            }
+        }
+        Err(e) => error_response(e, "Error retrieving Shipper Performance data"),
+    }
+}
 
+// ?top=10 or ?bottom=10, metric=revenue|units|order_count|customer_count, period=year|quarter|month, category_id
+#[get("/get_product_sales")]
+async fn get_product_sales(db: web::Data<dyn ReportRepository>, timeouts: web::Data<ReportTimeouts>, filter: web::Query<ReportFilter>, query: web::Query<ProductSalesQuery>) -> impl Responder {
+    if let Err(e) = filter.validate().and(query.validate()) {
+        return HttpResponse::BadRequest().json(e);
+    }
+    match timeouts.run("product_sales", db.get_product_sales(&filter, &query)).await {
+        Ok(product_sales) => {
+            if product_sales.is_empty() {
+                HttpResponse::NotFound().body("No data available in the database")
+            } else {
+                HttpResponse::Ok().json(product_sales)
+            }
+        }
+        Err(e) => error_response(e, "Error retrieving Product Sales data"),
+    }
+}
 
+// Same parameters as /get_product_sales
+#[get("/get_category_sales")]
+async fn get_category_sales(db: web::Data<dyn ReportRepository>, timeouts: web::Data<ReportTimeouts>, filter: web::Query<ReportFilter>, query: web::Query<ProductSalesQuery>) -> impl Responder {
+    if let Err(e) = filter.validate().and(query.validate()) {
+        return HttpResponse::BadRequest().json(e);
+    }
+    match timeouts.run("category_sales", db.get_category_sales(&filter, &query)).await {
+        Ok(category_sales) => {
+            if category_sales.is_empty() {
+                HttpResponse::NotFound().body("No data available in the database")
+            } else {
+                HttpResponse::Ok().json(category_sales)
+            }
+        }
+        Err(e) => error_response(e, "Error retrieving Category Sales data"),
+    }
+}
 
+// ?bucket=day|week|month|quarter|year&rolling=3&cumulative=true&yoy=true, over the from_date/to_date range
+#[get("/get_sales_timeseries")]
+async fn get_sales_timeseries(db: web::Data<dyn ReportRepository>, timeouts: web::Data<ReportTimeouts>, filter: web::Query<ReportFilter>, query: web::Query<TimeSeriesQuery>) -> impl Responder {
+    if let Err(e) = filter.validate().and(query.validate()).and(query.validate_range(&filter)) {
+        return HttpResponse::BadRequest().json(e);
+    }
+    match timeouts.run("sales_timeseries", db.get_sales_timeseries(&filter, &query)).await {
+        Ok(sales_timeseries) => {
+            if sales_timeseries.is_empty() {
+                HttpResponse::NotFound().body("No data available in the database")
+            } else {
+                HttpResponse::Ok().json(sales_timeseries)
+            }
+        }
+        Err(e) => error_response(e, "Error retrieving Sales time series data"),
+    }
+}
+
+// ?as_of=2023-12-31&segment=champions|loyal_customers|...|hibernating, plus the usual filters
+#[get("/get_rfm_segments")]
+async fn get_rfm_segments(db: web::Data<dyn ReportRepository>, timeouts: web::Data<ReportTimeouts>, filter: web::Query<ReportFilter>, query: web::Query<RfmQuery>) -> impl Responder {
+    if let Err(e) = filter.validate() {
+        return HttpResponse::BadRequest().json(e);
+    }
+    match timeouts.run("rfm_segments", db.get_rfm_segments(&filter, &query)).await {
+        Ok(Some(rfm_segments)) => HttpResponse::Ok().json(rfm_segments),
+        Ok(None) => HttpResponse::NotFound().body("No data available in the database"),
+        Err(e) => error_response(e, "Error retrieving RFM segments data"),
+    }
+}
+
+// ?cohort=month|quarter|year&max_periods=12, cohorts by first order date within from_date/to_date
+#[get("/get_cohort_retention")]
+async fn get_cohort_retention(db: web::Data<dyn ReportRepository>, timeouts: web::Data<ReportTimeouts>, filter: web::Query<ReportFilter>, query: web::Query<CohortQuery>) -> impl Responder {
+    if let Err(e) = filter.validate().and(query.validate()).and(query.validate_range(&filter)) {
+        return HttpResponse::BadRequest().json(e);
+    }
+    match timeouts.run("cohort_retention", db.get_cohort_retention(&filter, &query)).await {
+        Ok(cohort_retention) => {
+            if cohort_retention.is_empty() {
+                HttpResponse::NotFound().body("No data available in the database")
+            } else {
+                HttpResponse::Ok().json(cohort_retention)
+            }
+        }
+        Err(e) => error_response(e, "Error retrieving Cohort retention data"),
+    }
+}
 
+// ?horizon=6&by=total|country|category&method=auto|holt_winters|seasonal_naive&level=95
+#[get("/get_sales_forecast")]
+async fn get_sales_forecast(db: web::Data<dyn ReportRepository>, timeouts: web::Data<ReportTimeouts>, filter: web::Query<ReportFilter>, query: web::Query<SalesForecastQuery>) -> impl Responder {
+    if let Err(e) = filter.validate().and(query.validate()).and(query.validate_range(&filter)) {
+        return HttpResponse::BadRequest().json(e);
+    }
+    match timeouts.run("sales_forecast", db.get_sales_forecast(&filter, &query)).await {
+        Ok(sales_forecast) => {
+            if sales_forecast.is_empty() {
+                HttpResponse::NotFound().body("Not enough monthly history to forecast")
+            } else {
+                HttpResponse::Ok().json(sales_forecast)
+            }
+        }
+        Err(e) => error_response(e, "Error retrieving Sales forecast data"),
+    }
+}
+
+// ?dimension=customer|product&a=80&b=95, over the from_date/to_date range
+#[get("/get_abc_analysis")]
+async fn get_abc_analysis(db: web::Data<dyn ReportRepository>, timeouts: web::Data<ReportTimeouts>, filter: web::Query<ReportFilter>, query: web::Query<AbcQuery>) -> impl Responder {
+    if let Err(e) = filter.validate().and(query.validate()) {
+        return HttpResponse::BadRequest().json(e);
+    }
+    match timeouts.run("abc_analysis", db.get_abc_analysis(&filter, &query)).await {
+        Ok(Some(abc_analysis)) => HttpResponse::Ok().json(abc_analysis),
+        Ok(None) => HttpResponse::NotFound().body("No data available in the database"),
+        Err(e) => error_response(e, "Error retrieving ABC analysis data"),
+    }
+}
 
+// ?min_support=0.01&top=50, over the orders that pass the ReportFilter
+#[get("/get_market_basket")]
+async fn get_market_basket(db: web::Data<dyn ReportRepository>, timeouts: web::Data<ReportTimeouts>, filter: web::Query<ReportFilter>, query: web::Query<MarketBasketQuery>) -> impl Responder {
+    if let Err(e) = filter.validate().and(query.validate()) {
+        return HttpResponse::BadRequest().json(e);
+    }
+    match timeouts.run("market_basket", db.get_market_basket(&filter, &query)).await {
+        Ok(market_basket) => {
+            if market_basket.is_empty() {
+                HttpResponse::NotFound().body("No data available in the database")
+            } else {
+                HttpResponse::Ok().json(market_basket)
+            }
+        }
+        Err(e) => error_response(e, "Error retrieving Market Basket data"),
+    }
+}
+
+// "Frequently bought with" for one product, same parameters as /get_market_basket
+#[get("/products/{id}/bought_with")]
+async fn get_bought_with(db: web::Data<dyn ReportRepository>, timeouts: web::Data<ReportTimeouts>, product_id: web::Path<i32>, filter: web::Query<ReportFilter>, query: web::Query<MarketBasketQuery>) -> impl Responder {
+    if let Err(e) = filter.validate().and(query.validate()) {
+        return HttpResponse::BadRequest().json(e);
+    }
+    let product_id = product_id.into_inner();
+    match timeouts.run("bought_with", db.get_bought_with(product_id, &filter, &query)).await {
+        Ok(Some(bought_with)) => HttpResponse::Ok().json(bought_with),
+        Ok(None) => HttpResponse::NotFound().body(format!("Product {} not found in any order", product_id)),
+        Err(e) => error_response(e, "Error retrieving Bought With data"),
+    }
+}
+
+#[get("/pool_stats")]
+async fn get_pool_stats(db: web::Data<dyn ReportRepository>) -> impl Responder {
+    match db.pool_stats() {
+        Some(stats) => HttpResponse::Ok().json(stats),
+        None => HttpResponse::NotFound().body("The configured database does not use a connection pool"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::fixtures::FixtureData;
+    use crate::db::memory::DatabaseMemory;
+    use actix_web::{test, App};
+    use serde_json::{json, Value};
+    use std::collections::HashMap;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    // One order whose customer has no contact, whose employee has no title and that has no ship name.
+    fn nullable_fixture() -> FixtureData {
+        serde_json::from_value(json!({
+            "customers": [{"custid": 1, "companyname": "Customer NRZBB", "contactname": null, "contacttitle": "Owner",
+                           "city": "Berlin", "country": "Germany", "phone": "030-0074321"}],
+            "employees": [{"empid": 1, "lastname": "Davis", "firstname": "Sara", "title": null, "mgrid": null,
+                           "hiredate": "2012-05-01", "country": "USA"}],
+            "shippers": [{"shipperid": 1, "companyname": "Shipper GVSUA", "phone": "(503) 555-0137"}],
+            "categories": [{"categoryid": 1, "categoryname": "Beverages", "description": "Soft drinks, coffees, teas"}],
+            "products": [{"productid": 1, "productname": "Product HHYDP", "categoryid": 1, "unitprice": 18.0, "discontinued": false}],
+            "orders": [{"orderid": 10248, "custid": 1, "empid": 1, "orderdate": "2023-07-04", "requireddate": "2023-08-01",
+                        "shippeddate": null, "shipperid": 1, "freight": 32.38, "shipname": null, "shipcity": "Berlin",
+                        "shipcountry": "Germany"}],
+            "order_details": [{"orderid": 10248, "productid": 1, "unitprice": 18.0, "qty": 10, "discount": 0.0}],
+        }))
+        .unwrap()
+    }
+
+    async fn get_json(uri: &str) -> Value {
+        let db: Arc<dyn ReportRepository> = Arc::new(DatabaseMemory { data: nullable_fixture() });
+        let timeouts = ReportTimeouts { default: Duration::from_secs(5), overrides: HashMap::new() };
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::from(db))
+                .app_data(web::Data::new(timeouts))
+                .service(get_orders_report)
+                .service(get_top_performers),
+        )
+        .await;
+
+        let response = test::call_service(&app, test::TestRequest::get().uri(uri).to_request()).await;
+        assert!(response.status().is_success(), "{} answered {}", uri, response.status());
+        serde_json::from_slice(&test::read_body(response).await).unwrap()
+    }
+
+    #[actix_web::test]
+    async fn orders_report_serves_null_columns() {
+        let rows = get_json("/get_orders_report").await;
+        assert_eq!(rows.as_array().unwrap().len(), 1);
+        assert_eq!(rows[0]["order_id"], 10248);
+        assert_eq!(rows[0]["customer_contact_name"], Value::Null);
+        assert_eq!(rows[0]["employee_title"], Value::Null);
+        assert_eq!(rows[0]["ship_name"], Value::Null);
+        assert_eq!(rows[0]["billable_value"], 212.38);
+    }
+
+    #[actix_web::test]
+    async fn top_performers_serves_rows_with_null_columns() {
+        let rows = get_json("/get_top_performers").await;
+        assert_eq!(rows[0]["customer"], "Customer NRZBB");
+        assert_eq!(rows[0]["year"], 2023);
+        assert_eq!(rows[0]["employees"], json!(["Davis, Sara"]));
+    }
+}
@@ -0,0 +1,140 @@
+use actix_web::{web::Bytes, HttpResponse};
+use anyhow::Error;
+use futures::stream::{self, StreamExt};
+use serde::{Deserialize, Serialize};
+
+use crate::api::mssqlapi::error_response;
+use crate::db::stream::RowStream;
+
+#[derive(Deserialize, Debug, Default, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ResponseFormat {
+    #[default]
+    Json,
+    Ndjson,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct FormatQuery {
+    #[serde(default)]
+    pub format: ResponseFormat,
+}
+
+// Writes a report to the response as the rows arrive, either as one JSON array or as
+// newline-delimited JSON. The first row is awaited before answering, so an empty report
+// is still a 404 and a failing or timed out query still gets its 500 or 504.
+pub async fn stream_report<T>(mut rows: RowStream<T>, format: ResponseFormat, message: &str) -> HttpResponse
+where
+    T: Serialize + Send + 'static,
+{
+    let first = match rows.next().await {
+        Some(Ok(first)) => first,
+        Some(Err(e)) => return error_response(e, message),
+        None => return HttpResponse::NotFound().body("No data available in the database"),
+    };
+
+    let body = stream::once(async { Ok(first) })
+        .chain(rows)
+        .enumerate()
+        .map(move |(index, row)| {
+            let mut chunk = Vec::new();
+            if format == ResponseFormat::Json {
+                chunk.push(if index == 0 { b'[' } else { b',' });
+            }
+            serde_json::to_writer(&mut chunk, &row?)?;
+            if format == ResponseFormat::Ndjson {
+                chunk.push(b'\n');
+            }
+            Ok::<_, Error>(Bytes::from(chunk))
+        })
+        .chain(stream::iter((format == ResponseFormat::Json).then(|| Ok(Bytes::from_static(b"]")))));
+
+    // An error after the first row can only abort the response, the status is already sent
+    match format {
+        ResponseFormat::Json => HttpResponse::Ok().content_type("application/json").streaming(body),
+        ResponseFormat::Ndjson => HttpResponse::Ok().content_type("application/x-ndjson").streaming(body),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::body::MessageBody;
+    use actix_web::http::StatusCode;
+    use serde_json::{json, Value};
+    use std::pin::pin;
+
+    use crate::db::stream::iter_stream;
+
+    fn rows(values: Vec<Result<Value, Error>>) -> RowStream<Value> {
+        stream::iter(values).boxed()
+    }
+
+    // The body chunk by chunk. Like actix, stops at the first error, which drops the connection.
+    async fn chunks(response: HttpResponse) -> Vec<Result<String, String>> {
+        let mut body = pin!(response.into_body());
+        let mut chunks = Vec::new();
+        while let Some(chunk) = stream::poll_fn(|cx| body.as_mut().poll_next(cx)).next().await {
+            let failed = chunk.is_err();
+            chunks.push(chunk.map(|bytes| String::from_utf8(bytes.to_vec()).unwrap()).map_err(|e| e.to_string()));
+            if failed {
+                break;
+            }
+        }
+        chunks
+    }
+
+    async fn body(response: HttpResponse) -> String {
+        chunks(response).await.into_iter().collect::<Result<String, String>>().unwrap()
+    }
+
+    #[actix_web::test]
+    async fn json_is_one_array() {
+        let three = iter_stream(Ok(vec![json!({"id": 1}), json!({"id": 2}), json!({"id": 3})]));
+        let response = stream_report(three, ResponseFormat::Json, "Error").await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers().get("content-type").unwrap(), "application/json");
+        let array = body(response).await;
+        assert_eq!(array, r#"[{"id":1},{"id":2},{"id":3}]"#);
+        assert_eq!(serde_json::from_str::<Value>(&array).unwrap().as_array().unwrap().len(), 3);
+
+        let one = iter_stream(Ok(vec![json!("only")]));
+        assert_eq!(body(stream_report(one, ResponseFormat::Json, "Error").await).await, r#"["only"]"#);
+    }
+
+    #[actix_web::test]
+    async fn ndjson_is_one_object_per_line() {
+        let two = iter_stream(Ok(vec![json!({"id": 1}), json!({"id": 2, "name": "a\nb"})]));
+        let response = stream_report(two, ResponseFormat::Ndjson, "Error").await;
+        assert_eq!(response.headers().get("content-type").unwrap(), "application/x-ndjson");
+        let ndjson = body(response).await;
+        assert!(ndjson.ends_with('\n'));
+        let lines: Vec<Value> = ndjson.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+        assert_eq!(lines, vec![json!({"id": 1}), json!({"id": 2, "name": "a\nb"})]);
+    }
+
+    #[actix_web::test]
+    async fn empty_or_failing_reports_keep_their_status() {
+        for format in [ResponseFormat::Json, ResponseFormat::Ndjson] {
+            let response = stream_report(iter_stream(Ok(Vec::<Value>::new())), format, "Error").await;
+            assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+            let response = stream_report(iter_stream::<Value>(Err(Error::msg("bad SQL"))), format, "Error retrieving Orders data").await;
+            assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+            assert_eq!(body(response).await, "Error retrieving Orders data");
+        }
+    }
+
+    #[actix_web::test]
+    async fn an_error_mid_stream_aborts_the_body() {
+        let failing = || rows(vec![Ok(json!(1)), Ok(json!(2)), Err(Error::msg("connection reset")), Ok(json!(3))]);
+
+        // The 200 is already sent, so the client gets a truncated body rather than a valid one
+        let response = stream_report(failing(), ResponseFormat::Json, "Error").await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(chunks(response).await, vec![Ok("[1".to_string()), Ok(",2".to_string()), Err("connection reset".to_string())]);
+
+        let response = stream_report(failing(), ResponseFormat::Ndjson, "Error").await;
+        assert_eq!(chunks(response).await, vec![Ok("1\n".to_string()), Ok("2\n".to_string()), Err("connection reset".to_string())]);
+    }
+}
@@ -0,0 +1,84 @@
+// Country normalization shared by every report. The sample database stores most countries
+// by their English name but a few by abbreviation ('UK', 'USA'); reports show the display
+// name, the choropleth adds ISO 3166-1 codes, and filters accept any of the spellings.
+
+pub struct Country {
+    pub name: &'static str,
+    pub alpha2: &'static str,
+    pub alpha3: &'static str,
+    // Spelling used in Sales.Customers and Sales.Orders
+    pub stored: &'static str,
+    pub aliases: &'static [&'static str],
+}
+
+const fn country(name: &'static str, alpha2: &'static str, alpha3: &'static str) -> Country {
+    Country { name, alpha2, alpha3, stored: name, aliases: &[] }
+}
+
+pub const COUNTRIES: &[Country] = &[
+    country("Argentina", "AR", "ARG"),
+    country("Australia", "AU", "AUS"),
+    country("Austria", "AT", "AUT"),
+    country("Belgium", "BE", "BEL"),
+    country("Brazil", "BR", "BRA"),
+    country("Canada", "CA", "CAN"),
+    country("Chile", "CL", "CHL"),
+    country("China", "CN", "CHN"),
+    country("Colombia", "CO", "COL"),
+    Country { name: "Czechia", alpha2: "CZ", alpha3: "CZE", stored: "Czechia", aliases: &["Czech Republic"] },
+    country("Denmark", "DK", "DNK"),
+    country("Finland", "FI", "FIN"),
+    country("France", "FR", "FRA"),
+    country("Germany", "DE", "DEU"),
+    country("Greece", "GR", "GRC"),
+    country("Hungary", "HU", "HUN"),
+    country("Iceland", "IS", "ISL"),
+    country("India", "IN", "IND"),
+    country("Ireland", "IE", "IRL"),
+    country("Israel", "IL", "ISR"),
+    country("Italy", "IT", "ITA"),
+    country("Japan", "JP", "JPN"),
+    country("Luxembourg", "LU", "LUX"),
+    country("Mexico", "MX", "MEX"),
+    country("Netherlands", "NL", "NLD"),
+    country("New Zealand", "NZ", "NZL"),
+    country("Norway", "NO", "NOR"),
+    country("Peru", "PE", "PER"),
+    country("Poland", "PL", "POL"),
+    country("Portugal", "PT", "PRT"),
+    country("Singapore", "SG", "SGP"),
+    country("South Africa", "ZA", "ZAF"),
+    Country { name: "South Korea", alpha2: "KR", alpha3: "KOR", stored: "South Korea", aliases: &["Korea"] },
+    country("Spain", "ES", "ESP"),
+    country("Sweden", "SE", "SWE"),
+    country("Switzerland", "CH", "CHE"),
+    country("Turkey", "TR", "TUR"),
+    Country { name: "United Kingdom", alpha2: "GB", alpha3: "GBR", stored: "UK", aliases: &["Great Britain", "England"] },
+    Country { name: "United States", alpha2: "US", alpha3: "USA", stored: "USA", aliases: &["United States of America"] },
+    country("Venezuela", "VE", "VEN"),
+];
+
+// Finds a country by display name, stored spelling, alpha-2/alpha-3 code or alias, ignoring case.
+pub fn lookup(raw: &str) -> Option<&'static Country> {
+    let raw = raw.trim();
+    COUNTRIES.iter().find(|c| {
+        [c.name, c.stored, c.alpha2, c.alpha3].iter().chain(c.aliases.iter())
+            .any(|spelling| spelling.eq_ignore_ascii_case(raw))
+    })
+}
+
+// Display name for a country as stored in the database; unknown countries are passed through.
+pub fn display_name(raw: String) -> String {
+    match lookup(&raw) {
+        Some(country) => country.name.to_string(),
+        None => raw,
+    }
+}
+
+// Database spelling for a country given in any known form, used for filters.
+pub fn stored_name(raw: String) -> String {
+    match lookup(&raw) {
+        Some(country) => country.stored.to_string(),
+        None => raw,
+    }
+}
@@ -1,19 +1,38 @@
 use anyhow::{Error, Result};
+use async_trait::async_trait;
 use std::env;
-use std::sync::{Arc, Mutex};
-use tiberius::{Client, Config};
-
-use tokio::net::TcpStream;
-use tokio_util::compat::TokioAsyncWriteCompatExt;
-
+use tiberius::{Config, Row, ToSql};
+
+use crate::db::pool::{PoolConfig, PoolStats, TiberiusConnectionManager};
+use crate::db::repository::ReportRepository;
+use crate::db::retry::{with_retry, RetryPolicy};
+use crate::db::grid::{Dialect, GridQuery, SqlParam};
+use crate::db::row::{get_column, map_rows, FromRow};
+use crate::db::stream::{spawn_row_stream, RowStream};
+use crate::models::reportfilter::{ReportFilter, ReportPeriod, YearRange};
+
+use crate::models::gridrequest::Page;
+use crate::models::orderdetail::{OrderDetail, OrderHeader};
 use crate::models::ordersreport::OrdersReport;
-use crate::models::customerbyyear::CustomerByYear;
-use crate::models::topperformers::TopPerformers;
-use crate::models::saleschoropleth::SalesChoropleth;
+use crate::models::customerbyyear::{CustomerByYear, CustomerYearSales};
+use crate::models::customerprofile::{CustomerInfo, CustomerProfile, CustomerProfileQuery};
+use crate::models::employeeperformance::{EmployeePerformance, EmployeePerformanceQuery};
+use crate::models::topperformers::{CustomerEmployeeSales, TopPerformers, TopPerformersQuery};
+use crate::models::saleschoropleth::{ChoroplethQuery, CountryCustomerSales, SalesChoropleth};
+use crate::models::shipperperformance::{ShipmentTotals, ShipperPerformance, ShipperPerformanceQuery};
+use crate::models::productsales::{CategoryPeriodSales, CategorySales, ProductInfo, ProductPeriodSales, ProductSales, ProductSalesQuery};
+use crate::models::salestimeseries::{DailySales, TimeSeriesPoint, TimeSeriesQuery};
+use crate::models::rfmsegmentation::{CustomerOrderTotals, RfmQuery, RfmReport};
+use crate::models::cohortretention::{CohortQuery, CohortRetention, CustomerOrder};
+use crate::models::salesforecast::{ForecastGrouping, MonthlyRevenue, SalesForecast, SalesForecastQuery};
+use crate::models::abcanalysis::{AbcDimension, AbcQuery, AbcReport, ItemRevenue};
+use crate::models::marketbasket::{BoughtWithReport, MarketBasketQuery, OrderProduct, ProductPair};
 
 #[derive(Clone)]
 pub struct DatabaseMSSQL {
-    pub client: Arc<Mutex<Client<tokio_util::compat::Compat<TcpStream>>>>,
+    pub pool: bb8::Pool<TiberiusConnectionManager>,
+    pub pool_config: PoolConfig,
+    pub retry_policy: RetryPolicy,
 }
 
 impl DatabaseMSSQL {
@@ -28,32 +47,68 @@ impl DatabaseMSSQL {
 
         config.trust_cert();
 
-        // Tokio's TcpStream is used to create a connection
-        let tcp = TcpStream::connect(config.get_addr()).await?;
-        tcp.set_nodelay(true)?;
+        // Connections are opened lazily by the pool, up to POOL_MAX_SIZE, and checked with SELECT 1 before reuse
+        let pool_config = PoolConfig::from_env()?;
+        let pool = pool_config.build(config).await?;
 
-        // Use the `Client::connect` method to create a Tiberius client & Make the TcpStream compatible with Tiberius with compat
-        let client = Client::connect(config, tcp.compat_write()).await?;
-        let client = Arc::new(Mutex::new(client)); // Wrap the client in Arc<Mutex>
+        // Fail fast on bad credentials or an unreachable server instead of on the first request
+        drop(pool.get().await?);
 
-        Ok(DatabaseMSSQL{client})
+        let retry_policy = RetryPolicy::queries_from_env()?;
+
+        Ok(DatabaseMSSQL{pool, pool_config, retry_policy})
     }
 
+    // Every report is a read-only SELECT, so a query that fails on a dropped connection is
+    // simply run again on another one. Dead connections fail the SELECT 1 check and get replaced.
+    async fn query_rows(&self, report: &str, sql: &str, params: &[&dyn ToSql]) -> Result<Vec<Row>, Error> {
+        with_retry(&self.retry_policy, report, || async {
+            let mut conn = self.pool.get().await?;
+            Ok(conn.query_rows(sql, params).await?)
+        }).await
+    }
+}
 
-        pub async fn sales_orders_report(&self) -> Result<Vec<OrdersReport>, Error> {
+// Restricts the orders behind a report to the ReportFilter values, bound as @P1..@P6 in the
+// order filter_params lists them. Queries using it need the o (Orders) and c (Customers) aliases.
+pub(crate) const ORDER_FILTER: &str = "(@P1 IS NULL OR o.orderdate >= @P1)
+                        AND (@P2 IS NULL OR o.orderdate <= @P2)
+                        AND (@P3 IS NULL OR c.country = @P3)
+                        AND (@P4 IS NULL OR o.custid = @P4)
+                        AND (@P5 IS NULL OR o.empid = @P5)
+                        AND (@P6 IS NULL OR o.shipperid = @P6)";
+
+fn filter_params(filter: &ReportFilter) -> Vec<&dyn ToSql> {
+    vec![&filter.from_date, &filter.to_date, &filter.country, &filter.customer_id, &filter.employee_id, &filter.shipper_id]
+}
 
-            let mut client = self.client.lock().expect("Failed to lock client mutex");
+fn grid_params(params: &[SqlParam]) -> Vec<&dyn ToSql> {
+    params.iter()
+        .map(|param| match param {
+            SqlParam::Text(text) => text as &dyn ToSql,
+            SqlParam::Number(number) => number,
+            SqlParam::Int(int) => int,
+        })
+        .collect()
+}
 
-           
-            let mut orders_data = Vec::<OrdersReport>::new();
+// Period label of an order, "2023", "2023-Q2" or "2023-05" like ReportPeriod::label.
+fn period_sql(period: ReportPeriod) -> &'static str {
+    match period {
+        ReportPeriod::Year => "CAST(YEAR(o.orderdate) AS varchar(4))",
+        ReportPeriod::Quarter => "CAST(YEAR(o.orderdate) AS varchar(4)) + '-Q' + CAST(DATEPART(quarter, o.orderdate) AS varchar(1))",
+        ReportPeriod::Month => "CONVERT(varchar(7), o.orderdate, 126)",
+    }
+}
 
-            if let Some(rows) = client.query(" 
-                    SELECT c.companyname as cutomer_name
+// The orders report, one row per order filtered by ORDER_FILTER. Streamed as is, or wrapped
+// by the grid clauses for a single page.
+fn orders_report_sql() -> String {
+    format!("
+                    SELECT o.orderid as order_id
+                        , c.companyname as customer_name
                         , c.contactname as customer_contact
-                        , CASE WHEN c.country = 'UK' then 'United Kingdom'
-                                WHEN c.country = 'USA' then 'United States'
-                                ELSE c.country
-                            END as customer_country
+                        , c.country as customer_country
                         , e.lastname + ', ' + e.firstname as employee_name
                         , e.title as employee_title
                         , sh.companyname as shipper_name
@@ -69,7 +124,9 @@ impl DatabaseMSSQL {
                     JOIN [Sales].[Shippers] as sh on o.shipperid = sh.shipperid
                     JOIN [Sales].[OrderDetails] as od on o.orderid = od.orderid
                     JOIN [Production].[Products] as p on od.productid = p.productid
-                    GROUP BY c.companyname 
+                    WHERE {ORDER_FILTER}
+                    GROUP BY o.orderid
+                        , c.companyname
                         , c.contactname 
                         , c.country
                         , e.lastname + ', ' + e.firstname 
@@ -77,203 +134,553 @@ impl DatabaseMSSQL {
                         , sh.companyname 
                         , o.shipname
                         , o.orderdate
-                        , o.requireddate
-            ", &[]).await.ok() {
-
-                for row in rows.into_first_result().await? {
-
-                    let customer_name: &str= row.get("cutomer_name").expect("Failed to get cutomer_name");
-                    let customer_contact_name: &str = row.get("customer_contact").expect("Failed to get customer_contact");
-                    let customer_country: &str = row.get("customer_country").expect("Failed to get customer_country");
-                    let employee_name: &str = row.get("employee_name").expect("Failed to get employee_name");
-                    let employee_title: &str = row.get("employee_title").expect("Failed to get employee_title");
-                    let shipper_name: &str = row.get("shipper_name").expect("Failed to get shipper_name");
-                    let ship_name: &str = row.get("ship_name").expect("Failed to get ship_name");
-                    let order_date: &str = row.get("order_date").expect("Failed to get order_date");
-                    let delivery_date: &str = row.get("delivery_date").expect("Failed to get delivery_date");
-                    let freight_value: f64 = row.get("freight_value").expect("Failed to get freight_value");
-                    let order_value: f64 = row.get("order_value").expect("Failed to get order_value");
-                    let billable_value: f64 = row.get("billable_value").expect("Failed to get billable_value");
-
-                    let orders_report = OrdersReport {
-                        customer_name: customer_name.to_string(),
-                        customer_contact_name: customer_contact_name.to_string(),
-                        customer_country: customer_country.to_string(),
-                        employee_name: employee_name.to_string(),
-                        employee_title: employee_title.to_string(),
-                        shipper_name: shipper_name.to_string(),
-                        ship_name: ship_name.to_string(),
-                        order_date: order_date.to_string(),
-                        delivery_date: delivery_date.to_string(),
-                        freight_value,
-                        order_value,
-                        billable_value,
-                    };
-                    orders_data.push(orders_report);
-
-                    // println!("Orders Report: {}, {}, {}, {}, {}, {}, {}, {}, {}, {}, {}, {}", customer_name, customer_contact_name, customer_country, employee_name, employee_title, shipper_name, ship_name, order_date, delivery_date, freight_value, order_value, billable_value);
-                    }
-                } else {
-                    return Err(Error::msg("Failed to execute SQL query"));
-                }
-
-            Ok(orders_data)
-            
-        }
-
-        pub async fn get_customer_sales_by_year(&self) -> Result<Vec<CustomerByYear>, Error> {
-            let mut client = self.client.lock().expect("Failed to lock client mutex");
-
-            let mut customer_data = Vec::<CustomerByYear>::new();
-
-            if let Some(rows) = client.query(
-                "SELECT c.companyname as customer_name
-                            , CAST(SUM(CASE WHEN YEAR(o.orderdate) = 2021
-                                    THEN od.unitprice * od.qty * (1-od.discount) ELSE 0 END) as FLOAT) as [sales_2021]
-                            ,CAST(SUM(CASE WHEN YEAR(o.orderdate) = 2022
-                                    THEN od.unitprice * od.qty * (1-od.discount) ELSE 0 END) as FLOAT) as [sales_2022]
-                            ,CAST(SUM(CASE WHEN YEAR(o.orderdate) = 2023
-                                    THEN od.unitprice * od.qty * (1-od.discount) ELSE 0 END) as FLOAT) as [sales_2023]
+                        , o.requireddate")
+}
+
+// One row per product of each order that passes ORDER_FILTER, the baskets behind the
+// market basket reports.
+fn order_products_sql() -> String {
+    format!("
+                SELECT DISTINCT od.orderid AS order_id
+                    , od.productid AS product_id
+                    , p.productname AS product_name
+                FROM Sales.Orders AS o
+                JOIN Sales.OrderDetails AS od ON o.orderid = od.orderid
+                JOIN Production.Products AS p ON od.productid = p.productid
+                JOIN Sales.Customers AS c ON o.custid = c.custid
+                WHERE {ORDER_FILTER}")
+}
+
+#[async_trait]
+impl ReportRepository for DatabaseMSSQL {
+
+    fn stream_orders_report(&self, filter: &ReportFilter) -> RowStream<OrdersReport> {
+        let pool = self.pool.clone();
+        let retry_policy = self.retry_policy.clone();
+        let filter = filter.clone();
+        spawn_row_stream(move |tx| async move {
+            let sql = orders_report_sql();
+
+            // Only the checkout is retried, once rows have been sent the report cannot start over
+            let mut conn = with_retry(&retry_policy, "Orders report", || async { Ok(pool.get_owned().await?) }).await?;
+            conn.send_rows(&sql, &filter_params(&filter), &tx).await
+        })
+    }
+
+    async fn get_orders_report_page(&self, filter: &ReportFilter, query: &GridQuery) -> Result<Page<OrdersReport>, Error> {
+        // The grid's parameters follow the six filter parameters
+        let grid = query.to_sql(Dialect::MsSql, 7);
+        let report_sql = orders_report_sql();
+
+        let mut params = filter_params(filter);
+        params.extend(grid_params(&grid.params));
+        let count = self.query_rows("Orders report count", &grid.count(&report_sql), &params).await?;
+        params.extend(grid_params(&grid.page_params));
+        let rows = self.query_rows("Orders report page", &grid.select(&report_sql), &params).await?;
+
+        let total = match count.first() {
+            Some(row) => get_column(row, "total")?,
+            None => 0,
+        };
+        Ok(Page { rows: map_rows(&rows)?, total })
+    }
+
+    async fn get_order_detail(&self, order_id: i32) -> Result<Option<OrderDetail>, Error> {
+        let params: [&dyn ToSql; 1] = [&order_id];
+
+        let header = self.query_rows("Order detail", "
+            SELECT o.orderid AS order_id
+                , c.custid AS customer_id
+                , c.companyname AS customer_name
+                , c.contactname AS customer_contact
+                , c.country AS customer_country
+                , e.empid AS employee_id
+                , e.lastname + ', ' + e.firstname AS employee_name
+                , e.title AS employee_title
+                , sh.shipperid AS shipper_id
+                , sh.companyname AS shipper_name
+                , o.shipname AS ship_name
+                , o.shipcity AS ship_city
+                , o.shipcountry AS ship_country
+                , CAST(o.orderdate AS varchar(10)) AS order_date
+                , CAST(o.requireddate AS varchar(10)) AS required_date
+                , CAST(o.shippeddate AS varchar(10)) AS shipped_date
+                , CAST(o.freight AS FLOAT) AS freight
+            FROM Sales.Orders AS o
+            JOIN Sales.Customers AS c ON o.custid = c.custid
+            JOIN HR.Employees AS e ON o.empid = e.empid
+            JOIN Sales.Shippers AS sh ON o.shipperid = sh.shipperid
+            WHERE o.orderid = @P1", &params).await?;
+        let Some(header) = header.first() else {
+            return Ok(None);
+        };
+        let header = OrderHeader::from_row(header)?;
+
+        let lines = self.query_rows("Order lines", "
+            SELECT p.productid AS product_id
+                , p.productname AS product_name
+                , CAST(od.unitprice AS FLOAT) AS unit_price
+                , od.qty AS quantity
+                , CAST(od.discount AS FLOAT) AS discount
+                , CAST(od.unitprice * od.qty * (1 - od.discount) AS FLOAT) AS line_total
+            FROM Sales.OrderDetails AS od
+            JOIN Production.Products AS p ON od.productid = p.productid
+            WHERE od.orderid = @P1
+            ORDER BY p.productid", &params).await?;
+
+        Ok(Some(OrderDetail::new(header, map_rows(&lines)?)))
+    }
+
+    async fn get_customer_sales_by_year(&self, filter: &ReportFilter, years: &YearRange) -> Result<Vec<CustomerByYear>, Error> {
+        // Grouped by year here and pivoted in Rust, so the year columns never end up in the SQL text
+        let mut params = filter_params(filter);
+        params.extend([&years.from_year as &dyn ToSql, &years.to_year]);
+
+        let rows = self.query_rows("Customer sales by year", &format!(
+            "SELECT c.companyname as customer_name
+                            , YEAR(o.orderdate) as order_year
+                            , CAST(SUM(od.unitprice * od.qty * (1-od.discount)) as FLOAT) as sales
                         FROM Sales.Orders as o
                         JOIN
                             Sales.OrderDetails as od on o.orderid = od.orderid
                         JOIN Sales.Customers as c on o.custid = c.custid
-                        GROUP BY c.companyname
-                        ORDER BY 4 DESC", &[]).await.ok() {
-
-                for row in rows.into_first_result().await? {
-                    let customer_name: &str= row.get("customer_name").expect("Failed to get customer_name");
-                    let sales_2021: f64 = row.get("sales_2021").expect("Failed to get sales_2021");
-                    let sales_2022: f64 = row.get("sales_2022").expect("Failed to get sales_2022");
-                    let sales_2023: f64 = row.get("sales_2023").expect("Failed to get sales_2023");
-                    let customer_by_year = CustomerByYear {
-                        customer_name: customer_name.to_string(),
-                        sales_2021,
-                        sales_2022,
-                        sales_2023,
-                    };
-                    customer_data.push(customer_by_year);
-                }
-            } else {
-                return Err(Error::msg("Failed to execute SQL query"));
-            }
-            Ok(customer_data)
-        }
-
-        pub async fn get_top_performers(&self) -> Result<Vec<TopPerformers>, Error> {
-            let mut client = self.client.lock().expect("Failed to lock client mutex");
-            let mut top_performers = Vec::<TopPerformers>::new();
-
-            if let Some(rows) = client.query(
-                "SELECT 
-                            MAX(CASE WHEN companyname = 'Customer THHDP' THEN top_performers ELSE '' END) AS [customer_thhdp],
-                            MAX(CASE WHEN companyname = 'Customer CYZTN' THEN top_performers ELSE '' END) AS [customer_cyztn],
-                            MAX(CASE WHEN companyname = 'Customer IBVRG' THEN top_performers ELSE '' END) AS [customer_ibvrg],
-                            MAX(CASE WHEN companyname = 'Customer FRXZL' THEN top_performers ELSE '' END) AS [customer_frxzl],
-                            MAX(CASE WHEN companyname = 'Customer GLLAG' THEN top_performers ELSE '' END) AS [customer_gllag],
-                            MAX(CASE WHEN companyname = 'Customer IRRVL' THEN top_performers ELSE '' END) AS [customer_irrvl],
-                            MAX(CASE WHEN companyname = 'Customer NYUHS' THEN top_performers ELSE '' END) AS [customer_nyuhs],
-                            MAX(CASE WHEN companyname = 'Customer LCOUJ' THEN top_performers ELSE '' END) AS [customer_lcouj],
-                            MAX(CASE WHEN companyname = 'Customer SFOGW' THEN top_performers ELSE '' END) AS [customer_sfogw],
-                            MAX(CASE WHEN companyname = 'Customer YBQTI' THEN top_performers ELSE '' END) AS [customer_ybqti]
-                        FROM (
-                            SELECT 
-                                c.companyname, 
-                                top_performers,
-                                ROW_NUMBER() OVER (PARTITION BY c.companyname ORDER BY top_performers) AS RowNum
-                            FROM 
-                                Sales.Customers AS c 
-                            JOIN (
-                                SELECT DISTINCT
-                                    e.lastname + ', ' +  e.firstname AS top_performers, 
-                                    o.custid  
-                                FROM 
-                                    HR.Employees AS e
-                                JOIN
-                                    Sales.Orders AS o ON e.empid = o.empid
-                                JOIN (
-                                    SELECT TOP 10 WITH TIES 
-                                        o.custid,
-                                        c.companyname AS customer_name,
-                                        SUM(CASE WHEN YEAR(o.orderdate) = 2023 
-                                            THEN od.unitprice * od.qty * (1 - od.discount) ELSE 0 END) AS [sales_2023]
-                                    FROM 
-                                        Sales.Orders AS o
-                                    JOIN 
-                                        Sales.OrderDetails AS od ON o.orderid = od.orderid
-                                    JOIN
-                                        Sales.Customers AS c ON o.custid = c.custid
-                                    GROUP BY 
-                                        o.custid, c.companyname
-                                    ORDER BY 
-                                        sales_2023 DESC
-                                ) AS top_10_customers_2023 ON top_10_customers_2023.custid = o.custid
-                            ) AS performers_custid ON performers_custid.custid = c.custid
-                        ) AS top_company_employee
-                        GROUP BY RowNum;", &[]).await.ok() {
-
-                for row in rows.into_first_result().await? {
-                    let customer_thhdp: &str = row.get("customer_thhdp").expect("Failed to get customer_thhdp");
-                    let customer_cyztn: &str = row.get("customer_cyztn").expect("Failed to get customer_cyztn");
-                    let customer_ibvrg: &str = row.get("customer_ibvrg").expect("Failed to get customer_ibvrg");
-                    let customer_frxzl: &str = row.get("customer_frxzl").expect("Failed to get customer_frxzl");
-                    let customer_gllag: &str = row.get("customer_gllag").expect("Failed to get customer_gllag");
-                    let customer_irrvl: &str = row.get("customer_irrvl").expect("Failed to get customer_irrvl");
-                    let customer_nyuhs: &str = row.get("customer_nyuhs").expect("Failed to get customer_nyuhs");
-                    let customer_lcouj: &str = row.get("customer_lcouj").expect("Failed to get customer_lcouj");
-                    let customer_sfogw: &str = row.get("customer_sfogw").expect("Failed to get customer_sfogw");
-                    let customer_ybqti: &str = row.get("customer_ybqti").expect("Failed to get customer_ybqti");
-
-                    let top_performer = TopPerformers {
-                        customer_thhdp: customer_thhdp.to_string(),
-                        customer_cyztn: customer_cyztn.to_string(),
-                        customer_ibvrg: customer_ibvrg.to_string(),
-                        customer_frxzl: customer_frxzl.to_string(),
-                        customer_gllag: customer_gllag.to_string(),
-                        customer_irrvl: customer_irrvl.to_string(),
-                        customer_nyuhs: customer_nyuhs.to_string(),
-                        customer_lcouj: customer_lcouj.to_string(),
-                        customer_sfogw: customer_sfogw.to_string(),
-                        customer_ybqti: customer_ybqti.to_string(),
-                    };
-                    top_performers.push(top_performer);
-                }
-            }
-            Ok(top_performers)    
-    }  
+                        WHERE {ORDER_FILTER}
+                            AND (@P7 IS NULL OR YEAR(o.orderdate) >= @P7)
+                            AND (@P8 IS NULL OR YEAR(o.orderdate) <= @P8)
+                        GROUP BY c.companyname, YEAR(o.orderdate)"), &params).await?;
 
-    pub async fn get_sales_choropleth(&self) -> Result<Vec<SalesChoropleth>, Error> {
-        let mut client = self.client.lock().expect("Failed to lock client mutex");
+        let rows: Vec<CustomerYearSales> = map_rows(&rows)?;
+        Ok(CustomerByYear::pivot(rows, years))
+    }
 
-        let mut sales_choropleth_data = Vec::<SalesChoropleth>::new();
+    async fn get_customer_profile(&self, customer_id: i32, filter: &ReportFilter, query: &CustomerProfileQuery) -> Result<Option<CustomerProfile>, Error> {
+        let customer = self.query_rows("Customer profile", "
+            SELECT custid AS customer_id
+                , companyname AS customer_name
+                , contactname AS contact_name
+                , contacttitle AS contact_title
+                , city
+                , country
+                , phone
+            FROM Sales.Customers
+            WHERE custid = @P1", &[&customer_id as &dyn ToSql]).await?;
+        let Some(customer) = customer.first() else {
+            return Ok(None);
+        };
+        let customer = CustomerInfo::from_row(customer)?;
+
+        // Totals, trend and employees come from one row per order, products from one row per product
+        let mut params = filter_params(filter);
+        params.push(&customer_id);
+
+        let orders = self.query_rows("Customer profile orders", &format!(
+            "SELECT CAST(o.orderdate AS varchar(10)) AS order_date
+                    , e.empid AS employee_id
+                    , e.lastname + ', ' + e.firstname AS employee_name
+                    , CAST(SUM(od.unitprice * od.qty * (1 - od.discount)) AS FLOAT) AS order_value
+                FROM Sales.Orders AS o
+                JOIN Sales.OrderDetails AS od ON o.orderid = od.orderid
+                JOIN Sales.Customers AS c ON o.custid = c.custid
+                JOIN HR.Employees AS e ON o.empid = e.empid
+                WHERE {ORDER_FILTER}
+                    AND o.custid = @P7
+                GROUP BY o.orderid, o.orderdate, e.empid, e.lastname, e.firstname"), &params).await?;
+
+        let products = self.query_rows("Customer profile products", &format!(
+            "SELECT p.productid AS product_id
+                    , p.productname AS product_name
+                    , COUNT(DISTINCT o.orderid) AS order_count
+                    , SUM(od.qty) AS quantity
+                    , CAST(SUM(od.unitprice * od.qty * (1 - od.discount)) AS FLOAT) AS revenue
+                FROM Sales.Orders AS o
+                JOIN Sales.OrderDetails AS od ON o.orderid = od.orderid
+                JOIN Production.Products AS p ON od.productid = p.productid
+                JOIN Sales.Customers AS c ON o.custid = c.custid
+                WHERE {ORDER_FILTER}
+                    AND o.custid = @P7
+                GROUP BY p.productid, p.productname"), &params).await?;
+
+        Ok(Some(CustomerProfile::build(customer, map_rows(&orders)?, map_rows(&products)?, query)))
+    }
 
-        if let Some(rows) = client.query(
+    async fn get_top_performers(&self, filter: &ReportFilter, query: &TopPerformersQuery) -> Result<Vec<TopPerformers>, Error> {
+        // Ranking and the employee lists are built in Rust from the per-employee totals
+        let mut params = filter_params(filter);
+        params.push(&query.year);
+
+        let rows = self.query_rows("Top performers", &format!(
+            "SELECT c.custid AS customer_id
+                            , c.companyname AS customer_name
+                            , e.lastname + ', ' + e.firstname AS employee_name
+                            , YEAR(o.orderdate) AS order_year
+                            , COUNT(DISTINCT o.orderid) AS order_count
+                            , SUM(od.qty) AS quantity
+                            , CAST(SUM(od.unitprice * od.qty * (1 - od.discount)) AS FLOAT) AS sales
+                        FROM Sales.Orders AS o
+                        JOIN Sales.OrderDetails AS od ON o.orderid = od.orderid
+                        JOIN Sales.Customers AS c ON o.custid = c.custid
+                        JOIN HR.Employees AS e ON o.empid = e.empid
+                        WHERE {ORDER_FILTER}
+                            AND (@P7 IS NULL OR YEAR(o.orderdate) = @P7)
+                        GROUP BY c.custid, c.companyname, e.empid, e.lastname, e.firstname, YEAR(o.orderdate)"), &params).await?;
+
+        let rows: Vec<CustomerEmployeeSales> = map_rows(&rows)?;
+        Ok(TopPerformers::rank(rows, query))
+    }
+
+    async fn get_employee_performance(&self, filter: &ReportFilter, query: &EmployeePerformanceQuery) -> Result<Vec<EmployeePerformance>, Error> {
+        let period = period_sql(query.period);
+        let params = filter_params(filter);
+
+        let employees = self.query_rows("Employee performance employees", "
+            SELECT empid AS employee_id
+                , lastname + ', ' + firstname AS employee_name
+                , title
+                , mgrid AS manager_id
+            FROM HR.Employees", &[]).await?;
+
+        let own = self.query_rows("Employee performance", &format!(
+            "SELECT o.empid AS employee_id
+                    , {period} AS period
+                    , CAST(SUM(od.unitprice * od.qty * (1 - od.discount)) AS FLOAT) AS revenue
+                    , COUNT(DISTINCT o.orderid) AS order_count
+                    , COUNT(DISTINCT o.custid) AS customers_served
+                    , CAST(AVG(od.discount) AS FLOAT) AS average_discount
+                FROM Sales.Orders AS o
+                JOIN Sales.OrderDetails AS od ON o.orderid = od.orderid
+                JOIN Sales.Customers AS c ON o.custid = c.custid
+                WHERE {ORDER_FILTER}
+                GROUP BY o.empid, {period}"), &params).await?;
+
+        // Every employee is paired with everyone below them, themselves included, and the
+        // team's orders are summed per manager
+        let team = self.query_rows("Employee performance teams", &format!(
+            "WITH team AS (
+                    SELECT empid AS manager_id, empid AS member_id
+                    FROM HR.Employees
+                    UNION ALL
+                    SELECT t.manager_id, e.empid
+                    FROM team AS t
+                    JOIN HR.Employees AS e ON e.mgrid = t.member_id
+                )
+                SELECT t.manager_id AS employee_id
+                    , {period} AS period
+                    , CAST(SUM(od.unitprice * od.qty * (1 - od.discount)) AS FLOAT) AS revenue
+                    , COUNT(DISTINCT o.orderid) AS order_count
+                    , COUNT(DISTINCT o.custid) AS customers_served
+                    , CAST(AVG(od.discount) AS FLOAT) AS average_discount
+                FROM team AS t
+                JOIN Sales.Orders AS o ON o.empid = t.member_id
+                JOIN Sales.OrderDetails AS od ON o.orderid = od.orderid
+                JOIN Sales.Customers AS c ON o.custid = c.custid
+                WHERE {ORDER_FILTER}
+                GROUP BY t.manager_id, {period}"), &params).await?;
+
+        Ok(EmployeePerformance::build(map_rows(&employees)?, map_rows(&own)?, map_rows(&team)?))
+    }
+
+    async fn get_sales_choropleth(&self, filter: &ReportFilter, query: &ChoroplethQuery) -> Result<Vec<SalesChoropleth>, Error> {
+        // Order values are summed per order first so freight is not counted once per order line
+        let mut params = filter_params(filter);
+        params.push(&query.year);
+
+        let rows = self.query_rows("Sales choropleth", &format!(
             "SELECT c.country AS country
-                    , CAST(SUM(CASE WHEN YEAR(o.orderdate) = 2023
-                            THEN od.unitprice * od.qty * (1-od.discount) ELSE 0 END) as FLOAT) as [sales_2023]
-                    FROM Sales.Orders as o
-                    JOIN
-                        Sales.OrderDetails as od on o.orderid = od.orderid
-                    JOIN Sales.Customers as c on o.custid = c.custid
-                    GROUP BY c.country
-                    ORDER BY 2 desc;", &[]).await.ok() {
-            for row in rows.into_first_result().await? {
-
-                let country: &str = row.get("country").expect("Failed to get country");
-                let sales_2023: f64 = row.get("sales_2023").expect("Failed to get sales_2023");
-                
-                let sales_choropleth = SalesChoropleth {
-                    country: country.to_string(),
-                    sales_2023
-                };
-                sales_choropleth_data.push(sales_choropleth);
+                    , c.custid AS customer_id
+                    , YEAR(o.orderdate) AS order_year
+                    , COUNT(*) AS order_count
+                    , CAST(SUM(o.freight) AS FLOAT) AS freight
+                    , CAST(SUM(ov.order_value) AS FLOAT) AS revenue
+                    FROM Sales.Orders AS o
+                    JOIN (
+                        SELECT orderid, SUM(unitprice * qty * (1 - discount)) AS order_value
+                        FROM Sales.OrderDetails
+                        GROUP BY orderid
+                    ) AS ov ON ov.orderid = o.orderid
+                    JOIN Sales.Customers AS c ON o.custid = c.custid
+                    WHERE {ORDER_FILTER}
+                        AND (@P7 IS NULL OR YEAR(o.orderdate) = @P7)
+                    GROUP BY c.country, c.custid, YEAR(o.orderdate)"), &params).await?;
+
+        let rows: Vec<CountryCustomerSales> = map_rows(&rows)?;
+        let date_range = filter.from_date.is_some() || filter.to_date.is_some();
+        Ok(SalesChoropleth::aggregate(rows, query, date_range))
+    }
+
+    async fn get_shipper_performance(&self, filter: &ReportFilter, query: &ShipperPerformanceQuery) -> Result<Vec<ShipperPerformance>, Error> {
+        // Grouped by shipper, ship country and period here, rolled up to the requested grouping in Rust
+        let period = period_sql(query.period.unwrap_or_default());
+
+        let rows = self.query_rows("Shipper performance", &format!(
+            "SELECT sh.shipperid AS shipper_id
+                    , sh.companyname AS shipper_name
+                    , o.shipcountry AS ship_country
+                    , {period} AS period
+                    , COUNT(*) AS order_count
+                    , COUNT(o.shippeddate) AS shipped_count
+                    , SUM(CASE WHEN o.shippeddate <= o.requireddate THEN 1 ELSE 0 END) AS on_time_count
+                    , SUM(DATEDIFF(day, o.requireddate, o.shippeddate)) AS days_late
+                    , CAST(SUM(o.freight) AS FLOAT) AS freight
+                    , CAST(SUM(ov.order_value) AS FLOAT) AS order_value
+                FROM Sales.Orders AS o
+                JOIN (
+                    SELECT orderid, SUM(unitprice * qty * (1 - discount)) AS order_value
+                    FROM Sales.OrderDetails
+                    GROUP BY orderid
+                ) AS ov ON ov.orderid = o.orderid
+                JOIN Sales.Shippers AS sh ON o.shipperid = sh.shipperid
+                JOIN Sales.Customers AS c ON o.custid = c.custid
+                WHERE {ORDER_FILTER}
+                GROUP BY sh.shipperid, sh.companyname, o.shipcountry, {period}"), &filter_params(filter)).await?;
+
+        let rows: Vec<ShipmentTotals> = map_rows(&rows)?;
+        Ok(ShipperPerformance::aggregate(rows, query))
+    }
+
+    async fn get_product_sales(&self, filter: &ReportFilter, query: &ProductSalesQuery) -> Result<Vec<ProductSales>, Error> {
+        // Without a period the rows are not grouped by one, so customers and orders are counted once
+        let (period, group_period) = match query.period {
+            Some(period) => (period_sql(period), format!(", {}", period_sql(period))),
+            None => ("''", String::new()),
+        };
+        let mut params = filter_params(filter);
+        params.push(&query.category_id);
+
+        let rows = self.query_rows("Product sales", &format!(
+            "SELECT p.productid AS product_id
+                    , p.productname AS product_name
+                    , p.categoryid AS category_id
+                    , cat.categoryname AS category_name
+                    , CAST(p.unitprice AS FLOAT) AS list_price
+                    , p.discontinued AS discontinued
+                    , {period} AS period
+                    , CAST(SUM(od.unitprice * od.qty * (1 - od.discount)) AS FLOAT) AS revenue
+                    , CAST(SUM(od.unitprice * od.qty) AS FLOAT) AS gross_revenue
+                    , CAST(SUM(p.unitprice * od.qty) AS FLOAT) AS list_revenue
+                    , SUM(od.qty) AS units
+                    , COUNT(DISTINCT o.orderid) AS order_count
+                    , COUNT(DISTINCT o.custid) AS customer_count
+                FROM Sales.Orders AS o
+                JOIN Sales.OrderDetails AS od ON o.orderid = od.orderid
+                JOIN Production.Products AS p ON od.productid = p.productid
+                JOIN Production.Categories AS cat ON p.categoryid = cat.categoryid
+                JOIN Sales.Customers AS c ON o.custid = c.custid
+                WHERE {ORDER_FILTER}
+                    AND (@P7 IS NULL OR p.categoryid = @P7)
+                GROUP BY p.productid, p.productname, p.categoryid, cat.categoryname, p.unitprice, p.discontinued{group_period}"), &params).await?;
+
+        let rows: Vec<ProductPeriodSales> = map_rows(&rows)?;
+
+        // Products without sales only matter for the bottom of the ranking
+        let catalogue: Vec<ProductInfo> = match query.bottom {
+            Some(_) => {
+                let products = self.query_rows("Product catalogue", "
+                    SELECT p.productid AS product_id
+                        , p.productname AS product_name
+                        , p.categoryid AS category_id
+                        , cat.categoryname AS category_name
+                        , CAST(p.unitprice AS FLOAT) AS list_price
+                        , p.discontinued AS discontinued
+                    FROM Production.Products AS p
+                    JOIN Production.Categories AS cat ON p.categoryid = cat.categoryid
+                    WHERE @P1 IS NULL OR p.categoryid = @P1", &[&query.category_id]).await?;
+                map_rows(&products)?
             }
-        } else {
-            return Err(Error::msg("Failed to execute SQL query"));
-        }
-        Ok(sales_choropleth_data)    
+            None => Vec::new(),
+        };
+        Ok(ProductSales::rank(rows, catalogue, query))
+    }
+
+    async fn get_category_sales(&self, filter: &ReportFilter, query: &ProductSalesQuery) -> Result<Vec<CategorySales>, Error> {
+        let (period, group_period) = match query.period {
+            Some(period) => (period_sql(period), format!(", {}", period_sql(period))),
+            None => ("''", String::new()),
+        };
+        let mut params = filter_params(filter);
+        params.push(&query.category_id);
+
+        let rows = self.query_rows("Category sales", &format!(
+            "SELECT cat.categoryid AS category_id
+                    , cat.categoryname AS category_name
+                    , {period} AS period
+                    , COUNT(DISTINCT p.productid) AS product_count
+                    , CAST(SUM(od.unitprice * od.qty * (1 - od.discount)) AS FLOAT) AS revenue
+                    , CAST(SUM(od.unitprice * od.qty) AS FLOAT) AS gross_revenue
+                    , CAST(SUM(p.unitprice * od.qty) AS FLOAT) AS list_revenue
+                    , SUM(od.qty) AS units
+                    , COUNT(DISTINCT o.orderid) AS order_count
+                    , COUNT(DISTINCT o.custid) AS customer_count
+                FROM Sales.Orders AS o
+                JOIN Sales.OrderDetails AS od ON o.orderid = od.orderid
+                JOIN Production.Products AS p ON od.productid = p.productid
+                JOIN Production.Categories AS cat ON p.categoryid = cat.categoryid
+                JOIN Sales.Customers AS c ON o.custid = c.custid
+                WHERE {ORDER_FILTER}
+                    AND (@P7 IS NULL OR p.categoryid = @P7)
+                GROUP BY cat.categoryid, cat.categoryname{group_period}"), &params).await?;
+
+        let rows: Vec<CategoryPeriodSales> = map_rows(&rows)?;
+        Ok(CategorySales::rank(rows, query))
+    }
+
+    async fn get_sales_timeseries(&self, filter: &ReportFilter, query: &TimeSeriesQuery) -> Result<Vec<TimeSeriesPoint>, Error> {
+        // Daily totals, bucketed and zero-filled in Rust
+        let history = TimeSeriesPoint::history_filter(filter, query);
+
+        let rows = self.query_rows("Sales time series", &format!(
+            "SELECT o.orderdate AS order_date
+                    , COUNT(*) AS order_count
+                    , CAST(SUM(o.freight) AS FLOAT) AS freight
+                    , CAST(SUM(ov.order_value) AS FLOAT) AS revenue
+                FROM Sales.Orders AS o
+                JOIN (
+                    SELECT orderid, SUM(unitprice * qty * (1 - discount)) AS order_value
+                    FROM Sales.OrderDetails
+                    GROUP BY orderid
+                ) AS ov ON ov.orderid = o.orderid
+                JOIN Sales.Customers AS c ON o.custid = c.custid
+                WHERE {ORDER_FILTER}
+                GROUP BY o.orderdate"), &filter_params(&history)).await?;
+
+        let rows: Vec<DailySales> = map_rows(&rows)?;
+        Ok(TimeSeriesPoint::build(rows, filter, query))
+    }
+
+    async fn get_rfm_segments(&self, filter: &ReportFilter, query: &RfmQuery) -> Result<Option<RfmReport>, Error> {
+        // Scores and segments are assigned in Rust from the per-customer totals
+        let orders = query.order_filter(filter);
+
+        let rows = self.query_rows("RFM segments", &format!(
+            "SELECT c.custid AS customer_id
+                    , c.companyname AS customer_name
+                    , c.country AS country
+                    , MAX(o.orderdate) AS last_order_date
+                    , COUNT(*) AS order_count
+                    , CAST(SUM(ov.order_value) AS FLOAT) AS monetary
+                FROM Sales.Orders AS o
+                JOIN (
+                    SELECT orderid, SUM(unitprice * qty * (1 - discount)) AS order_value
+                    FROM Sales.OrderDetails
+                    GROUP BY orderid
+                ) AS ov ON ov.orderid = o.orderid
+                JOIN Sales.Customers AS c ON o.custid = c.custid
+                WHERE {ORDER_FILTER}
+                GROUP BY c.custid, c.companyname, c.country"), &filter_params(&orders)).await?;
+
+        let rows: Vec<CustomerOrderTotals> = map_rows(&rows)?;
+        Ok(RfmReport::build(rows, filter, query))
+    }
+
+    async fn get_cohort_retention(&self, filter: &ReportFilter, query: &CohortQuery) -> Result<Vec<CohortRetention>, Error> {
+        // One row per order, the cohorts are built in Rust
+        let orders = query.order_filter(filter);
+
+        let rows = self.query_rows("Cohort retention", &format!(
+            "SELECT o.custid AS customer_id
+                    , o.orderdate AS order_date
+                    , CAST(ov.order_value AS FLOAT) AS order_value
+                FROM Sales.Orders AS o
+                JOIN (
+                    SELECT orderid, SUM(unitprice * qty * (1 - discount)) AS order_value
+                    FROM Sales.OrderDetails
+                    GROUP BY orderid
+                ) AS ov ON ov.orderid = o.orderid
+                JOIN Sales.Customers AS c ON o.custid = c.custid
+                WHERE {ORDER_FILTER}"), &filter_params(&orders)).await?;
+
+        let rows: Vec<CustomerOrder> = map_rows(&rows)?;
+        Ok(CohortRetention::build(rows, filter, query))
     }
 
+    async fn get_sales_forecast(&self, filter: &ReportFilter, query: &SalesForecastQuery) -> Result<Vec<SalesForecast>, Error> {
+        // Monthly revenue per series, the models are fitted in Rust
+        let series = match query.by {
+            ForecastGrouping::Total => "'Total'",
+            ForecastGrouping::Country => "c.country",
+            ForecastGrouping::Category => "cat.categoryname",
+        };
+        // A constant series name is not grouped by
+        let group_series = match query.by {
+            ForecastGrouping::Total => String::new(),
+            _ => format!("{}, ", series),
+        };
+        let period = period_sql(ReportPeriod::Month);
+
+        let rows = self.query_rows("Sales forecast", &format!(
+            "SELECT {series} AS series
+                    , {period} AS period
+                    , CAST(SUM(od.unitprice * od.qty * (1 - od.discount)) AS FLOAT) AS revenue
+                FROM Sales.Orders AS o
+                JOIN Sales.OrderDetails AS od ON o.orderid = od.orderid
+                JOIN Production.Products AS p ON od.productid = p.productid
+                JOIN Production.Categories AS cat ON p.categoryid = cat.categoryid
+                JOIN Sales.Customers AS c ON o.custid = c.custid
+                WHERE {ORDER_FILTER}
+                GROUP BY {group_series}{period}"), &filter_params(filter)).await?;
+
+        let rows: Vec<MonthlyRevenue> = map_rows(&rows)?;
+        Ok(SalesForecast::build(rows, filter, query))
+    }
 
+    async fn get_abc_analysis(&self, filter: &ReportFilter, query: &AbcQuery) -> Result<Option<AbcReport>, Error> {
+        // Revenue per customer or product, ranked and classified in Rust
+        let (id, name) = match query.dimension {
+            AbcDimension::Customer => ("c.custid", "c.companyname"),
+            AbcDimension::Product => ("p.productid", "p.productname"),
+        };
+
+        let rows = self.query_rows("ABC analysis", &format!(
+            "SELECT {id} AS id
+                    , {name} AS name
+                    , CAST(SUM(od.unitprice * od.qty * (1 - od.discount)) AS FLOAT) AS revenue
+                FROM Sales.Orders AS o
+                JOIN Sales.OrderDetails AS od ON o.orderid = od.orderid
+                JOIN Production.Products AS p ON od.productid = p.productid
+                JOIN Sales.Customers AS c ON o.custid = c.custid
+                WHERE {ORDER_FILTER}
+                GROUP BY {id}, {name}"), &filter_params(filter)).await?;
+
+        let rows: Vec<ItemRevenue> = map_rows(&rows)?;
+        Ok(AbcReport::build(rows, query))
+    }
+
+    async fn get_market_basket(&self, filter: &ReportFilter, query: &MarketBasketQuery) -> Result<Vec<ProductPair>, Error> {
+        // The products of every order, paired and counted in Rust
+        let rows = self.query_rows("Market basket", &order_products_sql(), &filter_params(filter)).await?;
+        let rows: Vec<OrderProduct> = map_rows(&rows)?;
+        Ok(ProductPair::build(rows, query))
+    }
 
+    async fn get_bought_with(&self, product_id: i32, filter: &ReportFilter, query: &MarketBasketQuery) -> Result<Option<BoughtWithReport>, Error> {
+        let rows = self.query_rows("Bought with", &order_products_sql(), &filter_params(filter)).await?;
+        let rows: Vec<OrderProduct> = map_rows(&rows)?;
+        Ok(BoughtWithReport::build(rows, product_id, query))
+    }
+
+    fn pool_stats(&self) -> Option<PoolStats> {
+        Some(PoolStats::from_pool(&self.pool, self.pool_config.max_size))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tiberius::ColumnData;
+
+    use crate::db::tests::every_filter;
+
+    #[test]
+    fn filter_params_follow_the_placeholders() {
+        let filter = every_filter();
+        let params: Vec<ColumnData> = filter_params(&filter).iter().map(|param| param.to_sql()).collect();
+        assert_eq!(params, vec![
+            filter.from_date.to_sql(),
+            filter.to_date.to_sql(),
+            ColumnData::String(Some("Germany".into())),
+            ColumnData::I32(Some(5)),
+            ColumnData::I32(Some(7)),
+            ColumnData::I32(Some(3)),
+        ]);
+        assert_ne!(params[0], params[1]);
+    }
 }
@@ -0,0 +1,95 @@
+use anyhow::{Error, Result};
+use serde::Deserialize;
+use std::fs;
+
+// Rows of the Sales, HR and Production tables as they are stored in fixtures/sales.json.
+// Column names follow the SQL Server schema so the fixture can be loaded into any backend.
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct Customer {
+    pub custid: i32,
+    pub companyname: String,
+    pub contactname: Option<String>,
+    pub contacttitle: String,
+    pub city: String,
+    pub country: String,
+    pub phone: String,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct Employee {
+    pub empid: i32,
+    pub lastname: String,
+    pub firstname: String,
+    pub title: Option<String>,
+    pub mgrid: Option<i32>,
+    pub hiredate: String,
+    pub country: String,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct Shipper {
+    pub shipperid: i32,
+    pub companyname: String,
+    pub phone: String,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct Category {
+    pub categoryid: i32,
+    pub categoryname: String,
+    pub description: String,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct Product {
+    pub productid: i32,
+    pub productname: String,
+    pub categoryid: i32,
+    pub unitprice: f64,
+    pub discontinued: bool,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct Order {
+    pub orderid: i32,
+    pub custid: i32,
+    pub empid: i32,
+    pub orderdate: String,
+    pub requireddate: String,
+    pub shippeddate: Option<String>,
+    pub shipperid: i32,
+    pub freight: f64,
+    pub shipname: Option<String>,
+    pub shipcity: String,
+    pub shipcountry: String,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct OrderDetail {
+    pub orderid: i32,
+    pub productid: i32,
+    pub unitprice: f64,
+    pub qty: i32,
+    pub discount: f64,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct FixtureData {
+    pub customers: Vec<Customer>,
+    pub employees: Vec<Employee>,
+    pub shippers: Vec<Shipper>,
+    pub categories: Vec<Category>,
+    pub products: Vec<Product>,
+    pub orders: Vec<Order>,
+    pub order_details: Vec<OrderDetail>,
+}
+
+impl FixtureData {
+    pub fn load(path: &str) -> Result<Self, Error> {
+        let contents = fs::read_to_string(path)
+            .map_err(|e| Error::msg(format!("Failed to read fixture file {}: {}", path, e)))?;
+        let data = serde_json::from_str(&contents)?;
+        Ok(data)
+    }
+}
@@ -0,0 +1,650 @@
+use anyhow::{Error, Result};
+use chrono::NaiveDate;
+use serde::Serialize;
+use serde_json::Value as JsonValue;
+use std::cmp::Ordering;
+use std::fmt;
+
+use crate::models::gridrequest::{ColumnFilter, FilterKind, FilterType, GridRequest, JoinOperator, Page, SortDirection};
+
+// Server-side paging, sorting and filtering for reports shown in ag-Grid. The grid's request
+// is checked against a whitelist of report columns and compiled once; the SQL backends render
+// it as WHERE / ORDER BY / paging clauses over the report query with every value bound as a
+// parameter, the in-memory backend evaluates it on the report rows.
+
+pub const DEFAULT_PAGE_SIZE: u32 = 100;
+pub const MAX_PAGE_SIZE: u32 = 1000;
+
+// A report column the grid may sort and filter on. `id` is the JSON field (ag-Grid's colId),
+// `column` the name of the column in the report's result set.
+pub struct GridColumn {
+    pub id: &'static str,
+    pub column: &'static str,
+    pub kind: FilterType,
+    // For columns the report normalizes after the query (countries): maps a value to the
+    // spelling stored in the database, which is what SQL filters and sorts on.
+    pub stored: Option<fn(String) -> String>,
+}
+
+impl GridColumn {
+    pub const fn new(id: &'static str, column: &'static str, kind: FilterType) -> Self {
+        GridColumn { id, column, kind, stored: None }
+    }
+}
+
+// A request the grid should not have sent: unknown column, filter that does not fit the
+// column, page out of range. Answered with 400.
+#[derive(Debug)]
+pub struct GridError(String);
+
+impl fmt::Display for GridError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for GridError {}
+
+fn invalid<T>(message: String) -> Result<T, GridError> {
+    Err(GridError(message))
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SqlParam {
+    Text(String),
+    Number(f64),
+    Int(i64),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Dialect {
+    MsSql,
+    Sqlite,
+    Postgres,
+}
+
+impl Dialect {
+    fn placeholder(self, n: usize) -> String {
+        match self {
+            Dialect::MsSql => format!("@P{}", n),
+            Dialect::Sqlite => format!("?{}", n),
+            Dialect::Postgres => format!("${}", n),
+        }
+    }
+
+    // SQL Server and SQLite put NULLs first when sorting ascending, Postgres last; the
+    // Postgres pages are made to match.
+    fn nulls(self, direction: SortDirection) -> &'static str {
+        match (self, direction) {
+            (Dialect::Postgres, SortDirection::Asc) => " NULLS FIRST",
+            (Dialect::Postgres, SortDirection::Desc) => " NULLS LAST",
+            _ => "",
+        }
+    }
+
+    fn page(self, offset: &str, limit: &str) -> String {
+        match self {
+            Dialect::MsSql => format!("OFFSET {} ROWS FETCH NEXT {} ROWS ONLY", offset, limit),
+            Dialect::Sqlite | Dialect::Postgres => format!("LIMIT {} OFFSET {}", limit, offset),
+        }
+    }
+
+    // LIKE pattern with the wildcards in the user's text escaped by '\'.
+    fn like_pattern(self, text: &str, prefix: &str, suffix: &str) -> String {
+        let mut pattern = String::from(prefix);
+        for ch in text.chars() {
+            if matches!(ch, '\\' | '%' | '_') || (ch == '[' && self == Dialect::MsSql) {
+                pattern.push('\\');
+            }
+            pattern.push(ch);
+        }
+        pattern.push_str(suffix);
+        pattern
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Op {
+    Equals,
+    NotEqual,
+    Contains,
+    NotContains,
+    StartsWith,
+    EndsWith,
+    LessThan,
+    LessThanOrEqual,
+    GreaterThan,
+    GreaterThanOrEqual,
+    Blank,
+    NotBlank,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Operand {
+    Text(String),
+    Number(f64),
+}
+
+enum Condition {
+    Compare { column: &'static GridColumn, op: Op, value: Option<Operand> },
+    All(Vec<Condition>),
+    Any(Vec<Condition>),
+}
+
+// A grid request checked against a report's columns, ready to be rendered or applied.
+pub struct GridQuery {
+    conditions: Vec<Condition>,
+    sort: Vec<(&'static GridColumn, SortDirection)>,
+    pub offset: u32,
+    pub limit: u32,
+}
+
+// The clauses a SQL backend wraps around its report query. `params` are numbered from the
+// first_param passed to GridQuery::to_sql, `page_params` follow them and are only bound
+// to the page query, not to the count.
+pub struct GridSql {
+    pub filter: String,
+    pub order_by: String,
+    pub page: String,
+    pub params: Vec<SqlParam>,
+    pub page_params: Vec<SqlParam>,
+}
+
+impl GridSql {
+    pub fn select(&self, report_sql: &str) -> String {
+        format!("SELECT * FROM ({}) AS r WHERE {} ORDER BY {} {}", report_sql, self.filter, self.order_by, self.page)
+    }
+
+    pub fn count(&self, report_sql: &str) -> String {
+        format!("SELECT COUNT(*) AS total FROM ({}) AS r WHERE {}", report_sql, self.filter)
+    }
+}
+
+impl GridQuery {
+    pub fn parse(request: &GridRequest, columns: &'static [GridColumn]) -> Result<GridQuery, GridError> {
+        let end_row = request.end_row.unwrap_or(request.start_row.saturating_add(DEFAULT_PAGE_SIZE));
+        if end_row <= request.start_row || end_row - request.start_row > MAX_PAGE_SIZE {
+            return invalid(format!("endRow must be after startRow and at most {} rows past it", MAX_PAGE_SIZE));
+        }
+
+        let mut sort = Vec::new();
+        for sort_model in &request.sort_model {
+            let column = find_column(columns, &sort_model.col_id)?;
+            if !sort.iter().any(|(c, _): &(&GridColumn, _)| c.id == column.id) {
+                sort.push((column, sort_model.sort));
+            }
+        }
+        // The remaining columns break ties, so rows never move between pages
+        for column in columns {
+            if !sort.iter().any(|(c, _)| c.id == column.id) {
+                sort.push((column, SortDirection::Asc));
+            }
+        }
+
+        let mut conditions = Vec::new();
+        for (col_id, filter) in &request.filter_model {
+            let column = find_column(columns, col_id)?;
+            conditions.push(compile_filter(column, filter)?);
+        }
+
+        Ok(GridQuery { conditions, sort, offset: request.start_row, limit: end_row - request.start_row })
+    }
+
+    pub fn to_sql(&self, dialect: Dialect, first_param: usize) -> GridSql {
+        let mut params = Params { dialect, first: first_param, values: Vec::new() };
+
+        let filter = if self.conditions.is_empty() {
+            "1 = 1".to_string()
+        } else {
+            self.conditions.iter().map(|c| c.to_sql(&mut params)).collect::<Vec<_>>().join(" AND ")
+        };
+
+        let order_by = self.sort.iter()
+            .map(|(column, direction)| {
+                let keyword = if *direction == SortDirection::Asc { "ASC" } else { "DESC" };
+                format!("r.{} {}{}", column.column, keyword, dialect.nulls(*direction))
+            })
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        let filter_params = params.values.len();
+        let offset = params.push(SqlParam::Int(self.offset as i64));
+        let limit = params.push(SqlParam::Int(self.limit as i64));
+        let page = dialect.page(&offset, &limit);
+        let page_params = params.values.split_off(filter_params);
+
+        GridSql { filter, order_by, page, params: params.values, page_params }
+    }
+
+    // The same filtering, sorting and paging for backends that build the report in Rust.
+    // Rows are compared through their JSON form, so the column ids are the serialized field names.
+    pub fn apply<T: Serialize>(&self, rows: Vec<T>) -> Result<Page<T>, Error> {
+        let mut matching = Vec::new();
+        for row in rows {
+            let json = serde_json::to_value(&row)?;
+            if self.conditions.iter().all(|c| c.matches(&json)) {
+                matching.push((json, row));
+            }
+        }
+
+        matching.sort_by(|(a, _), (b, _)| {
+            self.sort.iter()
+                .map(|(column, direction)| {
+                    let ordering = compare_cells(&cell(column, a), &cell(column, b));
+                    if *direction == SortDirection::Asc { ordering } else { ordering.reverse() }
+                })
+                .find(|ordering| ordering.is_ne())
+                .unwrap_or(Ordering::Equal)
+        });
+
+        let total = matching.len() as i64;
+        let rows = matching.into_iter()
+            .skip(self.offset as usize)
+            .take(self.limit as usize)
+            .map(|(_, row)| row)
+            .collect();
+        Ok(Page { rows, total })
+    }
+}
+
+fn find_column(columns: &'static [GridColumn], col_id: &str) -> Result<&'static GridColumn, GridError> {
+    match columns.iter().find(|c| c.id == col_id) {
+        Some(column) => Ok(column),
+        None => invalid(format!("Unknown column `{}`", col_id)),
+    }
+}
+
+//-------- COMPILING FILTERS --------------//
+fn compile_filter(column: &'static GridColumn, filter: &ColumnFilter) -> Result<Condition, GridError> {
+    if filter.filter_type != column.kind {
+        let kind = format!("{:?}", column.kind).to_lowercase();
+        return invalid(format!("Column `{}` takes a {} filter", column.id, kind));
+    }
+
+    if !filter.conditions.is_empty() {
+        let conditions = filter.conditions.iter()
+            .map(|c| compile_filter(column, c))
+            .collect::<Result<Vec<_>, _>>()?;
+        return Ok(match filter.operator {
+            Some(JoinOperator::Or) => Condition::Any(conditions),
+            _ => Condition::All(conditions),
+        });
+    }
+
+    let Some(kind) = filter.kind else {
+        return invalid(format!("Filter on `{}` has no type", column.id));
+    };
+    let compare = |op, value| Condition::Compare { column, op, value };
+
+    let op = match kind {
+        FilterKind::Blank => return Ok(compare(Op::Blank, None)),
+        FilterKind::NotBlank => return Ok(compare(Op::NotBlank, None)),
+        FilterKind::InRange if column.kind != FilterType::Text => {
+            // Exclusive on both ends, like ag-Grid's own inRange
+            let (from, to) = range_operands(column, filter)?;
+            return Ok(Condition::All(vec![
+                compare(Op::GreaterThan, Some(from)),
+                compare(Op::LessThan, Some(to)),
+            ]));
+        }
+        FilterKind::Equals => Op::Equals,
+        FilterKind::NotEqual => Op::NotEqual,
+        FilterKind::Contains if column.kind == FilterType::Text => Op::Contains,
+        FilterKind::NotContains if column.kind == FilterType::Text => Op::NotContains,
+        FilterKind::StartsWith if column.kind == FilterType::Text => Op::StartsWith,
+        FilterKind::EndsWith if column.kind == FilterType::Text => Op::EndsWith,
+        FilterKind::LessThan if column.kind != FilterType::Text => Op::LessThan,
+        FilterKind::LessThanOrEqual if column.kind != FilterType::Text => Op::LessThanOrEqual,
+        FilterKind::GreaterThan if column.kind != FilterType::Text => Op::GreaterThan,
+        FilterKind::GreaterThanOrEqual if column.kind != FilterType::Text => Op::GreaterThanOrEqual,
+        other => return invalid(format!("Filter `{:?}` does not apply to column `{}`", other, column.id)),
+    };
+
+    let value = match column.kind {
+        FilterType::Text => {
+            let text = text_operand(column, filter.filter.as_ref())?;
+            // Equality accepts any spelling the column knows, the pattern filters match the stored text
+            match (column.stored, op) {
+                (Some(stored), Op::Equals | Op::NotEqual) => stored(text),
+                _ => text,
+            }
+        }
+        FilterType::Number => return Ok(compare(op, Some(number_operand(column, filter.filter.as_ref())?))),
+        FilterType::Date => date_operand(column, filter.date_from.as_deref())?,
+    };
+    Ok(compare(op, Some(Operand::Text(value))))
+}
+
+fn range_operands(column: &GridColumn, filter: &ColumnFilter) -> Result<(Operand, Operand), GridError> {
+    match column.kind {
+        FilterType::Date => Ok((
+            Operand::Text(date_operand(column, filter.date_from.as_deref())?),
+            Operand::Text(date_operand(column, filter.date_to.as_deref())?),
+        )),
+        _ => Ok((
+            number_operand(column, filter.filter.as_ref())?,
+            number_operand(column, filter.filter_to.as_ref())?,
+        )),
+    }
+}
+
+fn text_operand(column: &GridColumn, value: Option<&JsonValue>) -> Result<String, GridError> {
+    match value {
+        Some(JsonValue::String(text)) => Ok(text.clone()),
+        Some(JsonValue::Number(number)) => Ok(number.to_string()),
+        _ => invalid(format!("Filter on `{}` needs a text value", column.id)),
+    }
+}
+
+fn number_operand(column: &GridColumn, value: Option<&JsonValue>) -> Result<Operand, GridError> {
+    let number = match value {
+        Some(JsonValue::Number(number)) => number.as_f64(),
+        Some(JsonValue::String(text)) => text.trim().parse::<f64>().ok(),
+        _ => None,
+    };
+    match number {
+        Some(number) if number.is_finite() => Ok(Operand::Number(number)),
+        _ => invalid(format!("Filter on `{}` needs a number", column.id)),
+    }
+}
+
+// ag-Grid sends dates as "YYYY-MM-DD hh:mm:ss"; the reports hold "YYYY-MM-DD" text.
+fn date_operand(column: &GridColumn, value: Option<&str>) -> Result<String, GridError> {
+    let date = value
+        .and_then(|v| v.get(..10))
+        .and_then(|v| NaiveDate::parse_from_str(v, "%Y-%m-%d").ok());
+    match date {
+        Some(date) => Ok(date.format("%Y-%m-%d").to_string()),
+        None => invalid(format!("Filter on `{}` needs a date as YYYY-MM-DD", column.id)),
+    }
+}
+
+//-------- RENDERING SQL --------------//
+struct Params {
+    dialect: Dialect,
+    first: usize,
+    values: Vec<SqlParam>,
+}
+
+impl Params {
+    fn push(&mut self, value: SqlParam) -> String {
+        self.values.push(value);
+        self.dialect.placeholder(self.first + self.values.len() - 1)
+    }
+}
+
+impl Condition {
+    fn to_sql(&self, params: &mut Params) -> String {
+        match self {
+            Condition::All(conditions) => join_sql(conditions, " AND ", params),
+            Condition::Any(conditions) => join_sql(conditions, " OR ", params),
+            Condition::Compare { column, op, value } => {
+                let target = format!("r.{}", column.column);
+                let text = column.kind == FilterType::Text;
+                match (op, value) {
+                    (Op::Blank, _) if text => format!("({0} IS NULL OR {0} = '')", target),
+                    (Op::NotBlank, _) if text => format!("({0} IS NOT NULL AND {0} <> '')", target),
+                    (Op::Blank, _) => format!("{} IS NULL", target),
+                    (Op::NotBlank, _) => format!("{} IS NOT NULL", target),
+                    (op, Some(Operand::Text(value))) if text => {
+                        let dialect = params.dialect;
+                        let like = |params: &mut Params, prefix, suffix| {
+                            params.push(SqlParam::Text(dialect.like_pattern(value, prefix, suffix)))
+                        };
+                        match op {
+                            Op::Equals => format!("LOWER({}) = LOWER({})", target, params.push(SqlParam::Text(value.clone()))),
+                            Op::NotEqual => format!("({0} IS NULL OR LOWER({0}) <> LOWER({1}))", target, params.push(SqlParam::Text(value.clone()))),
+                            Op::Contains => format!("LOWER({}) LIKE LOWER({}) ESCAPE '\\'", target, like(params, "%", "%")),
+                            Op::NotContains => format!("({0} IS NULL OR LOWER({0}) NOT LIKE LOWER({1}) ESCAPE '\\')", target, like(params, "%", "%")),
+                            Op::StartsWith => format!("LOWER({}) LIKE LOWER({}) ESCAPE '\\'", target, like(params, "", "%")),
+                            _ => format!("LOWER({}) LIKE LOWER({}) ESCAPE '\\'", target, like(params, "%", "")),
+                        }
+                    }
+                    (op, Some(value)) => {
+                        let param = params.push(match value {
+                            Operand::Text(text) => SqlParam::Text(text.clone()),
+                            Operand::Number(number) => SqlParam::Number(*number),
+                        });
+                        match op {
+                            Op::NotEqual => format!("({0} IS NULL OR {0} <> {1})", target, param),
+                            op => format!("{} {} {}", target, comparison(*op), param),
+                        }
+                    }
+                    (_, None) => "1 = 1".to_string(),
+                }
+            }
+        }
+    }
+
+    fn matches(&self, row: &JsonValue) -> bool {
+        match self {
+            Condition::All(conditions) => conditions.iter().all(|c| c.matches(row)),
+            Condition::Any(conditions) => conditions.iter().any(|c| c.matches(row)),
+            Condition::Compare { column, op, value } => {
+                let cell = cell(column, row);
+                match (op, value, cell) {
+                    (Op::Blank, _, cell) => is_blank(&cell),
+                    (Op::NotBlank, _, cell) => !is_blank(&cell),
+                    (Op::NotEqual | Op::NotContains, _, None) => true,
+                    (_, _, None) | (_, None, _) => false,
+                    (op, Some(Operand::Text(value)), Some(Operand::Text(cell))) if column.kind == FilterType::Text => {
+                        let (cell, value) = (cell.to_lowercase(), value.to_lowercase());
+                        match op {
+                            Op::Equals => cell == value,
+                            Op::NotEqual => cell != value,
+                            Op::Contains => cell.contains(&value),
+                            Op::NotContains => !cell.contains(&value),
+                            Op::StartsWith => cell.starts_with(&value),
+                            _ => cell.ends_with(&value),
+                        }
+                    }
+                    (op, Some(value), Some(cell)) => {
+                        let ordering = compare_cells(&Some(cell), &Some(value.clone()));
+                        match op {
+                            Op::Equals => ordering.is_eq(),
+                            Op::NotEqual => ordering.is_ne(),
+                            Op::LessThan => ordering.is_lt(),
+                            Op::LessThanOrEqual => ordering.is_le(),
+                            Op::GreaterThan => ordering.is_gt(),
+                            _ => ordering.is_ge(),
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn join_sql(conditions: &[Condition], separator: &str, params: &mut Params) -> String {
+    let parts: Vec<String> = conditions.iter().map(|c| c.to_sql(params)).collect();
+    format!("({})", parts.join(separator))
+}
+
+fn comparison(op: Op) -> &'static str {
+    match op {
+        Op::LessThan => "<",
+        Op::LessThanOrEqual => "<=",
+        Op::GreaterThan => ">",
+        Op::GreaterThanOrEqual => ">=",
+        _ => "=",
+    }
+}
+
+//-------- EVALUATING IN RUST --------------//
+fn cell(column: &GridColumn, row: &JsonValue) -> Option<Operand> {
+    match (column.kind, row.get(column.id)?) {
+        (FilterType::Number, value) => value.as_f64().map(Operand::Number),
+        (_, JsonValue::String(text)) => match column.stored {
+            Some(stored) => Some(Operand::Text(stored(text.clone()))),
+            None => Some(Operand::Text(text.clone())),
+        },
+        _ => None,
+    }
+}
+
+fn is_blank(cell: &Option<Operand>) -> bool {
+    match cell {
+        None => true,
+        Some(Operand::Text(text)) => text.is_empty(),
+        Some(Operand::Number(_)) => false,
+    }
+}
+
+// NULLs sort first, as they do in SQL Server.
+fn compare_cells(a: &Option<Operand>, b: &Option<Operand>) -> Ordering {
+    match (a, b) {
+        (Some(Operand::Number(a)), Some(Operand::Number(b))) => a.total_cmp(b),
+        (Some(Operand::Text(a)), Some(Operand::Text(b))) => a.cmp(b),
+        (None, None) => Ordering::Equal,
+        (None, _) => Ordering::Less,
+        (_, None) => Ordering::Greater,
+        (Some(Operand::Number(_)), Some(Operand::Text(_))) => Ordering::Less,
+        (Some(Operand::Text(_)), Some(Operand::Number(_))) => Ordering::Greater,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::fixtures::FixtureData;
+    use crate::db::memory::DatabaseMemory;
+    use crate::db::repository::ReportRepository;
+    use crate::db::sqlite::DatabaseSQLite;
+    use crate::models::ordersreport::ORDERS_GRID_COLUMNS;
+    use crate::models::reportfilter::ReportFilter;
+    use serde_json::json;
+
+    fn parse(request: JsonValue) -> Result<GridQuery, GridError> {
+        GridQuery::parse(&serde_json::from_value(request).unwrap(), ORDERS_GRID_COLUMNS)
+    }
+
+    fn filter_sql(dialect: Dialect, filter_model: JsonValue) -> GridSql {
+        parse(json!({"startRow": 0, "endRow": 10, "filterModel": filter_model})).unwrap().to_sql(dialect, 7)
+    }
+
+    fn text(value: &str) -> SqlParam {
+        SqlParam::Text(value.to_string())
+    }
+
+    #[test]
+    fn unknown_columns_are_rejected() {
+        let sort = parse(json!({"sortModel": [{"colId": "x; DROP TABLE Sales.Orders", "sort": "asc"}]}));
+        assert_eq!(sort.err().unwrap().to_string(), "Unknown column `x; DROP TABLE Sales.Orders`");
+
+        let filter = parse(json!({"filterModel": {"x; DROP": {"filterType": "text", "type": "equals", "filter": "a"}}}));
+        assert_eq!(filter.err().unwrap().to_string(), "Unknown column `x; DROP`");
+
+        // The SQL column name is not accepted in place of the JSON field
+        assert!(parse(json!({"sortModel": [{"colId": "customer_contact", "sort": "asc"}]})).is_err());
+    }
+
+    #[test]
+    fn filters_must_fit_the_column() {
+        let number_on_text = json!({"customer_name": {"filterType": "number", "type": "equals", "filter": 1}});
+        assert!(parse(json!({"filterModel": number_on_text})).is_err());
+        let contains_on_number = json!({"order_id": {"filterType": "number", "type": "contains", "filter": 1}});
+        assert!(parse(json!({"filterModel": contains_on_number})).is_err());
+        let not_a_number = json!({"order_id": {"filterType": "number", "type": "equals", "filter": "1 OR 1=1"}});
+        assert!(parse(json!({"filterModel": not_a_number})).is_err());
+    }
+
+    #[test]
+    fn pages_are_bounded() {
+        assert!(parse(json!({"startRow": 10, "endRow": 10})).is_err());
+        assert!(parse(json!({"startRow": 0, "endRow": MAX_PAGE_SIZE + 1})).is_err());
+        let query = parse(json!({"startRow": 200})).unwrap();
+        assert_eq!((query.offset, query.limit), (200, DEFAULT_PAGE_SIZE));
+    }
+
+    #[test]
+    fn like_wildcards_are_escaped() {
+        let grid = filter_sql(Dialect::Sqlite, json!({"ship_name": {"filterType": "text", "type": "contains", "filter": "50%_off\\"}}));
+        assert_eq!(grid.filter, "LOWER(r.ship_name) LIKE LOWER(?7) ESCAPE '\\'");
+        assert_eq!(grid.params, vec![text("%50\\%\\_off\\\\%")]);
+
+        let starts = json!({"ship_name": {"filterType": "text", "type": "startsWith", "filter": "[a]"}});
+        assert_eq!(filter_sql(Dialect::MsSql, starts.clone()).params, vec![text("\\[a]%")]);
+        assert_eq!(filter_sql(Dialect::Postgres, starts).params, vec![text("[a]%")]);
+    }
+
+    #[test]
+    fn date_ranges_are_exclusive_and_normalized() {
+        let range = json!({"order_date": {"filterType": "date", "type": "inRange", "dateFrom": "2022-01-01 00:00:00", "dateTo": "2022-03-31 00:00:00"}});
+        let grid = filter_sql(Dialect::Postgres, range);
+        assert_eq!(grid.filter, "(r.order_date > $7 AND r.order_date < $8)");
+        assert_eq!(grid.params, vec![text("2022-01-01"), text("2022-03-31")]);
+
+        let bad = json!({"order_date": {"filterType": "date", "type": "inRange", "dateFrom": "2022-01-01", "dateTo": "31/03/2022"}});
+        assert!(parse(json!({"filterModel": bad})).is_err());
+    }
+
+    #[test]
+    fn placeholders_are_numbered_from_first_param() {
+        let filters = json!({
+            "customer_name": {"filterType": "text", "type": "equals", "filter": "Customer AHPOP"},
+            "freight_value": {"filterType": "number", "operator": "OR", "conditions": [
+                {"filterType": "number", "type": "lessThan", "filter": 10},
+                {"filterType": "number", "type": "greaterThan", "filter": 100},
+            ]},
+        });
+        let expected = [
+            (Dialect::MsSql, "LOWER(r.customer_name) = LOWER(@P7) AND (r.freight_value < @P8 OR r.freight_value > @P9)", "OFFSET @P10 ROWS FETCH NEXT @P11 ROWS ONLY"),
+            (Dialect::Sqlite, "LOWER(r.customer_name) = LOWER(?7) AND (r.freight_value < ?8 OR r.freight_value > ?9)", "LIMIT ?11 OFFSET ?10"),
+            (Dialect::Postgres, "LOWER(r.customer_name) = LOWER($7) AND (r.freight_value < $8 OR r.freight_value > $9)", "LIMIT $11 OFFSET $10"),
+        ];
+        for (dialect, filter, page) in expected {
+            let grid = filter_sql(dialect, filters.clone());
+            assert_eq!(grid.filter, filter);
+            assert_eq!(grid.page, page);
+            assert_eq!(grid.params, vec![text("Customer AHPOP"), SqlParam::Number(10.0), SqlParam::Number(100.0)]);
+            assert_eq!(grid.page_params, vec![SqlParam::Int(0), SqlParam::Int(10)]);
+        }
+    }
+
+    #[test]
+    fn postgres_sorts_nulls_like_sql_server() {
+        let query = parse(json!({"sortModel": [{"colId": "ship_name", "sort": "desc"}, {"colId": "employee_title", "sort": "asc"}]})).unwrap();
+        let postgres = query.to_sql(Dialect::Postgres, 1).order_by;
+        assert!(postgres.starts_with("r.ship_name DESC NULLS LAST, r.employee_title ASC NULLS FIRST, r.order_id ASC NULLS FIRST"), "{}", postgres);
+        let mssql = query.to_sql(Dialect::MsSql, 1).order_by;
+        assert!(mssql.starts_with("r.ship_name DESC, r.employee_title ASC, r.order_id ASC"), "{}", mssql);
+        assert_eq!(mssql.matches(", ").count(), ORDERS_GRID_COLUMNS.len() - 1);
+    }
+
+    // Rows and totals from the in-memory evaluation must match what SQLite computes from the SQL.
+    #[tokio::test]
+    async fn apply_matches_the_sql_path() {
+        let fixtures = FixtureData::load("fixtures/sales.json").unwrap();
+        let directory = std::env::temp_dir().join(format!("mssqlapi-grid-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&directory);
+        let sqlite = DatabaseSQLite::init(directory.to_str().unwrap()).await.unwrap();
+        sqlite.seed(&fixtures).await.unwrap();
+        let memory = DatabaseMemory { data: fixtures };
+
+        let requests = [
+            json!({"startRow": 20, "endRow": 40, "sortModel": [{"colId": "customer_country", "sort": "desc"}],
+                   "filterModel": {"customer_name": {"filterType": "text", "type": "contains", "filter": "a"}}}),
+            json!({"startRow": 0, "endRow": 15, "sortModel": [{"colId": "order_value", "sort": "desc"}],
+                   "filterModel": {"order_date": {"filterType": "date", "type": "inRange", "dateFrom": "2022-01-01 00:00:00", "dateTo": "2022-12-31 00:00:00"},
+                                   "freight_value": {"filterType": "number", "type": "greaterThan", "filter": 20}}}),
+            json!({"sortModel": [{"colId": "ship_name", "sort": "asc"}, {"colId": "employee_title", "sort": "desc"}]}),
+            json!({"filterModel": {"ship_name": {"filterType": "text", "type": "blank"}}}),
+            json!({"filterModel": {"customer_country": {"filterType": "text", "type": "equals", "filter": "United Kingdom"}}}),
+            json!({"filterModel": {"customer_contact_name": {"filterType": "text", "type": "notContains", "filter": "an"}}}),
+            json!({"filterModel": {"ship_name": {"filterType": "text", "type": "contains", "filter": "%"}}}),
+        ];
+
+        let filter = ReportFilter::default();
+        for request in requests {
+            let query = parse(request.clone()).unwrap();
+            let from_memory = memory.get_orders_report_page(&filter, &query).await.unwrap();
+            let from_sql = sqlite.get_orders_report_page(&filter, &query).await.unwrap();
+            let ids = |page: &Page<crate::models::ordersreport::OrdersReport>| page.rows.iter().map(|r| r.order_id).collect::<Vec<_>>();
+            assert_eq!(from_memory.total, from_sql.total, "{}", request);
+            assert_eq!(ids(&from_memory), ids(&from_sql), "{}", request);
+        }
+
+        sqlite.pool.close().await;
+        let _ = std::fs::remove_dir_all(&directory);
+    }
+}
@@ -0,0 +1,683 @@
+use anyhow::{Error, Result};
+use async_trait::async_trait;
+use chrono::NaiveDate;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+
+use crate::countries;
+use crate::db::fixtures::{Category, Customer, Employee, FixtureData, Order, Product, Shipper};
+use crate::db::grid::GridQuery;
+use crate::db::repository::ReportRepository;
+use crate::db::stream::{iter_stream, RowStream};
+
+use crate::models::gridrequest::Page;
+use crate::models::orderdetail::{OrderDetail, OrderHeader, OrderLine};
+use crate::models::ordersreport::OrdersReport;
+use crate::models::customerbyyear::{CustomerByYear, CustomerYearSales};
+use crate::models::customerprofile::{CustomerInfo, CustomerOrderValue, CustomerProductSales, CustomerProfile, CustomerProfileQuery};
+use crate::models::employeeperformance::{EmployeeInfo, EmployeePerformance, EmployeePerformanceQuery, EmployeePeriodSales};
+use crate::models::topperformers::{CustomerEmployeeSales, TopPerformers, TopPerformersQuery};
+use crate::models::saleschoropleth::{ChoroplethQuery, CountryCustomerSales, SalesChoropleth};
+use crate::models::shipperperformance::{ShipmentTotals, ShipperPerformance, ShipperPerformanceQuery};
+use crate::models::productsales::{CategoryPeriodSales, CategorySales, ProductInfo, ProductPeriodSales, ProductSales, ProductSalesQuery};
+use crate::models::salestimeseries::{DailySales, TimeSeriesPoint, TimeSeriesQuery};
+use crate::models::rfmsegmentation::{CustomerOrderTotals, RfmQuery, RfmReport};
+use crate::models::cohortretention::{CohortQuery, CohortRetention, CustomerOrder};
+use crate::models::salesforecast::{ForecastGrouping, MonthlyRevenue, SalesForecast, SalesForecastQuery};
+use crate::models::abcanalysis::{AbcDimension, AbcQuery, AbcReport, ItemRevenue};
+use crate::models::marketbasket::{BoughtWithReport, MarketBasketQuery, OrderProduct, ProductPair};
+use crate::models::reportfilter::{ReportFilter, ReportPeriod, YearRange};
+
+// Serves the reports from fixture tables held in memory. The calculations mirror
+// the T-SQL in database.rs so the dashboard looks the same without SQL Server.
+#[derive(Clone)]
+pub struct DatabaseMemory {
+    pub data: FixtureData,
+}
+
+impl DatabaseMemory {
+
+    //-------- INITIALIZING DATABASE --------------//
+    pub fn init(fixture_path: &str) -> Result<Self, Error> {
+        let data = FixtureData::load(fixture_path)?;
+        Ok(DatabaseMemory{data})
+    }
+
+    fn customer(&self, custid: i32) -> Result<&Customer, Error> {
+        self.data.customers.iter().find(|c| c.custid == custid)
+            .ok_or_else(|| Error::msg(format!("Unknown customer {}", custid)))
+    }
+
+    fn employee(&self, empid: i32) -> Result<&Employee, Error> {
+        self.data.employees.iter().find(|e| e.empid == empid)
+            .ok_or_else(|| Error::msg(format!("Unknown employee {}", empid)))
+    }
+
+    fn shipper(&self, shipperid: i32) -> Result<&Shipper, Error> {
+        self.data.shippers.iter().find(|s| s.shipperid == shipperid)
+            .ok_or_else(|| Error::msg(format!("Unknown shipper {}", shipperid)))
+    }
+
+    fn product(&self, productid: i32) -> Result<&Product, Error> {
+        self.data.products.iter().find(|p| p.productid == productid)
+            .ok_or_else(|| Error::msg(format!("Unknown product {}", productid)))
+    }
+
+    fn category(&self, categoryid: i32) -> Result<&Category, Error> {
+        self.data.categories.iter().find(|c| c.categoryid == categoryid)
+            .ok_or_else(|| Error::msg(format!("Unknown category {}", categoryid)))
+    }
+
+    // The products of every order that passes the filter, for the market basket reports.
+    fn order_products(&self, filter: &ReportFilter) -> Result<Vec<OrderProduct>, Error> {
+        let orders: HashSet<i32> = self.data.orders.iter()
+            .filter(|o| self.matches(o, filter))
+            .map(|o| o.orderid)
+            .collect();
+
+        self.data.order_details.iter()
+            .filter(|d| orders.contains(&d.orderid))
+            .map(|d| Ok(OrderProduct {
+                order_id: d.orderid,
+                product_id: d.productid,
+                product_name: self.product(d.productid)?.productname.clone(),
+            }))
+            .collect()
+    }
+
+    // Product sales per period ("" without a period), the rows behind both the product and
+    // the category report, keyed by product id and period.
+    fn product_sales(&self, filter: &ReportFilter, query: &ProductSalesQuery) -> Result<BTreeMap<(i32, String), ProductTotals>, Error> {
+        let orders: HashMap<i32, &Order> = self.data.orders.iter()
+            .filter(|o| self.matches(o, filter))
+            .map(|o| (o.orderid, o))
+            .collect();
+
+        let mut totals = BTreeMap::<(i32, String), ProductTotals>::new();
+        for detail in &self.data.order_details {
+            let Some(order) = orders.get(&detail.orderid) else {
+                continue;
+            };
+            let product = self.product(detail.productid)?;
+            if query.category_id.is_some_and(|id| id != product.categoryid) {
+                continue;
+            }
+            let period = query.period.map(|p| p.label(&order.orderdate)).unwrap_or_default();
+
+            let entry = totals.entry((product.productid, period)).or_default();
+            entry.revenue += detail.unitprice * detail.qty as f64 * (1.0 - detail.discount);
+            entry.gross_revenue += detail.unitprice * detail.qty as f64;
+            entry.list_revenue += product.unitprice * detail.qty as f64;
+            entry.units += detail.qty as i64;
+            entry.orders.insert(order.orderid);
+            entry.customers.insert(order.custid);
+        }
+        Ok(totals)
+    }
+
+    // Net value of every order line, unitprice * qty * (1 - discount), summed per order.
+    fn order_values(&self) -> HashMap<i32, f64> {
+        let mut values = HashMap::new();
+        for detail in &self.data.order_details {
+            *values.entry(detail.orderid).or_insert(0.0) += detail.unitprice * detail.qty as f64 * (1.0 - detail.discount);
+        }
+        values
+    }
+
+    // The WHERE clause the SQL backends build from the same filter.
+    fn matches(&self, order: &Order, filter: &ReportFilter) -> bool {
+        let date = NaiveDate::parse_from_str(&order.orderdate, "%Y-%m-%d").ok();
+        filter.from_date.is_none_or(|from| date.is_some_and(|d| d >= from))
+            && filter.to_date.is_none_or(|to| date.is_some_and(|d| d <= to))
+            && filter.country.as_ref().is_none_or(|country| {
+                self.customer(order.custid).is_ok_and(|c| &c.country == country)
+            })
+            && filter.customer_id.is_none_or(|id| order.custid == id)
+            && filter.employee_id.is_none_or(|id| order.empid == id)
+            && filter.shipper_id.is_none_or(|id| order.shipperid == id)
+    }
+
+    // Units ordered per order, summed over its detail lines.
+    fn order_quantities(&self) -> HashMap<i32, i64> {
+        let mut quantities = HashMap::new();
+        for detail in &self.data.order_details {
+            *quantities.entry(detail.orderid).or_insert(0) += detail.qty as i64;
+        }
+        quantities
+    }
+
+    // Orders joined to at least one detail line, like the INNER JOINs in the SQL reports.
+    fn orders_with_value(&self, filter: &ReportFilter) -> Vec<(&Order, f64)> {
+        let values = self.order_values();
+        self.data.orders.iter()
+            .filter(|o| self.matches(o, filter))
+            .filter_map(|o| values.get(&o.orderid).map(|v| (o, *v)))
+            .collect()
+    }
+
+    // One row per order, like the GROUP BY o.orderid in the SQL reports.
+    fn orders_report(&self, filter: &ReportFilter) -> Result<Vec<OrdersReport>, Error> {
+        let mut orders_data = Vec::<OrdersReport>::new();
+
+        for (order, order_value) in self.orders_with_value(filter) {
+            let customer = self.customer(order.custid)?;
+            let employee = self.employee(order.empid)?;
+            let shipper = self.shipper(order.shipperid)?;
+
+            orders_data.push(OrdersReport {
+                order_id: order.orderid,
+                customer_name: customer.companyname.clone(),
+                customer_contact_name: customer.contactname.clone(),
+                customer_country: countries::display_name(customer.country.clone()),
+                employee_name: employee_name(employee),
+                employee_title: employee.title.clone(),
+                shipper_name: shipper.companyname.clone(),
+                ship_name: order.shipname.clone(),
+                order_date: order.orderdate.clone(),
+                delivery_date: order.requireddate.clone(),
+                freight_value: order.freight,
+                order_value,
+                billable_value: order.freight + order_value,
+            });
+        }
+
+        Ok(orders_data)
+    }
+}
+
+// Running totals behind one ProductPeriodSales or CategoryPeriodSales row.
+#[derive(Default)]
+struct ProductTotals {
+    revenue: f64,
+    gross_revenue: f64,
+    list_revenue: f64,
+    units: i64,
+    orders: BTreeSet<i32>,
+    customers: BTreeSet<i32>,
+}
+
+// Running totals behind one EmployeePeriodSales row.
+#[derive(Default)]
+struct PeriodTotals {
+    revenue: f64,
+    orders: BTreeSet<i32>,
+    customers: BTreeSet<i32>,
+    discount_sum: f64,
+    lines: u32,
+}
+
+impl PeriodTotals {
+    fn add(&mut self, order: &Order, value: f64, discount: f64) {
+        self.revenue += value;
+        self.orders.insert(order.orderid);
+        self.customers.insert(order.custid);
+        self.discount_sum += discount;
+        self.lines += 1;
+    }
+}
+
+fn period_sales(totals: BTreeMap<(i32, String), PeriodTotals>) -> Vec<EmployeePeriodSales> {
+    totals.into_iter()
+        .map(|((employee_id, period), t)| EmployeePeriodSales {
+            employee_id,
+            period,
+            revenue: t.revenue,
+            order_count: t.orders.len() as i64,
+            customers_served: t.customers.len() as i64,
+            average_discount: t.discount_sum / t.lines as f64,
+        })
+        .collect()
+}
+
+// Days from `from` to `to`, both "YYYY-MM-DD".
+fn days_between(from: &str, to: &str) -> Result<i64, Error> {
+    let from = NaiveDate::parse_from_str(from, "%Y-%m-%d")?;
+    let to = NaiveDate::parse_from_str(to, "%Y-%m-%d")?;
+    Ok((to - from).num_days())
+}
+
+fn order_year(order: &Order) -> i32 {
+    order.orderdate[..4].parse().unwrap_or_default()
+}
+
+fn employee_name(employee: &Employee) -> String {
+    format!("{}, {}", employee.lastname, employee.firstname)
+}
+
+#[async_trait]
+impl ReportRepository for DatabaseMemory {
+
+    fn stream_orders_report(&self, filter: &ReportFilter) -> RowStream<OrdersReport> {
+        iter_stream(self.orders_report(filter))
+    }
+
+    async fn get_orders_report_page(&self, filter: &ReportFilter, query: &GridQuery) -> Result<Page<OrdersReport>, Error> {
+        query.apply(self.orders_report(filter)?)
+    }
+
+    async fn get_order_detail(&self, order_id: i32) -> Result<Option<OrderDetail>, Error> {
+        let Some(order) = self.data.orders.iter().find(|o| o.orderid == order_id) else {
+            return Ok(None);
+        };
+        let customer = self.customer(order.custid)?;
+        let employee = self.employee(order.empid)?;
+        let shipper = self.shipper(order.shipperid)?;
+
+        let header = OrderHeader {
+            order_id: order.orderid,
+            customer_id: customer.custid,
+            customer_name: customer.companyname.clone(),
+            customer_contact_name: customer.contactname.clone(),
+            customer_country: countries::display_name(customer.country.clone()),
+            employee_id: employee.empid,
+            employee_name: employee_name(employee),
+            employee_title: employee.title.clone(),
+            shipper_id: shipper.shipperid,
+            shipper_name: shipper.companyname.clone(),
+            ship_name: order.shipname.clone(),
+            ship_city: order.shipcity.clone(),
+            ship_country: countries::display_name(order.shipcountry.clone()),
+            order_date: order.orderdate.clone(),
+            required_date: order.requireddate.clone(),
+            shipped_date: order.shippeddate.clone(),
+            freight: order.freight,
+        };
+
+        let mut lines = Vec::new();
+        for detail in self.data.order_details.iter().filter(|d| d.orderid == order_id) {
+            let product = self.data.products.iter().find(|p| p.productid == detail.productid)
+                .ok_or_else(|| Error::msg(format!("Unknown product {}", detail.productid)))?;
+            lines.push(OrderLine {
+                product_id: product.productid,
+                product_name: product.productname.clone(),
+                unit_price: detail.unitprice,
+                quantity: detail.qty,
+                discount: detail.discount,
+                line_total: detail.unitprice * detail.qty as f64 * (1.0 - detail.discount),
+            });
+        }
+        lines.sort_by_key(|line| line.product_id);
+
+        Ok(Some(OrderDetail::new(header, lines)))
+    }
+
+    async fn get_customer_sales_by_year(&self, filter: &ReportFilter, years: &YearRange) -> Result<Vec<CustomerByYear>, Error> {
+        let mut totals = BTreeMap::<(String, i32), f64>::new();
+
+        for (order, value) in self.orders_with_value(filter) {
+            let year = order_year(order);
+            if years.from_year.is_some_and(|from| year < from) || years.to_year.is_some_and(|to| year > to) {
+                continue;
+            }
+            let customer = self.customer(order.custid)?;
+            *totals.entry((customer.companyname.clone(), year)).or_insert(0.0) += value;
+        }
+
+        let rows = totals.into_iter()
+            .map(|((customer_name, order_year), sales)| CustomerYearSales { customer_name, order_year, sales })
+            .collect();
+
+        Ok(CustomerByYear::pivot(rows, years))
+    }
+
+    async fn get_customer_profile(&self, customer_id: i32, filter: &ReportFilter, query: &CustomerProfileQuery) -> Result<Option<CustomerProfile>, Error> {
+        let Some(customer) = self.data.customers.iter().find(|c| c.custid == customer_id) else {
+            return Ok(None);
+        };
+        let info = CustomerInfo {
+            customer_id: customer.custid,
+            customer_name: customer.companyname.clone(),
+            contact_name: customer.contactname.clone(),
+            contact_title: Some(customer.contacttitle.clone()),
+            city: customer.city.clone(),
+            country: countries::display_name(customer.country.clone()),
+            phone: Some(customer.phone.clone()),
+        };
+
+        let mut orders = Vec::new();
+        let mut order_ids = BTreeSet::new();
+        for (order, order_value) in self.orders_with_value(filter).into_iter().filter(|(o, _)| o.custid == customer_id) {
+            let employee = self.employee(order.empid)?;
+            order_ids.insert(order.orderid);
+            orders.push(CustomerOrderValue {
+                order_date: order.orderdate.clone(),
+                employee_id: employee.empid,
+                employee_name: employee_name(employee),
+                order_value,
+            });
+        }
+
+        let mut by_product = BTreeMap::<i32, CustomerProductSales>::new();
+        for detail in self.data.order_details.iter().filter(|d| order_ids.contains(&d.orderid)) {
+            let product = self.data.products.iter().find(|p| p.productid == detail.productid)
+                .ok_or_else(|| Error::msg(format!("Unknown product {}", detail.productid)))?;
+            let sales = by_product.entry(product.productid).or_insert_with(|| CustomerProductSales {
+                product_id: product.productid,
+                product_name: product.productname.clone(),
+                order_count: 0,
+                quantity: 0,
+                revenue: 0.0,
+            });
+            // (orderid, productid) is the key of OrderDetails, so each line is a distinct order
+            sales.order_count += 1;
+            sales.quantity += detail.qty as i64;
+            sales.revenue += detail.unitprice * detail.qty as f64 * (1.0 - detail.discount);
+        }
+
+        Ok(Some(CustomerProfile::build(info, orders, by_product.into_values().collect(), query)))
+    }
+
+    async fn get_top_performers(&self, filter: &ReportFilter, query: &TopPerformersQuery) -> Result<Vec<TopPerformers>, Error> {
+        let mut totals = BTreeMap::<(i32, i32, i32), (BTreeSet<i32>, i64, f64)>::new();
+        let quantities = self.order_quantities();
+
+        for (order, value) in self.orders_with_value(filter) {
+            let year = order_year(order);
+            if query.year.is_some_and(|y| y != year) {
+                continue;
+            }
+            let entry = totals.entry((order.custid, order.empid, year)).or_default();
+            entry.0.insert(order.orderid);
+            entry.1 += quantities.get(&order.orderid).copied().unwrap_or(0);
+            entry.2 += value;
+        }
+
+        let mut rows = Vec::new();
+        for ((custid, empid, order_year), (orders, quantity, sales)) in totals {
+            rows.push(CustomerEmployeeSales {
+                customer_id: custid,
+                customer_name: self.customer(custid)?.companyname.clone(),
+                employee_name: employee_name(self.employee(empid)?),
+                order_year,
+                order_count: orders.len() as i64,
+                quantity,
+                sales,
+            });
+        }
+
+        Ok(TopPerformers::rank(rows, query))
+    }
+
+    async fn get_employee_performance(&self, filter: &ReportFilter, query: &EmployeePerformanceQuery) -> Result<Vec<EmployeePerformance>, Error> {
+        let employees: Vec<EmployeeInfo> = self.data.employees.iter()
+            .map(|e| EmployeeInfo {
+                employee_id: e.empid,
+                employee_name: employee_name(e),
+                title: e.title.clone(),
+                manager_id: e.mgrid,
+            })
+            .collect();
+
+        // The employee and everyone above them, what the recursive CTE pairs them with
+        let chain = |empid: i32| {
+            let mut chain = vec![empid];
+            let mut current = self.data.employees.iter().find(|e| e.empid == empid).and_then(|e| e.mgrid);
+            while let Some(manager) = current.filter(|m| !chain.contains(m)) {
+                chain.push(manager);
+                current = self.data.employees.iter().find(|e| e.empid == manager).and_then(|e| e.mgrid);
+            }
+            chain
+        };
+
+        let orders: HashMap<i32, &Order> = self.data.orders.iter()
+            .filter(|o| self.matches(o, filter))
+            .map(|o| (o.orderid, o))
+            .collect();
+
+        let mut own = BTreeMap::<(i32, String), PeriodTotals>::new();
+        let mut team = BTreeMap::<(i32, String), PeriodTotals>::new();
+        for detail in &self.data.order_details {
+            let Some(order) = orders.get(&detail.orderid) else {
+                continue;
+            };
+            let period = query.period.label(&order.orderdate);
+            let value = detail.unitprice * detail.qty as f64 * (1.0 - detail.discount);
+
+            own.entry((order.empid, period.clone())).or_default().add(order, value, detail.discount);
+            for manager in chain(order.empid) {
+                team.entry((manager, period.clone())).or_default().add(order, value, detail.discount);
+            }
+        }
+
+        Ok(EmployeePerformance::build(employees, period_sales(own), period_sales(team)))
+    }
+
+    async fn get_sales_choropleth(&self, filter: &ReportFilter, query: &ChoroplethQuery) -> Result<Vec<SalesChoropleth>, Error> {
+        let mut totals = BTreeMap::<(i32, i32), (i64, f64, f64)>::new();
+
+        for (order, value) in self.orders_with_value(filter) {
+            let year = order_year(order);
+            if query.year.is_some_and(|y| y != year) {
+                continue;
+            }
+            let entry = totals.entry((order.custid, year)).or_default();
+            entry.0 += 1;
+            entry.1 += order.freight;
+            entry.2 += value;
+        }
+
+        let mut rows = Vec::new();
+        for ((custid, order_year), (order_count, freight, revenue)) in totals {
+            rows.push(CountryCustomerSales {
+                country: self.customer(custid)?.country.clone(),
+                customer_id: custid,
+                order_year,
+                order_count,
+                freight,
+                revenue,
+            });
+        }
+
+        let date_range = filter.from_date.is_some() || filter.to_date.is_some();
+        Ok(SalesChoropleth::aggregate(rows, query, date_range))
+    }
+
+    async fn get_shipper_performance(&self, filter: &ReportFilter, query: &ShipperPerformanceQuery) -> Result<Vec<ShipperPerformance>, Error> {
+        let period = query.period.unwrap_or_default();
+        let mut totals = BTreeMap::<(i32, String, String), ShipmentTotals>::new();
+
+        for (order, order_value) in self.orders_with_value(filter) {
+            let shipper = self.shipper(order.shipperid)?;
+            let key = (shipper.shipperid, order.shipcountry.clone(), period.label(&order.orderdate));
+            let row = totals.entry(key).or_insert_with(|| ShipmentTotals {
+                shipper_id: shipper.shipperid,
+                shipper_name: shipper.companyname.clone(),
+                ship_country: order.shipcountry.clone(),
+                period: period.label(&order.orderdate),
+                order_count: 0,
+                shipped_count: 0,
+                on_time_count: 0,
+                days_late: 0,
+                freight: 0.0,
+                order_value: 0.0,
+            });
+
+            row.order_count += 1;
+            row.freight += order.freight;
+            row.order_value += order_value;
+            if let Some(shipped) = &order.shippeddate {
+                row.shipped_count += 1;
+                if shipped <= &order.requireddate {
+                    row.on_time_count += 1;
+                }
+                row.days_late += days_between(&order.requireddate, shipped)?;
+            }
+        }
+
+        Ok(ShipperPerformance::aggregate(totals.into_values().collect(), query))
+    }
+
+    async fn get_product_sales(&self, filter: &ReportFilter, query: &ProductSalesQuery) -> Result<Vec<ProductSales>, Error> {
+        let mut rows = Vec::new();
+        for ((productid, period), t) in self.product_sales(filter, query)? {
+            let product = self.product(productid)?;
+            rows.push(ProductPeriodSales {
+                product_id: productid,
+                product_name: product.productname.clone(),
+                category_id: product.categoryid,
+                category_name: self.category(product.categoryid)?.categoryname.clone(),
+                list_price: product.unitprice,
+                discontinued: product.discontinued,
+                period,
+                revenue: t.revenue,
+                gross_revenue: t.gross_revenue,
+                list_revenue: t.list_revenue,
+                units: t.units,
+                order_count: t.orders.len() as i64,
+                customer_count: t.customers.len() as i64,
+            });
+        }
+
+        let mut catalogue = Vec::new();
+        if query.bottom.is_some() {
+            for product in self.data.products.iter().filter(|p| query.category_id.is_none_or(|id| id == p.categoryid)) {
+                catalogue.push(ProductInfo {
+                    product_id: product.productid,
+                    product_name: product.productname.clone(),
+                    category_id: product.categoryid,
+                    category_name: self.category(product.categoryid)?.categoryname.clone(),
+                    list_price: product.unitprice,
+                    discontinued: product.discontinued,
+                });
+            }
+        }
+        Ok(ProductSales::rank(rows, catalogue, query))
+    }
+
+    async fn get_category_sales(&self, filter: &ReportFilter, query: &ProductSalesQuery) -> Result<Vec<CategorySales>, Error> {
+        let mut categories = BTreeMap::<(i32, String), (ProductTotals, BTreeSet<i32>)>::new();
+        for ((productid, period), t) in self.product_sales(filter, query)? {
+            let product = self.product(productid)?;
+            let (totals, products) = categories.entry((product.categoryid, period)).or_default();
+            totals.revenue += t.revenue;
+            totals.gross_revenue += t.gross_revenue;
+            totals.list_revenue += t.list_revenue;
+            totals.units += t.units;
+            totals.orders.extend(t.orders);
+            totals.customers.extend(t.customers);
+            products.insert(productid);
+        }
+
+        let mut rows = Vec::new();
+        for ((categoryid, period), (t, products)) in categories {
+            rows.push(CategoryPeriodSales {
+                category_id: categoryid,
+                category_name: self.category(categoryid)?.categoryname.clone(),
+                period,
+                product_count: products.len() as i64,
+                revenue: t.revenue,
+                gross_revenue: t.gross_revenue,
+                list_revenue: t.list_revenue,
+                units: t.units,
+                order_count: t.orders.len() as i64,
+                customer_count: t.customers.len() as i64,
+            });
+        }
+
+        Ok(CategorySales::rank(rows, query))
+    }
+
+    async fn get_sales_timeseries(&self, filter: &ReportFilter, query: &TimeSeriesQuery) -> Result<Vec<TimeSeriesPoint>, Error> {
+        let history = TimeSeriesPoint::history_filter(filter, query);
+
+        let mut days = BTreeMap::<NaiveDate, DailySales>::new();
+        for (order, order_value) in self.orders_with_value(&history) {
+            let order_date = NaiveDate::parse_from_str(&order.orderdate, "%Y-%m-%d")?;
+            let day = days.entry(order_date).or_insert_with(|| DailySales { order_date, order_count: 0, freight: 0.0, revenue: 0.0 });
+            day.order_count += 1;
+            day.freight += order.freight;
+            day.revenue += order_value;
+        }
+
+        Ok(TimeSeriesPoint::build(days.into_values().collect(), filter, query))
+    }
+
+    async fn get_rfm_segments(&self, filter: &ReportFilter, query: &RfmQuery) -> Result<Option<RfmReport>, Error> {
+        let mut totals = BTreeMap::<i32, CustomerOrderTotals>::new();
+        for (order, order_value) in self.orders_with_value(&query.order_filter(filter)) {
+            let customer = self.customer(order.custid)?;
+            let order_date = NaiveDate::parse_from_str(&order.orderdate, "%Y-%m-%d")?;
+            let row = totals.entry(order.custid).or_insert_with(|| CustomerOrderTotals {
+                customer_id: customer.custid,
+                customer_name: customer.companyname.clone(),
+                country: countries::display_name(customer.country.clone()),
+                last_order_date: order_date,
+                order_count: 0,
+                monetary: 0.0,
+            });
+            row.last_order_date = row.last_order_date.max(order_date);
+            row.order_count += 1;
+            row.monetary += order_value;
+        }
+
+        Ok(RfmReport::build(totals.into_values().collect(), filter, query))
+    }
+
+    async fn get_cohort_retention(&self, filter: &ReportFilter, query: &CohortQuery) -> Result<Vec<CohortRetention>, Error> {
+        let mut rows = Vec::new();
+        for (order, order_value) in self.orders_with_value(&query.order_filter(filter)) {
+            rows.push(CustomerOrder {
+                customer_id: order.custid,
+                order_date: NaiveDate::parse_from_str(&order.orderdate, "%Y-%m-%d")?,
+                order_value,
+            });
+        }
+
+        Ok(CohortRetention::build(rows, filter, query))
+    }
+
+    async fn get_sales_forecast(&self, filter: &ReportFilter, query: &SalesForecastQuery) -> Result<Vec<SalesForecast>, Error> {
+        let orders: HashMap<i32, &Order> = self.data.orders.iter()
+            .filter(|o| self.matches(o, filter))
+            .map(|o| (o.orderid, o))
+            .collect();
+
+        let mut totals = BTreeMap::<(String, String), f64>::new();
+        for detail in &self.data.order_details {
+            let Some(order) = orders.get(&detail.orderid) else {
+                continue;
+            };
+            let series = match query.by {
+                ForecastGrouping::Total => "Total".to_string(),
+                ForecastGrouping::Country => self.customer(order.custid)?.country.clone(),
+                ForecastGrouping::Category => self.category(self.product(detail.productid)?.categoryid)?.categoryname.clone(),
+            };
+            let period = ReportPeriod::Month.label(&order.orderdate);
+            *totals.entry((series, period)).or_default() += detail.unitprice * detail.qty as f64 * (1.0 - detail.discount);
+        }
+
+        let rows = totals.into_iter()
+            .map(|((series, period), revenue)| MonthlyRevenue { series, period, revenue })
+            .collect();
+        Ok(SalesForecast::build(rows, filter, query))
+    }
+
+    async fn get_abc_analysis(&self, filter: &ReportFilter, query: &AbcQuery) -> Result<Option<AbcReport>, Error> {
+        let orders: HashMap<i32, &Order> = self.data.orders.iter()
+            .filter(|o| self.matches(o, filter))
+            .map(|o| (o.orderid, o))
+            .collect();
+
+        let mut totals = BTreeMap::<i32, ItemRevenue>::new();
+        for detail in &self.data.order_details {
+            let Some(order) = orders.get(&detail.orderid) else {
+                continue;
+            };
+            let (id, name) = match query.dimension {
+                AbcDimension::Customer => (order.custid, &self.customer(order.custid)?.companyname),
+                AbcDimension::Product => (detail.productid, &self.product(detail.productid)?.productname),
+            };
+            let item = totals.entry(id).or_insert_with(|| ItemRevenue { id, name: name.clone(), revenue: 0.0 });
+            item.revenue += detail.unitprice * detail.qty as f64 * (1.0 - detail.discount);
+        }
+
+        Ok(AbcReport::build(totals.into_values().collect(), query))
+    }
+
+    async fn get_market_basket(&self, filter: &ReportFilter, query: &MarketBasketQuery) -> Result<Vec<ProductPair>, Error> {
+        Ok(ProductPair::build(self.order_products(filter)?, query))
+    }
+
+    async fn get_bought_with(&self, product_id: i32, filter: &ReportFilter, query: &MarketBasketQuery) -> Result<Option<BoughtWithReport>, Error> {
+        Ok(BoughtWithReport::build(self.order_products(filter)?, product_id, query))
+    }
+}
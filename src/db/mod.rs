@@ -1 +1,138 @@
-pub mod database;
\ No newline at end of file
+pub mod database;
+pub mod fixtures;
+pub mod grid;
+pub mod memory;
+pub mod pool;
+pub mod postgres;
+pub mod repository;
+pub mod retry;
+pub mod row;
+pub mod sqlite;
+pub mod stream;
+pub mod timeout;
+
+use anyhow::{Error, Result};
+use chrono::NaiveDate;
+use sqlx::query::Query;
+use std::env;
+use std::sync::Arc;
+
+use crate::db::database::DatabaseMSSQL;
+use crate::db::memory::DatabaseMemory;
+use crate::db::postgres::DatabasePostgres;
+use crate::db::repository::ReportRepository;
+use crate::db::sqlite::DatabaseSQLite;
+use crate::db::fixtures::FixtureData;
+use crate::models::reportfilter::ReportFilter;
+
+//-------- SELECTING THE BACKEND --------------//
+// DATABASE_BACKEND picks the implementation behind the API. Without it the scheme of
+// CONNECTION_STRING decides: postgres:// URLs go to Postgres, anything else to SQL Server.
+pub async fn init_repository() -> Result<Arc<dyn ReportRepository>, Error> {
+    dotenv::dotenv().ok();
+
+    let conn_str = env::var("CONNECTION_STRING").unwrap_or_default();
+    let backend = env::var("DATABASE_BACKEND").unwrap_or_else(|_| backend_for_connection_string(&conn_str).to_string());
+
+    match backend.to_lowercase().as_str() {
+        "mssql" => Ok(Arc::new(DatabaseMSSQL::init().await?)),
+        "postgres" => Ok(Arc::new(DatabasePostgres::init(&conn_str).await?)),
+        "memory" => Ok(Arc::new(DatabaseMemory::init(&fixture_path())?)),
+        "sqlite" => {
+            let directory = env::var("SQLITE_DIR").unwrap_or_else(|_| "data".to_string());
+            let db = DatabaseSQLite::init(&directory).await?;
+
+            if env::var("SQLITE_SEED_FIXTURES").is_ok_and(|v| v == "true" || v == "1") {
+                db.seed(&FixtureData::load(&fixture_path())?).await?;
+            }
+            Ok(Arc::new(db))
+        }
+        other => Err(Error::msg(format!("Unknown DATABASE_BACKEND: {}", other))),
+    }
+}
+
+fn fixture_path() -> String {
+    env::var("FIXTURE_PATH").unwrap_or_else(|_| "fixtures/sales.json".to_string())
+}
+
+fn backend_for_connection_string(conn_str: &str) -> &'static str {
+    let scheme = conn_str.split("://").next().unwrap_or_default().to_lowercase();
+    match scheme.as_str() {
+        "postgres" | "postgresql" => "postgres",
+        _ => "mssql",
+    }
+}
+
+//-------- FILTER PARAMETERS --------------//
+// Binds the ReportFilter as parameters 1 to 6 of every sqlx backend's ORDER_FILTER: from_date,
+// to_date, country, customer_id, employee_id and shipper_id. SQL Server binds the same six in
+// the same order through database::filter_params.
+pub(crate) fn bind_filter<'q, DB>(query: Query<'q, DB, DB::Arguments<'q>>, filter: &ReportFilter) -> Query<'q, DB, DB::Arguments<'q>>
+where
+    DB: sqlx::Database,
+    Option<NaiveDate>: sqlx::Encode<'q, DB> + sqlx::Type<DB>,
+    Option<String>: sqlx::Encode<'q, DB> + sqlx::Type<DB>,
+    Option<i32>: sqlx::Encode<'q, DB> + sqlx::Type<DB>,
+{
+    query
+        .bind(filter.from_date)
+        .bind(filter.to_date)
+        .bind(filter.country.clone())
+        .bind(filter.customer_id)
+        .bind(filter.employee_id)
+        .bind(filter.shipper_id)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use sqlx::sqlite::SqlitePoolOptions;
+    use sqlx::Row;
+
+    // The column each ORDER_FILTER parameter is compared with, in binding order
+    const FILTER_COLUMNS: [&str; 6] = ["o.orderdate >=", "o.orderdate <=", "c.country =", "o.custid =", "o.empid =", "o.shipperid ="];
+
+    pub(crate) fn every_filter() -> ReportFilter {
+        ReportFilter {
+            from_date: NaiveDate::from_ymd_opt(2022, 1, 1),
+            to_date: NaiveDate::from_ymd_opt(2022, 12, 31),
+            country: Some("Germany".to_string()),
+            customer_id: Some(5),
+            employee_id: Some(7),
+            shipper_id: Some(3),
+        }
+    }
+
+    // Every clause of a backend's ORDER_FILTER uses the placeholder of its position.
+    pub(crate) fn assert_order_filter(order_filter: &str, placeholder: impl Fn(usize) -> String) {
+        let clauses: Vec<&str> = order_filter.split("AND").map(str::trim).collect();
+        assert_eq!(clauses.len(), FILTER_COLUMNS.len(), "{}", order_filter);
+        for (i, (clause, column)) in clauses.iter().zip(FILTER_COLUMNS).enumerate() {
+            let p = placeholder(i + 1);
+            assert_eq!(*clause, format!("({p} IS NULL OR {column} {p})"));
+        }
+    }
+
+    #[tokio::test]
+    async fn filter_binds_in_placeholder_order() {
+        let pool = SqlitePoolOptions::new().connect("sqlite::memory:").await.unwrap();
+        let row = bind_filter(sqlx::query("SELECT ?1 AS p1, ?2 AS p2, ?3 AS p3, ?4 AS p4, ?5 AS p5, ?6 AS p6"), &every_filter())
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+
+        let dates: (String, String) = (row.get("p1"), row.get("p2"));
+        assert_eq!(dates, ("2022-01-01".to_string(), "2022-12-31".to_string()));
+        assert_eq!(row.get::<String, _>("p3"), "Germany");
+        let ids: (i32, i32, i32) = (row.get("p4"), row.get("p5"), row.get("p6"));
+        assert_eq!(ids, (5, 7, 3));
+        pool.close().await;
+    }
+
+    #[test]
+    fn every_backend_filters_the_same_columns() {
+        assert_order_filter(sqlite::ORDER_FILTER, |i| format!("?{i}"));
+        assert_order_filter(postgres::ORDER_FILTER, |i| format!("${i}"));
+        assert_order_filter(database::ORDER_FILTER, |i| format!("@P{i}"));
+    }
+}
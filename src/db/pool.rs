@@ -0,0 +1,251 @@
+use anyhow::{Error, Result};
+use futures::TryStreamExt;
+use serde::Serialize;
+use std::env;
+use std::time::Duration;
+use tiberius::{Client, Config, Row, ToSql};
+
+use tokio::net::TcpStream;
+use tokio_util::compat::{Compat, TokioAsyncWriteCompatExt};
+
+use crate::db::row::FromRow;
+use crate::db::stream::{send_row, RowSender};
+
+pub type TdsClient = Client<Compat<TcpStream>>;
+
+//-------- POOLED CONNECTION --------------//
+// A client that remembers whether a request is still running on it. When a report is
+// cancelled halfway (timeout or the HTTP client going away) the rest of the response is
+// still on the wire, so the connection is closed instead of being handed out again.
+// Closing it is also what makes SQL Server abort the running batch.
+pub struct TdsConnection {
+    client: TdsClient,
+    in_flight: bool,
+}
+
+impl TdsConnection {
+    pub async fn query_rows(&mut self, sql: &str, params: &[&dyn ToSql]) -> Result<Vec<Row>, tiberius::error::Error> {
+        self.in_flight = true;
+        let rows = self.client.query(sql, params).await?.into_first_result().await?;
+        self.in_flight = false;
+        Ok(rows)
+    }
+
+    // Maps and sends each row as it comes off the wire instead of collecting the result first.
+    pub async fn send_rows<T: FromRow>(&mut self, sql: &str, params: &[&dyn ToSql], tx: &RowSender<T>) -> Result<(), Error> {
+        self.in_flight = true;
+        let mut rows = self.client.query(sql, params).await?.into_row_stream();
+        while let Some(row) = rows.try_next().await? {
+            if !send_row(tx, T::from_row(&row)?).await {
+                return Ok(());
+            }
+        }
+        drop(rows);
+        self.in_flight = false;
+        Ok(())
+    }
+}
+
+//-------- CONNECTION MANAGER --------------//
+// Opens tiberius clients for the bb8 pool and checks them before they are handed out again.
+pub struct TiberiusConnectionManager {
+    config: Config,
+}
+
+impl TiberiusConnectionManager {
+    pub fn new(config: Config) -> Self {
+        TiberiusConnectionManager { config }
+    }
+}
+
+impl bb8::ManageConnection for TiberiusConnectionManager {
+    type Connection = TdsConnection;
+    type Error = tiberius::error::Error;
+
+    async fn connect(&self) -> Result<Self::Connection, Self::Error> {
+        // Tokio's TcpStream is used to create a connection
+        let tcp = TcpStream::connect(self.config.get_addr()).await?;
+        tcp.set_nodelay(true)?;
+
+        // Make the TcpStream compatible with Tiberius with compat
+        let client = Client::connect(self.config.clone(), tcp.compat_write()).await?;
+        Ok(TdsConnection { client, in_flight: false })
+    }
+
+    async fn is_valid(&self, conn: &mut Self::Connection) -> Result<(), Self::Error> {
+        conn.client.simple_query("SELECT 1").await?.into_row().await?;
+        Ok(())
+    }
+
+    // A query that never finished (dropped future or error halfway) leaves the connection unusable
+    fn has_broken(&self, conn: &mut Self::Connection) -> bool {
+        conn.in_flight
+    }
+}
+
+//-------- POOL CONFIGURATION --------------//
+#[derive(Debug, Clone)]
+pub struct PoolConfig {
+    pub max_size: u32,
+    pub min_idle: u32,
+    pub checkout_timeout: Duration,
+    pub idle_timeout: Duration,
+}
+
+impl Default for PoolConfig {
+    fn default() -> Self {
+        PoolConfig {
+            max_size: 10,
+            min_idle: 2,
+            checkout_timeout: Duration::from_secs(30),
+            idle_timeout: Duration::from_secs(600),
+        }
+    }
+}
+
+impl PoolConfig {
+    // Every setting is optional, missing variables fall back to the defaults above.
+    pub fn from_env() -> Result<Self, Error> {
+        let default = PoolConfig::default();
+
+        let config = PoolConfig {
+            max_size: env_or("POOL_MAX_SIZE", default.max_size)?,
+            min_idle: env_or("POOL_MIN_IDLE", default.min_idle)?,
+            checkout_timeout: Duration::from_secs(env_or("POOL_CHECKOUT_TIMEOUT_SECS", default.checkout_timeout.as_secs())?),
+            idle_timeout: Duration::from_secs(env_or("POOL_IDLE_TIMEOUT_SECS", default.idle_timeout.as_secs())?),
+        };
+
+        if config.max_size == 0 || config.min_idle > config.max_size {
+            return Err(Error::msg("POOL_MIN_IDLE must not exceed POOL_MAX_SIZE and POOL_MAX_SIZE must be positive"));
+        }
+
+        Ok(config)
+    }
+
+    pub async fn build(&self, config: Config) -> Result<bb8::Pool<TiberiusConnectionManager>, Error> {
+        let pool = bb8::Pool::builder()
+            .max_size(self.max_size)
+            .min_idle(self.min_idle)
+            .connection_timeout(self.checkout_timeout)
+            .idle_timeout(self.idle_timeout)
+            .test_on_check_out(true)
+            .build(TiberiusConnectionManager::new(config))
+            .await?;
+
+        Ok(pool)
+    }
+}
+
+pub(crate) fn env_or<T: std::str::FromStr>(key: &str, default: T) -> Result<T, Error> {
+    match env::var(key) {
+        Ok(value) => value
+            .parse()
+            .map_err(|_| Error::msg(format!("{} has an invalid value: {}", key, value))),
+        Err(_) => Ok(default),
+    }
+}
+
+//-------- POOL STATISTICS --------------//
+#[derive(Serialize, Debug)]
+pub struct PoolStats {
+    pub max_size: u32,
+    pub connections: u32,
+    pub idle_connections: u32,
+    // Checkout and connection counters, only bb8 keeps them
+    #[serde(flatten)]
+    pub counters: Option<PoolCounters>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct PoolCounters {
+    pub checkouts_direct: u64,
+    pub checkouts_waited: u64,
+    pub checkouts_timed_out: u64,
+    pub total_wait_ms: u128,
+    pub connections_created: u64,
+    pub connections_closed_broken: u64,
+    pub connections_closed_invalid: u64,
+    pub connections_closed_idle_timeout: u64,
+}
+
+impl PoolStats {
+    pub fn from_pool(pool: &bb8::Pool<TiberiusConnectionManager>, max_size: u32) -> Self {
+        let state = pool.state();
+        let statistics = state.statistics;
+
+        PoolStats {
+            max_size,
+            connections: state.connections,
+            idle_connections: state.idle_connections,
+            counters: Some(PoolCounters {
+                checkouts_direct: statistics.get_direct,
+                checkouts_waited: statistics.get_waited,
+                checkouts_timed_out: statistics.get_timed_out,
+                total_wait_ms: statistics.get_wait_time.as_millis(),
+                connections_created: statistics.connections_created,
+                connections_closed_broken: statistics.connections_closed_broken,
+                connections_closed_invalid: statistics.connections_closed_invalid,
+                connections_closed_idle_timeout: statistics.connections_closed_idle_timeout,
+            }),
+        }
+    }
+
+    // sqlx pools only report their size and idle connections.
+    pub fn from_sqlx_pool<DB: sqlx::Database>(pool: &sqlx::Pool<DB>) -> Self {
+        PoolStats {
+            max_size: pool.options().get_max_connections(),
+            connections: pool.size(),
+            idle_connections: pool.num_idle() as u32,
+            counters: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn env_or_parses_or_falls_back() {
+        env::remove_var("POOL_TEST_MISSING");
+        assert_eq!(env_or("POOL_TEST_MISSING", 7u32).unwrap(), 7);
+
+        env::set_var("POOL_TEST_NUMBER", "12");
+        assert_eq!(env_or("POOL_TEST_NUMBER", 7u32).unwrap(), 12);
+
+        for invalid in ["ten", "-1", "", "4.5"] {
+            env::set_var("POOL_TEST_INVALID", invalid);
+            let error = env_or("POOL_TEST_INVALID", 7u32).unwrap_err();
+            assert_eq!(error.to_string(), format!("POOL_TEST_INVALID has an invalid value: {}", invalid));
+        }
+    }
+
+    // The only test that sets the POOL_* variables, so it cannot race with another one
+    #[test]
+    fn pool_config_from_env() {
+        let keys = ["POOL_MAX_SIZE", "POOL_MIN_IDLE", "POOL_CHECKOUT_TIMEOUT_SECS", "POOL_IDLE_TIMEOUT_SECS"];
+        let set = |values: [Option<&str>; 4]| {
+            for (key, value) in keys.iter().zip(values) {
+                match value {
+                    Some(value) => env::set_var(key, value),
+                    None => env::remove_var(key),
+                }
+            }
+            PoolConfig::from_env()
+        };
+
+        let config = set([None; 4]).unwrap();
+        assert_eq!((config.max_size, config.min_idle), (10, 2));
+        assert_eq!((config.checkout_timeout, config.idle_timeout), (Duration::from_secs(30), Duration::from_secs(600)));
+
+        let config = set([Some("4"), Some("4"), Some("5"), Some("60")]).unwrap();
+        assert_eq!((config.max_size, config.min_idle), (4, 4));
+        assert_eq!((config.checkout_timeout, config.idle_timeout), (Duration::from_secs(5), Duration::from_secs(60)));
+
+        assert!(set([Some("0"), Some("0"), None, None]).is_err());
+        assert!(set([Some("4"), Some("5"), None, None]).is_err());
+        assert!(set([None, None, Some("soon"), None]).is_err());
+        assert!(set([None, None, None, Some("-60")]).is_err());
+        set([None; 4]).unwrap();
+    }
+}
@@ -0,0 +1,673 @@
+use anyhow::{Error, Result};
+use async_trait::async_trait;
+use futures::TryStreamExt;
+use sqlx::query::Query;
+use sqlx::postgres::{PgArguments, PgPool, PgPoolOptions, Postgres};
+
+use crate::db::bind_filter;
+use crate::db::pool::{PoolConfig, PoolStats};
+use crate::db::repository::ReportRepository;
+use crate::db::grid::{Dialect, GridQuery, SqlParam};
+use crate::db::row::{get_column, map_rows, FromRow};
+use crate::db::stream::{send_row, spawn_row_stream, RowStream};
+
+use crate::models::gridrequest::Page;
+use crate::models::orderdetail::{OrderDetail, OrderHeader};
+use crate::models::ordersreport::OrdersReport;
+use crate::models::customerbyyear::{CustomerByYear, CustomerYearSales};
+use crate::models::customerprofile::{CustomerInfo, CustomerProfile, CustomerProfileQuery};
+use crate::models::employeeperformance::{EmployeePerformance, EmployeePerformanceQuery};
+use crate::models::topperformers::{CustomerEmployeeSales, TopPerformers, TopPerformersQuery};
+use crate::models::saleschoropleth::{ChoroplethQuery, CountryCustomerSales, SalesChoropleth};
+use crate::models::shipperperformance::{ShipmentTotals, ShipperPerformance, ShipperPerformanceQuery};
+use crate::models::productsales::{CategoryPeriodSales, CategorySales, ProductInfo, ProductPeriodSales, ProductSales, ProductSalesQuery};
+use crate::models::salestimeseries::{DailySales, TimeSeriesPoint, TimeSeriesQuery};
+use crate::models::rfmsegmentation::{CustomerOrderTotals, RfmQuery, RfmReport};
+use crate::models::cohortretention::{CohortQuery, CohortRetention, CustomerOrder};
+use crate::models::salesforecast::{ForecastGrouping, MonthlyRevenue, SalesForecast, SalesForecastQuery};
+use crate::models::abcanalysis::{AbcDimension, AbcQuery, AbcReport, ItemRevenue};
+use crate::models::marketbasket::{BoughtWithReport, MarketBasketQuery, OrderProduct, ProductPair};
+use crate::models::reportfilter::{ReportFilter, ReportPeriod, YearRange};
+
+// Expects the Sales, HR and Production schemas with the same tables and columns as SQL Server.
+// Unquoted identifiers fold to lower case, so Sales.Orders resolves to sales.orders.
+#[derive(Clone)]
+pub struct DatabasePostgres {
+    pub pool: PgPool,
+}
+
+impl DatabasePostgres {
+
+    //-------- INITIALIZING DATABASE --------------//
+    pub async fn init(conn_str: &str) -> Result<Self, Error> {
+        let pool_config = PoolConfig::from_env()?;
+
+        let pool = PgPoolOptions::new()
+            .max_connections(pool_config.max_size)
+            .min_connections(pool_config.min_idle)
+            .acquire_timeout(pool_config.checkout_timeout)
+            .idle_timeout(pool_config.idle_timeout)
+            .test_before_acquire(true)
+            .connect(conn_str)
+            .await?;
+
+        Ok(DatabasePostgres{pool})
+    }
+}
+
+// Restricts the orders behind a report to the ReportFilter values, bound as $1..$6 by
+// bind_filter. Queries using it need the o (Sales.Orders) and c (Sales.Customers) aliases.
+pub(crate) const ORDER_FILTER: &str = "($1 IS NULL OR o.orderdate >= $1)
+                    AND ($2 IS NULL OR o.orderdate <= $2)
+                    AND ($3 IS NULL OR c.country = $3)
+                    AND ($4 IS NULL OR o.custid = $4)
+                    AND ($5 IS NULL OR o.empid = $5)
+                    AND ($6 IS NULL OR o.shipperid = $6)";
+
+fn bind_grid<'q>(query: Query<'q, Postgres, PgArguments>, params: &[SqlParam]) -> Query<'q, Postgres, PgArguments> {
+    params.iter().fold(query, |query, param| match param {
+        SqlParam::Text(text) => query.bind(text.clone()),
+        SqlParam::Number(number) => query.bind(*number),
+        SqlParam::Int(int) => query.bind(*int),
+    })
+}
+
+// Period label of an order, "2023", "2023-Q2" or "2023-05" like ReportPeriod::label.
+fn period_sql(period: ReportPeriod) -> &'static str {
+    match period {
+        ReportPeriod::Year => "TO_CHAR(o.orderdate, 'YYYY')",
+        ReportPeriod::Quarter => "TO_CHAR(o.orderdate, 'YYYY-\"Q\"Q')",
+        ReportPeriod::Month => "TO_CHAR(o.orderdate, 'YYYY-MM')",
+    }
+}
+
+// The orders report, one row per order filtered by ORDER_FILTER. Streamed as is, or wrapped
+// by the grid clauses for a single page.
+fn orders_report_sql() -> String {
+    format!("
+                SELECT o.orderid as order_id
+                    , c.companyname as customer_name
+                    , c.contactname as customer_contact
+                    , c.country as customer_country
+                    , e.lastname || ', ' || e.firstname as employee_name
+                    , e.title as employee_title
+                    , sh.companyname as shipper_name
+                    , o.shipname as ship_name
+                    , TO_CHAR(o.orderdate, 'YYYY-MM-DD') as order_date
+                    , TO_CHAR(o.requireddate, 'YYYY-MM-DD') as delivery_date
+                    , CAST(MAX(o.freight) as double precision) as freight_value
+                    , CAST(SUM(od.unitprice*od.qty*(1-od.discount)) as double precision) as order_value
+                    , CAST(MAX(o.freight) + SUM(od.unitprice*od.qty*(1-od.discount)) as double precision) as billable_value
+                FROM Sales.Orders as o
+                JOIN Sales.Customers as c on o.custid = c.custid
+                JOIN HR.Employees as e on o.empid = e.empid
+                JOIN Sales.Shippers as sh on o.shipperid = sh.shipperid
+                JOIN Sales.OrderDetails as od on o.orderid = od.orderid
+                JOIN Production.Products as p on od.productid = p.productid
+                WHERE {ORDER_FILTER}
+                GROUP BY o.orderid
+                    , c.companyname
+                    , c.contactname
+                    , c.country
+                    , e.lastname || ', ' || e.firstname
+                    , e.title
+                    , sh.companyname
+                    , o.shipname
+                    , o.orderdate
+                    , o.requireddate")
+}
+
+// One row per product of each order that passes ORDER_FILTER, the baskets behind the
+// market basket reports.
+fn order_products_sql() -> String {
+    format!("
+                SELECT DISTINCT od.orderid AS order_id
+                    , od.productid AS product_id
+                    , p.productname AS product_name
+                FROM Sales.Orders AS o
+                JOIN Sales.OrderDetails AS od ON o.orderid = od.orderid
+                JOIN Production.Products AS p ON od.productid = p.productid
+                JOIN Sales.Customers AS c ON o.custid = c.custid
+                WHERE {ORDER_FILTER}")
+}
+
+#[async_trait]
+impl ReportRepository for DatabasePostgres {
+
+    fn stream_orders_report(&self, filter: &ReportFilter) -> RowStream<OrdersReport> {
+        let pool = self.pool.clone();
+        let filter = filter.clone();
+        spawn_row_stream(move |tx| async move {
+            let sql = orders_report_sql();
+            let mut rows = bind_filter(sqlx::query(&sql), &filter).fetch(&pool);
+            while let Some(row) = rows.try_next().await? {
+                if !send_row(&tx, OrdersReport::from_row(&row)?).await {
+                    break;
+                }
+            }
+            Ok(())
+        })
+    }
+
+    async fn get_orders_report_page(&self, filter: &ReportFilter, query: &GridQuery) -> Result<Page<OrdersReport>, Error> {
+        // The grid's parameters follow the six filter parameters
+        let grid = query.to_sql(Dialect::Postgres, 7);
+        let report_sql = orders_report_sql();
+
+        let count_sql = grid.count(&report_sql);
+        let count = bind_grid(bind_filter(sqlx::query(&count_sql), filter), &grid.params)
+            .fetch_one(&self.pool)
+            .await?;
+
+        let select_sql = grid.select(&report_sql);
+        let rows = bind_grid(bind_grid(bind_filter(sqlx::query(&select_sql), filter), &grid.params), &grid.page_params)
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(Page { rows: map_rows(&rows)?, total: get_column(&count, "total")? })
+    }
+
+    async fn get_order_detail(&self, order_id: i32) -> Result<Option<OrderDetail>, Error> {
+        let header = sqlx::query("
+            SELECT o.orderid AS order_id
+                , c.custid AS customer_id
+                , c.companyname AS customer_name
+                , c.contactname AS customer_contact
+                , c.country AS customer_country
+                , e.empid AS employee_id
+                , e.lastname || ', ' || e.firstname AS employee_name
+                , e.title AS employee_title
+                , sh.shipperid AS shipper_id
+                , sh.companyname AS shipper_name
+                , o.shipname AS ship_name
+                , o.shipcity AS ship_city
+                , o.shipcountry AS ship_country
+                , TO_CHAR(o.orderdate, 'YYYY-MM-DD') AS order_date
+                , TO_CHAR(o.requireddate, 'YYYY-MM-DD') AS required_date
+                , TO_CHAR(o.shippeddate, 'YYYY-MM-DD') AS shipped_date
+                , CAST(o.freight AS double precision) AS freight
+            FROM Sales.Orders AS o
+            JOIN Sales.Customers AS c ON o.custid = c.custid
+            JOIN HR.Employees AS e ON o.empid = e.empid
+            JOIN Sales.Shippers AS sh ON o.shipperid = sh.shipperid
+            WHERE o.orderid = $1")
+            .bind(order_id)
+            .fetch_optional(&self.pool)
+            .await?;
+        let Some(header) = header else {
+            return Ok(None);
+        };
+        let header = OrderHeader::from_row(&header)?;
+
+        let lines = sqlx::query("
+            SELECT p.productid AS product_id
+                , p.productname AS product_name
+                , CAST(od.unitprice AS double precision) AS unit_price
+                , CAST(od.qty AS integer) AS quantity
+                , CAST(od.discount AS double precision) AS discount
+                , CAST(od.unitprice * od.qty * (1 - od.discount) AS double precision) AS line_total
+            FROM Sales.OrderDetails AS od
+            JOIN Production.Products AS p ON od.productid = p.productid
+            WHERE od.orderid = $1
+            ORDER BY p.productid")
+            .bind(order_id)
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(Some(OrderDetail::new(header, map_rows(&lines)?)))
+    }
+
+    async fn get_customer_sales_by_year(&self, filter: &ReportFilter, years: &YearRange) -> Result<Vec<CustomerByYear>, Error> {
+        let rows = bind_filter(sqlx::query(&format!(
+            "SELECT c.companyname as customer_name
+                    , CAST(EXTRACT(YEAR FROM o.orderdate) as integer) as order_year
+                    , CAST(SUM(od.unitprice * od.qty * (1-od.discount)) as double precision) as sales
+                FROM Sales.Orders as o
+                JOIN Sales.OrderDetails as od on o.orderid = od.orderid
+                JOIN Sales.Customers as c on o.custid = c.custid
+                WHERE {ORDER_FILTER}
+                    AND ($7 IS NULL OR EXTRACT(YEAR FROM o.orderdate) >= $7)
+                    AND ($8 IS NULL OR EXTRACT(YEAR FROM o.orderdate) <= $8)
+                GROUP BY c.companyname, EXTRACT(YEAR FROM o.orderdate)")), filter)
+            .bind(years.from_year)
+            .bind(years.to_year)
+            .fetch_all(&self.pool)
+            .await?;
+
+        let rows: Vec<CustomerYearSales> = map_rows(&rows)?;
+        Ok(CustomerByYear::pivot(rows, years))
+    }
+
+    async fn get_customer_profile(&self, customer_id: i32, filter: &ReportFilter, query: &CustomerProfileQuery) -> Result<Option<CustomerProfile>, Error> {
+        let customer = sqlx::query("
+            SELECT custid AS customer_id
+                , companyname AS customer_name
+                , contactname AS contact_name
+                , contacttitle AS contact_title
+                , city
+                , country
+                , phone
+            FROM Sales.Customers
+            WHERE custid = $1")
+            .bind(customer_id)
+            .fetch_optional(&self.pool)
+            .await?;
+        let Some(customer) = customer else {
+            return Ok(None);
+        };
+        let customer = CustomerInfo::from_row(&customer)?;
+
+        // Totals, trend and employees come from one row per order, products from one row per product
+        let orders = bind_filter(sqlx::query(&format!(
+            "SELECT TO_CHAR(o.orderdate, 'YYYY-MM-DD') AS order_date
+                    , e.empid AS employee_id
+                    , e.lastname || ', ' || e.firstname AS employee_name
+                    , CAST(SUM(od.unitprice * od.qty * (1 - od.discount)) AS double precision) AS order_value
+                FROM Sales.Orders AS o
+                JOIN Sales.OrderDetails AS od ON o.orderid = od.orderid
+                JOIN Sales.Customers AS c ON o.custid = c.custid
+                JOIN HR.Employees AS e ON o.empid = e.empid
+                WHERE {ORDER_FILTER}
+                    AND o.custid = $7
+                GROUP BY o.orderid, o.orderdate, e.empid, e.lastname, e.firstname")), filter)
+            .bind(customer_id)
+            .fetch_all(&self.pool)
+            .await?;
+
+        let products = bind_filter(sqlx::query(&format!(
+            "SELECT p.productid AS product_id
+                    , p.productname AS product_name
+                    , COUNT(DISTINCT o.orderid) AS order_count
+                    , CAST(SUM(od.qty) AS bigint) AS quantity
+                    , CAST(SUM(od.unitprice * od.qty * (1 - od.discount)) AS double precision) AS revenue
+                FROM Sales.Orders AS o
+                JOIN Sales.OrderDetails AS od ON o.orderid = od.orderid
+                JOIN Production.Products AS p ON od.productid = p.productid
+                JOIN Sales.Customers AS c ON o.custid = c.custid
+                WHERE {ORDER_FILTER}
+                    AND o.custid = $7
+                GROUP BY p.productid, p.productname")), filter)
+            .bind(customer_id)
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(Some(CustomerProfile::build(customer, map_rows(&orders)?, map_rows(&products)?, query)))
+    }
+
+    async fn get_top_performers(&self, filter: &ReportFilter, query: &TopPerformersQuery) -> Result<Vec<TopPerformers>, Error> {
+        let rows = bind_filter(sqlx::query(&format!(
+            "SELECT c.custid AS customer_id
+                    , c.companyname AS customer_name
+                    , e.lastname || ', ' || e.firstname AS employee_name
+                    , CAST(EXTRACT(YEAR FROM o.orderdate) AS integer) AS order_year
+                    , COUNT(DISTINCT o.orderid) AS order_count
+                    , CAST(SUM(od.qty) AS bigint) AS quantity
+                    , CAST(SUM(od.unitprice * od.qty * (1 - od.discount)) AS double precision) AS sales
+                FROM Sales.Orders AS o
+                JOIN Sales.OrderDetails AS od ON o.orderid = od.orderid
+                JOIN Sales.Customers AS c ON o.custid = c.custid
+                JOIN HR.Employees AS e ON o.empid = e.empid
+                WHERE {ORDER_FILTER}
+                    AND ($7 IS NULL OR EXTRACT(YEAR FROM o.orderdate) = $7)
+                GROUP BY c.custid, c.companyname, e.empid, e.lastname, e.firstname, EXTRACT(YEAR FROM o.orderdate)")), filter)
+            .bind(query.year)
+            .fetch_all(&self.pool)
+            .await?;
+
+        let rows: Vec<CustomerEmployeeSales> = map_rows(&rows)?;
+        Ok(TopPerformers::rank(rows, query))
+    }
+
+    async fn get_employee_performance(&self, filter: &ReportFilter, query: &EmployeePerformanceQuery) -> Result<Vec<EmployeePerformance>, Error> {
+        let period = period_sql(query.period);
+
+        let employees = sqlx::query("
+            SELECT empid AS employee_id
+                , lastname || ', ' || firstname AS employee_name
+                , title
+                , mgrid AS manager_id
+            FROM HR.Employees")
+            .fetch_all(&self.pool)
+            .await?;
+
+        let own = bind_filter(sqlx::query(&format!(
+            "SELECT o.empid AS employee_id
+                    , {period} AS period
+                    , CAST(SUM(od.unitprice * od.qty * (1 - od.discount)) AS double precision) AS revenue
+                    , COUNT(DISTINCT o.orderid) AS order_count
+                    , COUNT(DISTINCT o.custid) AS customers_served
+                    , CAST(AVG(od.discount) AS double precision) AS average_discount
+                FROM Sales.Orders AS o
+                JOIN Sales.OrderDetails AS od ON o.orderid = od.orderid
+                JOIN Sales.Customers AS c ON o.custid = c.custid
+                WHERE {ORDER_FILTER}
+                GROUP BY o.empid, {period}")), filter)
+            .fetch_all(&self.pool)
+            .await?;
+
+        // Every employee is paired with everyone below them, themselves included, and the
+        // team's orders are summed per manager
+        let team = bind_filter(sqlx::query(&format!(
+            "WITH RECURSIVE team AS (
+                    SELECT empid AS manager_id, empid AS member_id
+                    FROM HR.Employees
+                    UNION ALL
+                    SELECT t.manager_id, e.empid
+                    FROM team AS t
+                    JOIN HR.Employees AS e ON e.mgrid = t.member_id
+                )
+                SELECT t.manager_id AS employee_id
+                    , {period} AS period
+                    , CAST(SUM(od.unitprice * od.qty * (1 - od.discount)) AS double precision) AS revenue
+                    , COUNT(DISTINCT o.orderid) AS order_count
+                    , COUNT(DISTINCT o.custid) AS customers_served
+                    , CAST(AVG(od.discount) AS double precision) AS average_discount
+                FROM team AS t
+                JOIN Sales.Orders AS o ON o.empid = t.member_id
+                JOIN Sales.OrderDetails AS od ON o.orderid = od.orderid
+                JOIN Sales.Customers AS c ON o.custid = c.custid
+                WHERE {ORDER_FILTER}
+                GROUP BY t.manager_id, {period}")), filter)
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(EmployeePerformance::build(map_rows(&employees)?, map_rows(&own)?, map_rows(&team)?))
+    }
+
+    async fn get_sales_choropleth(&self, filter: &ReportFilter, query: &ChoroplethQuery) -> Result<Vec<SalesChoropleth>, Error> {
+        let rows = bind_filter(sqlx::query(&format!(
+            "SELECT c.country AS country
+                    , c.custid AS customer_id
+                    , CAST(EXTRACT(YEAR FROM o.orderdate) AS integer) AS order_year
+                    , COUNT(*) AS order_count
+                    , CAST(SUM(o.freight) AS double precision) AS freight
+                    , CAST(SUM(ov.order_value) AS double precision) AS revenue
+                FROM Sales.Orders AS o
+                JOIN (
+                    SELECT orderid, SUM(unitprice * qty * (1 - discount)) AS order_value
+                    FROM Sales.OrderDetails
+                    GROUP BY orderid
+                ) AS ov ON ov.orderid = o.orderid
+                JOIN Sales.Customers AS c ON o.custid = c.custid
+                WHERE {ORDER_FILTER}
+                    AND ($7 IS NULL OR EXTRACT(YEAR FROM o.orderdate) = $7)
+                GROUP BY c.country, c.custid, EXTRACT(YEAR FROM o.orderdate)")), filter)
+            .bind(query.year)
+            .fetch_all(&self.pool)
+            .await?;
+
+        let rows: Vec<CountryCustomerSales> = map_rows(&rows)?;
+        let date_range = filter.from_date.is_some() || filter.to_date.is_some();
+        Ok(SalesChoropleth::aggregate(rows, query, date_range))
+    }
+
+    async fn get_shipper_performance(&self, filter: &ReportFilter, query: &ShipperPerformanceQuery) -> Result<Vec<ShipperPerformance>, Error> {
+        let period = period_sql(query.period.unwrap_or_default());
+
+        let rows = bind_filter(sqlx::query(&format!(
+            "SELECT sh.shipperid AS shipper_id
+                    , sh.companyname AS shipper_name
+                    , o.shipcountry AS ship_country
+                    , {period} AS period
+                    , COUNT(*) AS order_count
+                    , COUNT(o.shippeddate) AS shipped_count
+                    , SUM(CASE WHEN o.shippeddate <= o.requireddate THEN 1 ELSE 0 END) AS on_time_count
+                    , SUM(o.shippeddate - o.requireddate) AS days_late
+                    , CAST(SUM(o.freight) AS double precision) AS freight
+                    , CAST(SUM(ov.order_value) AS double precision) AS order_value
+                FROM Sales.Orders AS o
+                JOIN (
+                    SELECT orderid, SUM(unitprice * qty * (1 - discount)) AS order_value
+                    FROM Sales.OrderDetails
+                    GROUP BY orderid
+                ) AS ov ON ov.orderid = o.orderid
+                JOIN Sales.Shippers AS sh ON o.shipperid = sh.shipperid
+                JOIN Sales.Customers AS c ON o.custid = c.custid
+                WHERE {ORDER_FILTER}
+                GROUP BY sh.shipperid, sh.companyname, o.shipcountry, {period}")), filter)
+            .fetch_all(&self.pool)
+            .await?;
+
+        let rows: Vec<ShipmentTotals> = map_rows(&rows)?;
+        Ok(ShipperPerformance::aggregate(rows, query))
+    }
+
+    async fn get_product_sales(&self, filter: &ReportFilter, query: &ProductSalesQuery) -> Result<Vec<ProductSales>, Error> {
+        // Without a period the rows are not grouped by one, so customers and orders are counted once
+        let (period, group_period) = match query.period {
+            Some(period) => (period_sql(period), format!(", {}", period_sql(period))),
+            None => ("''", String::new()),
+        };
+
+        let rows = bind_filter(sqlx::query(&format!(
+            "SELECT p.productid AS product_id
+                    , p.productname AS product_name
+                    , p.categoryid AS category_id
+                    , cat.categoryname AS category_name
+                    , CAST(p.unitprice AS double precision) AS list_price
+                    , p.discontinued AS discontinued
+                    , {period} AS period
+                    , CAST(SUM(od.unitprice * od.qty * (1 - od.discount)) AS double precision) AS revenue
+                    , CAST(SUM(od.unitprice * od.qty) AS double precision) AS gross_revenue
+                    , CAST(SUM(p.unitprice * od.qty) AS double precision) AS list_revenue
+                    , SUM(od.qty) AS units
+                    , COUNT(DISTINCT o.orderid) AS order_count
+                    , COUNT(DISTINCT o.custid) AS customer_count
+                FROM Sales.Orders AS o
+                JOIN Sales.OrderDetails AS od ON o.orderid = od.orderid
+                JOIN Production.Products AS p ON od.productid = p.productid
+                JOIN Production.Categories AS cat ON p.categoryid = cat.categoryid
+                JOIN Sales.Customers AS c ON o.custid = c.custid
+                WHERE {ORDER_FILTER}
+                    AND ($7 IS NULL OR p.categoryid = $7)
+                GROUP BY p.productid, p.productname, p.categoryid, cat.categoryname, p.unitprice, p.discontinued{group_period}")), filter)
+            .bind(query.category_id)
+            .fetch_all(&self.pool)
+            .await?;
+
+        let rows: Vec<ProductPeriodSales> = map_rows(&rows)?;
+
+        // Products without sales only matter for the bottom of the ranking
+        let catalogue: Vec<ProductInfo> = match query.bottom {
+            Some(_) => {
+                let products = sqlx::query("
+                    SELECT p.productid AS product_id
+                        , p.productname AS product_name
+                        , p.categoryid AS category_id
+                        , cat.categoryname AS category_name
+                        , CAST(p.unitprice AS double precision) AS list_price
+                        , p.discontinued AS discontinued
+                    FROM Production.Products AS p
+                    JOIN Production.Categories AS cat ON p.categoryid = cat.categoryid
+                    WHERE $1 IS NULL OR p.categoryid = $1")
+                    .bind(query.category_id)
+                    .fetch_all(&self.pool)
+                    .await?;
+                map_rows(&products)?
+            }
+            None => Vec::new(),
+        };
+        Ok(ProductSales::rank(rows, catalogue, query))
+    }
+
+    async fn get_category_sales(&self, filter: &ReportFilter, query: &ProductSalesQuery) -> Result<Vec<CategorySales>, Error> {
+        let (period, group_period) = match query.period {
+            Some(period) => (period_sql(period), format!(", {}", period_sql(period))),
+            None => ("''", String::new()),
+        };
+
+        let rows = bind_filter(sqlx::query(&format!(
+            "SELECT cat.categoryid AS category_id
+                    , cat.categoryname AS category_name
+                    , {period} AS period
+                    , COUNT(DISTINCT p.productid) AS product_count
+                    , CAST(SUM(od.unitprice * od.qty * (1 - od.discount)) AS double precision) AS revenue
+                    , CAST(SUM(od.unitprice * od.qty) AS double precision) AS gross_revenue
+                    , CAST(SUM(p.unitprice * od.qty) AS double precision) AS list_revenue
+                    , SUM(od.qty) AS units
+                    , COUNT(DISTINCT o.orderid) AS order_count
+                    , COUNT(DISTINCT o.custid) AS customer_count
+                FROM Sales.Orders AS o
+                JOIN Sales.OrderDetails AS od ON o.orderid = od.orderid
+                JOIN Production.Products AS p ON od.productid = p.productid
+                JOIN Production.Categories AS cat ON p.categoryid = cat.categoryid
+                JOIN Sales.Customers AS c ON o.custid = c.custid
+                WHERE {ORDER_FILTER}
+                    AND ($7 IS NULL OR p.categoryid = $7)
+                GROUP BY cat.categoryid, cat.categoryname{group_period}")), filter)
+            .bind(query.category_id)
+            .fetch_all(&self.pool)
+            .await?;
+
+        let rows: Vec<CategoryPeriodSales> = map_rows(&rows)?;
+        Ok(CategorySales::rank(rows, query))
+    }
+
+    async fn get_sales_timeseries(&self, filter: &ReportFilter, query: &TimeSeriesQuery) -> Result<Vec<TimeSeriesPoint>, Error> {
+        let history = TimeSeriesPoint::history_filter(filter, query);
+
+        let rows = bind_filter(sqlx::query(&format!(
+            "SELECT o.orderdate AS order_date
+                    , COUNT(*) AS order_count
+                    , CAST(SUM(o.freight) AS double precision) AS freight
+                    , CAST(SUM(ov.order_value) AS double precision) AS revenue
+                FROM Sales.Orders AS o
+                JOIN (
+                    SELECT orderid, SUM(unitprice * qty * (1 - discount)) AS order_value
+                    FROM Sales.OrderDetails
+                    GROUP BY orderid
+                ) AS ov ON ov.orderid = o.orderid
+                JOIN Sales.Customers AS c ON o.custid = c.custid
+                WHERE {ORDER_FILTER}
+                GROUP BY o.orderdate")), &history)
+            .fetch_all(&self.pool)
+            .await?;
+
+        let rows: Vec<DailySales> = map_rows(&rows)?;
+        Ok(TimeSeriesPoint::build(rows, filter, query))
+    }
+
+    async fn get_rfm_segments(&self, filter: &ReportFilter, query: &RfmQuery) -> Result<Option<RfmReport>, Error> {
+        let orders = query.order_filter(filter);
+
+        let rows = bind_filter(sqlx::query(&format!(
+            "SELECT c.custid AS customer_id
+                    , c.companyname AS customer_name
+                    , c.country AS country
+                    , MAX(o.orderdate) AS last_order_date
+                    , COUNT(*) AS order_count
+                    , CAST(SUM(ov.order_value) AS double precision) AS monetary
+                FROM Sales.Orders AS o
+                JOIN (
+                    SELECT orderid, SUM(unitprice * qty * (1 - discount)) AS order_value
+                    FROM Sales.OrderDetails
+                    GROUP BY orderid
+                ) AS ov ON ov.orderid = o.orderid
+                JOIN Sales.Customers AS c ON o.custid = c.custid
+                WHERE {ORDER_FILTER}
+                GROUP BY c.custid, c.companyname, c.country")), &orders)
+            .fetch_all(&self.pool)
+            .await?;
+
+        let rows: Vec<CustomerOrderTotals> = map_rows(&rows)?;
+        Ok(RfmReport::build(rows, filter, query))
+    }
+
+    async fn get_cohort_retention(&self, filter: &ReportFilter, query: &CohortQuery) -> Result<Vec<CohortRetention>, Error> {
+        let orders = query.order_filter(filter);
+
+        let rows = bind_filter(sqlx::query(&format!(
+            "SELECT o.custid AS customer_id
+                    , o.orderdate AS order_date
+                    , CAST(ov.order_value AS double precision) AS order_value
+                FROM Sales.Orders AS o
+                JOIN (
+                    SELECT orderid, SUM(unitprice * qty * (1 - discount)) AS order_value
+                    FROM Sales.OrderDetails
+                    GROUP BY orderid
+                ) AS ov ON ov.orderid = o.orderid
+                JOIN Sales.Customers AS c ON o.custid = c.custid
+                WHERE {ORDER_FILTER}")), &orders)
+            .fetch_all(&self.pool)
+            .await?;
+
+        let rows: Vec<CustomerOrder> = map_rows(&rows)?;
+        Ok(CohortRetention::build(rows, filter, query))
+    }
+
+    async fn get_sales_forecast(&self, filter: &ReportFilter, query: &SalesForecastQuery) -> Result<Vec<SalesForecast>, Error> {
+        let series = match query.by {
+            ForecastGrouping::Total => "'Total'",
+            ForecastGrouping::Country => "c.country",
+            ForecastGrouping::Category => "cat.categoryname",
+        };
+        // A constant series name is not grouped by
+        let group_series = match query.by {
+            ForecastGrouping::Total => String::new(),
+            _ => format!("{}, ", series),
+        };
+        let period = period_sql(ReportPeriod::Month);
+
+        let rows = bind_filter(sqlx::query(&format!(
+            "SELECT {series} AS series
+                    , {period} AS period
+                    , CAST(SUM(od.unitprice * od.qty * (1 - od.discount)) AS double precision) AS revenue
+                FROM Sales.Orders AS o
+                JOIN Sales.OrderDetails AS od ON o.orderid = od.orderid
+                JOIN Production.Products AS p ON od.productid = p.productid
+                JOIN Production.Categories AS cat ON p.categoryid = cat.categoryid
+                JOIN Sales.Customers AS c ON o.custid = c.custid
+                WHERE {ORDER_FILTER}
+                GROUP BY {group_series}{period}")), filter)
+            .fetch_all(&self.pool)
+            .await?;
+
+        let rows: Vec<MonthlyRevenue> = map_rows(&rows)?;
+        Ok(SalesForecast::build(rows, filter, query))
+    }
+
+    async fn get_abc_analysis(&self, filter: &ReportFilter, query: &AbcQuery) -> Result<Option<AbcReport>, Error> {
+        let (id, name) = match query.dimension {
+            AbcDimension::Customer => ("c.custid", "c.companyname"),
+            AbcDimension::Product => ("p.productid", "p.productname"),
+        };
+
+        let rows = bind_filter(sqlx::query(&format!(
+            "SELECT {id} AS id
+                    , {name} AS name
+                    , CAST(SUM(od.unitprice * od.qty * (1 - od.discount)) AS double precision) AS revenue
+                FROM Sales.Orders AS o
+                JOIN Sales.OrderDetails AS od ON o.orderid = od.orderid
+                JOIN Production.Products AS p ON od.productid = p.productid
+                JOIN Sales.Customers AS c ON o.custid = c.custid
+                WHERE {ORDER_FILTER}
+                GROUP BY {id}, {name}")), filter)
+            .fetch_all(&self.pool)
+            .await?;
+
+        let rows: Vec<ItemRevenue> = map_rows(&rows)?;
+        Ok(AbcReport::build(rows, query))
+    }
+
+    async fn get_market_basket(&self, filter: &ReportFilter, query: &MarketBasketQuery) -> Result<Vec<ProductPair>, Error> {
+        let rows = bind_filter(sqlx::query(&order_products_sql()), filter)
+            .fetch_all(&self.pool)
+            .await?;
+
+        let rows: Vec<OrderProduct> = map_rows(&rows)?;
+        Ok(ProductPair::build(rows, query))
+    }
+
+    async fn get_bought_with(&self, product_id: i32, filter: &ReportFilter, query: &MarketBasketQuery) -> Result<Option<BoughtWithReport>, Error> {
+        let rows = bind_filter(sqlx::query(&order_products_sql()), filter)
+            .fetch_all(&self.pool)
+            .await?;
+
+        let rows: Vec<OrderProduct> = map_rows(&rows)?;
+        Ok(BoughtWithReport::build(rows, product_id, query))
+    }
+
+    fn pool_stats(&self) -> Option<PoolStats> {
+        Some(PoolStats::from_sqlx_pool(&self.pool))
+    }
+}
@@ -0,0 +1,85 @@
+use anyhow::{Error, Result};
+use async_trait::async_trait;
+
+use crate::db::grid::GridQuery;
+use crate::db::pool::PoolStats;
+use crate::db::stream::RowStream;
+use crate::models::gridrequest::Page;
+use crate::models::orderdetail::OrderDetail;
+use crate::models::ordersreport::OrdersReport;
+use crate::models::customerbyyear::CustomerByYear;
+use crate::models::customerprofile::{CustomerProfile, CustomerProfileQuery};
+use crate::models::employeeperformance::{EmployeePerformance, EmployeePerformanceQuery};
+use crate::models::topperformers::{TopPerformers, TopPerformersQuery};
+use crate::models::saleschoropleth::{ChoroplethQuery, SalesChoropleth};
+use crate::models::shipperperformance::{ShipperPerformance, ShipperPerformanceQuery};
+use crate::models::productsales::{CategorySales, ProductSales, ProductSalesQuery};
+use crate::models::salestimeseries::{TimeSeriesPoint, TimeSeriesQuery};
+use crate::models::rfmsegmentation::{RfmQuery, RfmReport};
+use crate::models::cohortretention::{CohortQuery, CohortRetention};
+use crate::models::salesforecast::{SalesForecast, SalesForecastQuery};
+use crate::models::abcanalysis::{AbcQuery, AbcReport};
+use crate::models::marketbasket::{BoughtWithReport, MarketBasketQuery, ProductPair};
+use crate::models::reportfilter::{ReportFilter, YearRange};
+
+// Everything the API layer needs from a database. Handlers only see this trait,
+// so any backend that can produce the four reports can serve the dashboard.
+// Every report is built only from the orders that pass the request's ReportFilter.
+#[async_trait]
+pub trait ReportRepository: Send + Sync {
+    // Detail reports are streamed, the rows are sent on while the query is still running.
+    fn stream_orders_report(&self, filter: &ReportFilter) -> RowStream<OrdersReport>;
+
+    // One page of the orders report for the dashboard grid, with the grid's own sort and
+    // column filters applied on top of the ReportFilter.
+    async fn get_orders_report_page(&self, filter: &ReportFilter, query: &GridQuery) -> Result<Page<OrdersReport>, Error>;
+
+    // A single order with its lines, None when there is no such order.
+    async fn get_order_detail(&self, order_id: i32) -> Result<Option<OrderDetail>, Error>;
+
+    async fn get_customer_sales_by_year(&self, filter: &ReportFilter, years: &YearRange) -> Result<Vec<CustomerByYear>, Error>;
+
+    // Profile of one customer from the orders that pass the filter, None when there is no such customer.
+    async fn get_customer_profile(&self, customer_id: i32, filter: &ReportFilter, query: &CustomerProfileQuery) -> Result<Option<CustomerProfile>, Error>;
+
+    async fn get_top_performers(&self, filter: &ReportFilter, query: &TopPerformersQuery) -> Result<Vec<TopPerformers>, Error>;
+
+    // Employee sales per period with team totals rolled up along the manager hierarchy.
+    async fn get_employee_performance(&self, filter: &ReportFilter, query: &EmployeePerformanceQuery) -> Result<Vec<EmployeePerformance>, Error>;
+
+    async fn get_sales_choropleth(&self, filter: &ReportFilter, query: &ChoroplethQuery) -> Result<Vec<SalesChoropleth>, Error>;
+
+    // Delivery performance by shipper and/or destination country.
+    async fn get_shipper_performance(&self, filter: &ReportFilter, query: &ShipperPerformanceQuery) -> Result<Vec<ShipperPerformance>, Error>;
+
+    // Product and category sales, ranked by the requested metric within each period.
+    async fn get_product_sales(&self, filter: &ReportFilter, query: &ProductSalesQuery) -> Result<Vec<ProductSales>, Error>;
+
+    async fn get_category_sales(&self, filter: &ReportFilter, query: &ProductSalesQuery) -> Result<Vec<CategorySales>, Error>;
+
+    // Revenue, orders and freight per day, week, month, quarter or year, empty buckets included.
+    async fn get_sales_timeseries(&self, filter: &ReportFilter, query: &TimeSeriesQuery) -> Result<Vec<TimeSeriesPoint>, Error>;
+
+    // Customers scored on recency, frequency and monetary value. None when no customer ordered.
+    async fn get_rfm_segments(&self, filter: &ReportFilter, query: &RfmQuery) -> Result<Option<RfmReport>, Error>;
+
+    // Customers grouped by the period of their first order, with their activity in every later period.
+    async fn get_cohort_retention(&self, filter: &ReportFilter, query: &CohortQuery) -> Result<Vec<CohortRetention>, Error>;
+
+    // Monthly revenue forecasts, overall or per customer country or product category.
+    async fn get_sales_forecast(&self, filter: &ReportFilter, query: &SalesForecastQuery) -> Result<Vec<SalesForecast>, Error>;
+
+    // Customers or products ranked by revenue and classified A, B or C. None when nothing sold.
+    async fn get_abc_analysis(&self, filter: &ReportFilter, query: &AbcQuery) -> Result<Option<AbcReport>, Error>;
+
+    // Products bought in the same orders, with the support, confidence and lift of each pair.
+    async fn get_market_basket(&self, filter: &ReportFilter, query: &MarketBasketQuery) -> Result<Vec<ProductPair>, Error>;
+
+    // The products bought together with one product. None when no order holds it.
+    async fn get_bought_with(&self, product_id: i32, filter: &ReportFilter, query: &MarketBasketQuery) -> Result<Option<BoughtWithReport>, Error>;
+
+    // Only pooled backends have something to report here.
+    fn pool_stats(&self) -> Option<PoolStats> {
+        None
+    }
+}
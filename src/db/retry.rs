@@ -0,0 +1,188 @@
+use anyhow::{Error, Result};
+use std::future::Future;
+use std::time::Duration;
+
+use crate::db::pool::{env_or, TiberiusConnectionManager};
+
+// SQL Server error numbers that mean "try again later" rather than "this query is wrong":
+// deadlock victim, database unavailable or being recovered, and the Azure SQL throttling/failover codes.
+const TRANSIENT_SERVER_ERRORS: [u32; 11] = [1205, 4060, 4221, 10053, 10054, 10928, 10929, 40143, 40197, 40501, 40613];
+
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    pub max_attempts: u32,
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+}
+
+impl RetryPolicy {
+    // Report queries are read-only, so a couple of quick retries on a fresh connection are safe.
+    pub fn queries_from_env() -> Result<Self, Error> {
+        Ok(RetryPolicy {
+            max_attempts: env_or("QUERY_RETRY_ATTEMPTS", 3)?,
+            initial_backoff: Duration::from_millis(env_or("QUERY_RETRY_BACKOFF_MS", 200)?),
+            max_backoff: Duration::from_secs(2),
+        })
+    }
+
+    // Keeps the service waiting for the database instead of exiting when both start together.
+    pub fn startup_from_env() -> Result<Self, Error> {
+        Ok(RetryPolicy {
+            max_attempts: env_or("STARTUP_RETRY_ATTEMPTS", 10)?,
+            initial_backoff: Duration::from_millis(env_or("STARTUP_RETRY_BACKOFF_MS", 1000)?),
+            max_backoff: Duration::from_secs(30),
+        })
+    }
+
+    // initial, 2x, 4x, ... capped at max_backoff
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let factor = 2u32.saturating_pow(attempt.saturating_sub(1));
+        self.initial_backoff.saturating_mul(factor).min(self.max_backoff)
+    }
+}
+
+pub async fn with_retry<T, F, Fut>(policy: &RetryPolicy, operation: &str, mut f: F) -> Result<T, Error>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, Error>>,
+{
+    let mut attempt = 1;
+    loop {
+        match f().await {
+            Ok(value) => return Ok(value),
+            Err(e) if attempt < policy.max_attempts && is_transient(&e) => {
+                let backoff = policy.backoff(attempt);
+                println!("{} failed (attempt {}/{}): {}. Retrying in {:?}", operation, attempt, policy.max_attempts, e, backoff);
+                tokio::time::sleep(backoff).await;
+                attempt += 1;
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+// Connection loss, pool timeouts and the server-side "busy" errors are worth retrying,
+// anything else (bad SQL, failed login, conversion errors) fails straight away.
+pub fn is_transient(e: &Error) -> bool {
+    e.chain().any(|cause| {
+        if let Some(tds) = cause.downcast_ref::<tiberius::error::Error>() {
+            return is_transient_tds(tds);
+        }
+        if let Some(run) = cause.downcast_ref::<bb8::RunError<<TiberiusConnectionManager as bb8::ManageConnection>::Error>>() {
+            return match run {
+                bb8::RunError::User(tds) => is_transient_tds(tds),
+                bb8::RunError::TimedOut => true,
+            };
+        }
+        if let Some(sqlx_error) = cause.downcast_ref::<sqlx::Error>() {
+            return matches!(sqlx_error, sqlx::Error::Io(_) | sqlx::Error::PoolTimedOut | sqlx::Error::PoolClosed);
+        }
+        cause.downcast_ref::<std::io::Error>().is_some()
+    })
+}
+
+fn is_transient_tds(e: &tiberius::error::Error) -> bool {
+    match e {
+        tiberius::error::Error::Io { .. } => true,
+        tiberius::error::Error::Server(token) => TRANSIENT_SERVER_ERRORS.contains(&token.code()),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+    use std::io;
+
+    fn policy(max_attempts: u32) -> RetryPolicy {
+        RetryPolicy { max_attempts, initial_backoff: Duration::from_millis(1), max_backoff: Duration::from_millis(4) }
+    }
+
+    fn connection_reset() -> Error {
+        Error::new(io::Error::new(io::ErrorKind::ConnectionReset, "connection reset"))
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_the_cap() {
+        let policy = RetryPolicy { max_attempts: 10, initial_backoff: Duration::from_millis(200), max_backoff: Duration::from_secs(2) };
+        let backoffs: Vec<u128> = (1..=6).map(|attempt| policy.backoff(attempt).as_millis()).collect();
+        assert_eq!(backoffs, vec![200, 400, 800, 1600, 2000, 2000]);
+        assert_eq!(policy.backoff(u32::MAX), Duration::from_secs(2));
+    }
+
+    #[tokio::test]
+    async fn transient_errors_are_retried_until_success() {
+        let calls = Cell::new(0);
+        let result = with_retry(&policy(3), "test", || {
+            calls.set(calls.get() + 1);
+            let call = calls.get();
+            async move { if call < 3 { Err(connection_reset()) } else { Ok(call) } }
+        })
+        .await;
+        assert_eq!(result.unwrap(), 3);
+        assert_eq!(calls.get(), 3);
+    }
+
+    #[tokio::test]
+    async fn retries_stop_at_max_attempts() {
+        let calls = Cell::new(0);
+        let result: Result<(), Error> = with_retry(&policy(4), "test", || {
+            calls.set(calls.get() + 1);
+            async { Err(connection_reset()) }
+        })
+        .await;
+        assert!(result.is_err());
+        assert_eq!(calls.get(), 4);
+    }
+
+    #[tokio::test]
+    async fn permanent_errors_fail_straight_away() {
+        let calls = Cell::new(0);
+        let result: Result<(), Error> = with_retry(&policy(4), "test", || {
+            calls.set(calls.get() + 1);
+            async { Err(Error::msg("Invalid column name 'custid'")) }
+        })
+        .await;
+        assert_eq!(result.unwrap_err().to_string(), "Invalid column name 'custid'");
+        assert_eq!(calls.get(), 1);
+    }
+
+    // tiberius does not let server error tokens be built outside the crate, so those are
+    // covered by TRANSIENT_SERVER_ERRORS alone.
+    #[test]
+    fn transient_errors_are_recognized() {
+        let tds_io: tiberius::error::Error = io::Error::new(io::ErrorKind::BrokenPipe, "broken pipe").into();
+        let tds_conversion = tiberius::error::Error::Conversion("cannot convert".into());
+
+        assert!(is_transient(&connection_reset()));
+        assert!(is_transient(&connection_reset().context("Error retrieving Orders report data")));
+        assert!(is_transient(&Error::new(tds_io)));
+        assert!(is_transient(&Error::new(bb8::RunError::<tiberius::error::Error>::TimedOut)));
+        assert!(is_transient(&Error::new(sqlx::Error::PoolTimedOut)));
+        assert!(is_transient(&Error::new(sqlx::Error::PoolClosed)));
+
+        assert!(!is_transient(&Error::new(tds_conversion)));
+        assert!(!is_transient(&Error::new(sqlx::Error::RowNotFound)));
+        assert!(!is_transient(&Error::msg("Invalid column name 'custid'")));
+        assert!(TRANSIENT_SERVER_ERRORS.contains(&1205));
+    }
+
+    // The only test that sets the retry variables
+    #[test]
+    fn policies_from_env() {
+        std::env::remove_var("QUERY_RETRY_ATTEMPTS");
+        std::env::set_var("QUERY_RETRY_BACKOFF_MS", "50");
+        let policy = RetryPolicy::queries_from_env().unwrap();
+        assert_eq!((policy.max_attempts, policy.initial_backoff), (3, Duration::from_millis(50)));
+
+        std::env::set_var("QUERY_RETRY_ATTEMPTS", "three");
+        assert!(RetryPolicy::queries_from_env().is_err());
+        std::env::remove_var("QUERY_RETRY_ATTEMPTS");
+        std::env::remove_var("QUERY_RETRY_BACKOFF_MS");
+
+        std::env::set_var("STARTUP_RETRY_BACKOFF_MS", "-1");
+        assert!(RetryPolicy::startup_from_env().is_err());
+        std::env::remove_var("STARTUP_RETRY_BACKOFF_MS");
+    }
+}
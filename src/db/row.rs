@@ -0,0 +1,378 @@
+use chrono::{NaiveDate, NaiveDateTime};
+use std::fmt;
+
+// Row mapping shared by every SQL backend. A backend only has to turn one column of its
+// native row type into a `Value`; `#[derive(FromRow)]` on a model does the rest, and a
+// NULL or an unexpected type becomes a `MappingError` instead of a panic.
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    Text(String),
+    Date(NaiveDate),
+    DateTime(NaiveDateTime),
+}
+
+impl Value {
+    fn type_name(&self) -> &'static str {
+        match self {
+            Value::Null => "NULL",
+            Value::Bool(_) => "bool",
+            Value::Int(_) => "integer",
+            Value::Float(_) => "float",
+            Value::Text(_) => "text",
+            Value::Date(_) => "date",
+            Value::DateTime(_) => "datetime",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum MappingError {
+    MissingColumn { column: String },
+    UnexpectedNull { column: String },
+    TypeMismatch { column: String, expected: &'static str, found: &'static str },
+    Backend { column: String, message: String },
+}
+
+impl fmt::Display for MappingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MappingError::MissingColumn { column } => write!(f, "column `{}` is missing from the result set", column),
+            MappingError::UnexpectedNull { column } => write!(f, "column `{}` is NULL but the field is not optional", column),
+            MappingError::TypeMismatch { column, expected, found } => write!(f, "column `{}` holds {} but {} was expected", column, found, expected),
+            MappingError::Backend { column, message } => write!(f, "column `{}` could not be read: {}", column, message),
+        }
+    }
+}
+
+impl std::error::Error for MappingError {}
+
+//-------- READING COLUMNS --------------//
+pub trait RowSource {
+    fn value(&self, column: &str) -> Result<Value, MappingError>;
+}
+
+pub trait FromRow: Sized {
+    fn from_row<R: RowSource + ?Sized>(row: &R) -> Result<Self, MappingError>;
+}
+
+pub fn map_rows<T: FromRow, R: RowSource>(rows: &[R]) -> Result<Vec<T>, MappingError> {
+    rows.iter().map(T::from_row).collect()
+}
+
+// Used by the derive for plain fields.
+pub fn get_column<T: FromValue, R: RowSource + ?Sized>(row: &R, column: &str) -> Result<T, MappingError> {
+    T::from_value(row.value(column)?, column)
+}
+
+// Used by the derive for `#[row(default)]` fields: a missing column or a NULL gives `T::default()`.
+pub fn get_column_or_default<T: FromValue + Default, R: RowSource + ?Sized>(row: &R, column: &str) -> Result<T, MappingError> {
+    match row.value(column) {
+        Ok(Value::Null) | Err(MappingError::MissingColumn { .. }) => Ok(T::default()),
+        Ok(value) => T::from_value(value, column),
+        Err(e) => Err(e),
+    }
+}
+
+//-------- CONVERTING VALUES --------------//
+// Conversions are lenient where nothing is lost: integers widen to floats, whole floats
+// narrow to integers, dates render as ISO strings. Everything else is a TypeMismatch.
+pub trait FromValue: Sized {
+    fn from_value(value: Value, column: &str) -> Result<Self, MappingError>;
+}
+
+fn mismatch<T>(column: &str, expected: &'static str, value: &Value) -> Result<T, MappingError> {
+    match value {
+        Value::Null => Err(MappingError::UnexpectedNull { column: column.to_string() }),
+        _ => Err(MappingError::TypeMismatch { column: column.to_string(), expected, found: value.type_name() }),
+    }
+}
+
+impl<T: FromValue> FromValue for Option<T> {
+    fn from_value(value: Value, column: &str) -> Result<Self, MappingError> {
+        match value {
+            Value::Null => Ok(None),
+            value => T::from_value(value, column).map(Some),
+        }
+    }
+}
+
+impl FromValue for String {
+    fn from_value(value: Value, column: &str) -> Result<Self, MappingError> {
+        match value {
+            Value::Text(s) => Ok(s),
+            Value::Date(d) => Ok(d.format("%Y-%m-%d").to_string()),
+            Value::DateTime(dt) => Ok(dt.format("%Y-%m-%d %H:%M:%S").to_string()),
+            Value::Int(i) => Ok(i.to_string()),
+            other => mismatch(column, "text", &other),
+        }
+    }
+}
+
+impl FromValue for f64 {
+    fn from_value(value: Value, column: &str) -> Result<Self, MappingError> {
+        match value {
+            Value::Float(f) => Ok(f),
+            Value::Int(i) => Ok(i as f64),
+            other => mismatch(column, "float", &other),
+        }
+    }
+}
+
+impl FromValue for i64 {
+    fn from_value(value: Value, column: &str) -> Result<Self, MappingError> {
+        match value {
+            Value::Int(i) => Ok(i),
+            Value::Float(f) if f.fract() == 0.0 => Ok(f as i64),
+            Value::Bool(b) => Ok(b as i64),
+            other => mismatch(column, "integer", &other),
+        }
+    }
+}
+
+impl FromValue for i32 {
+    fn from_value(value: Value, column: &str) -> Result<Self, MappingError> {
+        let found = value.type_name();
+        let wide = i64::from_value(value, column)?;
+        i32::try_from(wide).map_err(|_| MappingError::TypeMismatch { column: column.to_string(), expected: "32-bit integer", found })
+    }
+}
+
+impl FromValue for bool {
+    fn from_value(value: Value, column: &str) -> Result<Self, MappingError> {
+        match value {
+            Value::Bool(b) => Ok(b),
+            Value::Int(0) => Ok(false),
+            Value::Int(1) => Ok(true),
+            other => mismatch(column, "bool", &other),
+        }
+    }
+}
+
+impl FromValue for NaiveDate {
+    fn from_value(value: Value, column: &str) -> Result<Self, MappingError> {
+        match value {
+            Value::Date(d) => Ok(d),
+            Value::DateTime(dt) => Ok(dt.date()),
+            Value::Text(ref s) => NaiveDate::parse_from_str(&s[..s.len().min(10)], "%Y-%m-%d")
+                .or_else(|_| mismatch(column, "date", &value)),
+            other => mismatch(column, "date", &other),
+        }
+    }
+}
+
+//-------- BACKEND ROWS --------------//
+impl RowSource for tiberius::Row {
+    fn value(&self, column: &str) -> Result<Value, MappingError> {
+        if !self.columns().iter().any(|c| c.name() == column) {
+            return Err(MappingError::MissingColumn { column: column.to_string() });
+        }
+
+        // tiberius only exposes typed getters, so try the types our queries produce until one
+        // fits. A conversion error means "not this type", Ok(None) means the value is NULL.
+        macro_rules! try_as {
+            ($ty:ty, $to_value:expr) => {
+                if let Ok(value) = self.try_get::<$ty, _>(column) {
+                    return Ok(value.map($to_value).unwrap_or(Value::Null));
+                }
+            };
+        }
+
+        try_as!(&str, |s: &str| Value::Text(s.to_string()));
+        try_as!(f64, Value::Float);
+        try_as!(i32, |i: i32| Value::Int(i as i64));
+        try_as!(i64, Value::Int);
+        try_as!(i16, |i: i16| Value::Int(i as i64));
+        try_as!(u8, |i: u8| Value::Int(i as i64));
+        try_as!(f32, |f: f32| Value::Float(f as f64));
+        try_as!(bool, Value::Bool);
+        try_as!(tiberius::numeric::Numeric, |n: tiberius::numeric::Numeric| Value::Float(n.into()));
+        try_as!(NaiveDate, Value::Date);
+        try_as!(NaiveDateTime, Value::DateTime);
+
+        Err(MappingError::Backend { column: column.to_string(), message: "unsupported SQL Server column type".to_string() })
+    }
+}
+
+fn sqlx_value<R>(row: &R, column: &str) -> Result<Value, MappingError>
+where
+    R: sqlx::Row,
+    usize: sqlx::ColumnIndex<R>,
+    for<'a> &'a str: sqlx::ColumnIndex<R>,
+    for<'r> &'r str: sqlx::Decode<'r, R::Database> + sqlx::Type<R::Database>,
+    for<'r> f64: sqlx::Decode<'r, R::Database> + sqlx::Type<R::Database>,
+    for<'r> i64: sqlx::Decode<'r, R::Database> + sqlx::Type<R::Database>,
+    for<'r> i32: sqlx::Decode<'r, R::Database> + sqlx::Type<R::Database>,
+    for<'r> bool: sqlx::Decode<'r, R::Database> + sqlx::Type<R::Database>,
+    for<'r> NaiveDate: sqlx::Decode<'r, R::Database> + sqlx::Type<R::Database>,
+{
+    use sqlx::{TypeInfo, ValueRef};
+
+    let backend_error = |e: sqlx::Error| match e {
+        sqlx::Error::ColumnNotFound(_) => MappingError::MissingColumn { column: column.to_string() },
+        e => MappingError::Backend { column: column.to_string(), message: e.to_string() },
+    };
+
+    let raw = row.try_get_raw(column).map_err(backend_error)?;
+    if raw.is_null() {
+        return Ok(Value::Null);
+    }
+    let type_name = raw.type_info().name().to_uppercase();
+
+    // SQLite reports the storage class of the value, Postgres the column type.
+    let value = match type_name.as_str() {
+        "INTEGER" | "INT8" | "BIGINT" => Value::Int(row.try_get::<i64, _>(column).map_err(backend_error)?),
+        "INT4" | "INT" | "INT2" | "SMALLINT" => Value::Int(row.try_get::<i32, _>(column).map_err(backend_error)? as i64),
+        "REAL" | "FLOAT8" | "DOUBLE PRECISION" => Value::Float(row.try_get::<f64, _>(column).map_err(backend_error)?),
+        "BOOL" | "BOOLEAN" => Value::Bool(row.try_get::<bool, _>(column).map_err(backend_error)?),
+        "DATE" => Value::Date(row.try_get::<NaiveDate, _>(column).map_err(backend_error)?),
+        "TEXT" | "VARCHAR" | "BPCHAR" | "NAME" | "CHAR" => Value::Text(row.try_get::<&str, _>(column).map_err(backend_error)?.to_string()),
+        other => return Err(MappingError::Backend { column: column.to_string(), message: format!("unsupported column type {}", other) }),
+    };
+    Ok(value)
+}
+
+impl RowSource for sqlx::sqlite::SqliteRow {
+    fn value(&self, column: &str) -> Result<Value, MappingError> {
+        sqlx_value(self, column)
+    }
+}
+
+impl RowSource for sqlx::postgres::PgRow {
+    fn value(&self, column: &str) -> Result<Value, MappingError> {
+        sqlx_value(self, column)
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use mssqlapi_derive::FromRow;
+
+    // A result row held as column/value pairs, for testing the mapping without a database.
+    pub(crate) struct FakeRow(pub Vec<(&'static str, Value)>);
+
+    impl RowSource for FakeRow {
+        fn value(&self, column: &str) -> Result<Value, MappingError> {
+            self.0.iter()
+                .find(|(name, _)| *name == column)
+                .map(|(_, value)| value.clone())
+                .ok_or_else(|| MappingError::MissingColumn { column: column.to_string() })
+        }
+    }
+
+    fn shout(name: String) -> String {
+        name.to_uppercase()
+    }
+
+    #[derive(Debug, FromRow)]
+    struct Sample {
+        id: i32,
+        #[row(rename = "company_name")]
+        name: String,
+        #[row(default)]
+        revenue: f64,
+        #[row(with = "shout")]
+        country: String,
+        contact: Option<String>,
+        order_date: NaiveDate,
+    }
+
+    fn sample_row() -> Vec<(&'static str, Value)> {
+        vec![
+            ("id", Value::Int(7)),
+            ("company_name", Value::Text("Customer AHPOP".to_string())),
+            ("revenue", Value::Int(1250)),
+            ("country", Value::Text("uk".to_string())),
+            ("contact", Value::Null),
+            ("order_date", Value::Text("2023-05-04".to_string())),
+        ]
+    }
+
+    fn with_value(column: &'static str, value: Value) -> FakeRow {
+        let mut row = sample_row();
+        row.iter_mut().find(|(name, _)| *name == column).unwrap().1 = value;
+        FakeRow(row)
+    }
+
+    fn without(column: &'static str) -> FakeRow {
+        FakeRow(sample_row().into_iter().filter(|(name, _)| *name != column).collect())
+    }
+
+    #[test]
+    fn maps_every_attribute() {
+        let sample = Sample::from_row(&FakeRow(sample_row())).unwrap();
+        assert_eq!(sample.id, 7);
+        assert_eq!(sample.name, "Customer AHPOP");
+        assert_eq!(sample.revenue, 1250.0);
+        assert_eq!(sample.country, "UK");
+        assert_eq!(sample.contact, None);
+        assert_eq!(sample.order_date, NaiveDate::from_ymd_opt(2023, 5, 4).unwrap());
+    }
+
+    #[test]
+    fn rename_reads_the_named_column_only() {
+        let mut row = without("company_name");
+        row.0.push(("name", Value::Text("Customer AHPOP".to_string())));
+        assert_eq!(Sample::from_row(&row).unwrap_err(), MappingError::MissingColumn { column: "company_name".to_string() });
+    }
+
+    #[test]
+    fn default_covers_null_and_missing_columns() {
+        assert_eq!(Sample::from_row(&with_value("revenue", Value::Null)).unwrap().revenue, 0.0);
+        assert_eq!(Sample::from_row(&without("revenue")).unwrap().revenue, 0.0);
+        assert_eq!(
+            Sample::from_row(&with_value("revenue", Value::Text("lots".to_string()))).unwrap_err(),
+            MappingError::TypeMismatch { column: "revenue".to_string(), expected: "float", found: "text" },
+        );
+    }
+
+    #[test]
+    fn option_fields_keep_values() {
+        let row = with_value("contact", Value::Text("Allen, Michael".to_string()));
+        assert_eq!(Sample::from_row(&row).unwrap().contact.as_deref(), Some("Allen, Michael"));
+    }
+
+    #[test]
+    fn null_into_a_required_field_is_an_error() {
+        assert_eq!(Sample::from_row(&with_value("id", Value::Null)).unwrap_err(), MappingError::UnexpectedNull { column: "id".to_string() });
+        assert_eq!(Sample::from_row(&with_value("country", Value::Null)).unwrap_err(), MappingError::UnexpectedNull { column: "country".to_string() });
+    }
+
+    #[test]
+    fn wrong_types_are_errors() {
+        assert_eq!(
+            Sample::from_row(&with_value("id", Value::Text("seven".to_string()))).unwrap_err(),
+            MappingError::TypeMismatch { column: "id".to_string(), expected: "integer", found: "text" },
+        );
+        assert_eq!(
+            Sample::from_row(&with_value("id", Value::Int(i64::from(i32::MAX) + 1))).unwrap_err(),
+            MappingError::TypeMismatch { column: "id".to_string(), expected: "32-bit integer", found: "integer" },
+        );
+        assert_eq!(
+            Sample::from_row(&with_value("id", Value::Float(7.5))).unwrap_err(),
+            MappingError::TypeMismatch { column: "id".to_string(), expected: "integer", found: "float" },
+        );
+        assert_eq!(
+            Sample::from_row(&with_value("order_date", Value::Text("May 4th".to_string()))).unwrap_err(),
+            MappingError::TypeMismatch { column: "order_date".to_string(), expected: "date", found: "text" },
+        );
+    }
+
+    #[test]
+    fn lossless_conversions_are_accepted() {
+        assert_eq!(i64::from_value(Value::Float(3.0), "c"), Ok(3));
+        assert_eq!(bool::from_value(Value::Int(1), "c"), Ok(true));
+        assert_eq!(String::from_value(Value::Date(NaiveDate::from_ymd_opt(2023, 1, 2).unwrap()), "c"), Ok("2023-01-02".to_string()));
+    }
+
+    #[test]
+    fn errors_name_the_column() {
+        let e = Sample::from_row(&with_value("id", Value::Null)).unwrap_err();
+        assert_eq!(e.to_string(), "column `id` is NULL but the field is not optional");
+    }
+}
@@ -0,0 +1,842 @@
+use anyhow::{Error, Result};
+use async_trait::async_trait;
+use futures::TryStreamExt;
+use sqlx::query::Query;
+use sqlx::sqlite::{Sqlite, SqliteArguments, SqliteConnectOptions, SqlitePool, SqlitePoolOptions};
+use sqlx::Executor;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::db::fixtures::FixtureData;
+use crate::db::repository::ReportRepository;
+use crate::db::grid::{Dialect, GridQuery, SqlParam};
+use crate::db::bind_filter;
+use crate::db::pool::PoolStats;
+use crate::db::row::{get_column, map_rows, FromRow};
+use crate::db::stream::{send_row, spawn_row_stream, RowStream};
+
+use crate::models::gridrequest::Page;
+use crate::models::orderdetail::{OrderDetail, OrderHeader};
+use crate::models::ordersreport::OrdersReport;
+use crate::models::customerbyyear::{CustomerByYear, CustomerYearSales};
+use crate::models::customerprofile::{CustomerInfo, CustomerProfile, CustomerProfileQuery};
+use crate::models::employeeperformance::{EmployeePerformance, EmployeePerformanceQuery};
+use crate::models::topperformers::{CustomerEmployeeSales, TopPerformers, TopPerformersQuery};
+use crate::models::saleschoropleth::{ChoroplethQuery, CountryCustomerSales, SalesChoropleth};
+use crate::models::shipperperformance::{ShipmentTotals, ShipperPerformance, ShipperPerformanceQuery};
+use crate::models::productsales::{CategoryPeriodSales, CategorySales, ProductInfo, ProductPeriodSales, ProductSales, ProductSalesQuery};
+use crate::models::salestimeseries::{DailySales, TimeSeriesPoint, TimeSeriesQuery};
+use crate::models::rfmsegmentation::{CustomerOrderTotals, RfmQuery, RfmReport};
+use crate::models::cohortretention::{CohortQuery, CohortRetention, CustomerOrder};
+use crate::models::salesforecast::{ForecastGrouping, MonthlyRevenue, SalesForecast, SalesForecastQuery};
+use crate::models::abcanalysis::{AbcDimension, AbcQuery, AbcReport, ItemRevenue};
+use crate::models::marketbasket::{BoughtWithReport, MarketBasketQuery, OrderProduct, ProductPair};
+use crate::models::reportfilter::{ReportFilter, ReportPeriod, YearRange};
+
+// Each SQL Server schema lives in its own attached database file, so the
+// queries can keep the Sales.Orders / HR.Employees / Production.Products names.
+const SCHEMAS: [&str; 3] = ["Sales", "HR", "Production"];
+
+const CREATE_SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS HR.Employees (
+        empid INTEGER NOT NULL PRIMARY KEY,
+        lastname TEXT NOT NULL,
+        firstname TEXT NOT NULL,
+        title TEXT NULL,
+        hiredate TEXT NOT NULL,
+        country TEXT NOT NULL,
+        mgrid INTEGER NULL REFERENCES Employees(empid)
+    );
+    CREATE TABLE IF NOT EXISTS Sales.Customers (
+        custid INTEGER NOT NULL PRIMARY KEY,
+        companyname TEXT NOT NULL,
+        contactname TEXT NULL,
+        contacttitle TEXT NOT NULL,
+        city TEXT NOT NULL,
+        country TEXT NOT NULL,
+        phone TEXT NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS Sales.Shippers (
+        shipperid INTEGER NOT NULL PRIMARY KEY,
+        companyname TEXT NOT NULL,
+        phone TEXT NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS Production.Categories (
+        categoryid INTEGER NOT NULL PRIMARY KEY,
+        categoryname TEXT NOT NULL,
+        description TEXT NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS Production.Products (
+        productid INTEGER NOT NULL PRIMARY KEY,
+        productname TEXT NOT NULL,
+        categoryid INTEGER NOT NULL,
+        unitprice REAL NOT NULL DEFAULT 0 CHECK (unitprice >= 0),
+        discontinued INTEGER NOT NULL DEFAULT 0
+    );
+    CREATE TABLE IF NOT EXISTS Sales.Orders (
+        orderid INTEGER NOT NULL PRIMARY KEY,
+        custid INTEGER NOT NULL,
+        empid INTEGER NOT NULL,
+        orderdate TEXT NOT NULL,
+        requireddate TEXT NOT NULL,
+        shippeddate TEXT NULL,
+        shipperid INTEGER NOT NULL,
+        freight REAL NOT NULL DEFAULT 0,
+        shipname TEXT NULL,
+        shipcity TEXT NOT NULL,
+        shipcountry TEXT NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS Sales.idx_orders_custid ON Orders(custid);
+    CREATE INDEX IF NOT EXISTS Sales.idx_orders_orderdate ON Orders(orderdate);
+    CREATE TABLE IF NOT EXISTS Sales.OrderDetails (
+        orderid INTEGER NOT NULL REFERENCES Orders(orderid),
+        productid INTEGER NOT NULL,
+        unitprice REAL NOT NULL DEFAULT 0 CHECK (unitprice >= 0),
+        qty INTEGER NOT NULL DEFAULT 1 CHECK (qty > 0),
+        discount REAL NOT NULL DEFAULT 0 CHECK (discount BETWEEN 0 AND 1),
+        PRIMARY KEY (orderid, productid)
+    );
+";
+
+#[derive(Clone)]
+pub struct DatabaseSQLite {
+    pub pool: SqlitePool,
+}
+
+impl DatabaseSQLite {
+
+    //-------- INITIALIZING DATABASE --------------//
+    pub async fn init(directory: &str) -> Result<Self, Error> {
+        fs::create_dir_all(directory)?;
+
+        let directory = PathBuf::from(directory);
+        let options = SqliteConnectOptions::new()
+            .filename(directory.join("reports.db"))
+            .create_if_missing(true);
+
+        // ATTACH is per connection, so every pooled connection gets the schema files
+        let pool = SqlitePoolOptions::new()
+            .after_connect(move |conn, _meta| {
+                let directory = directory.clone();
+                Box::pin(async move {
+                    for schema in SCHEMAS {
+                        let file = schema_file(&directory, schema);
+                        sqlx::query(&format!("ATTACH DATABASE ? AS {}", schema))
+                            .bind(file)
+                            .execute(&mut *conn)
+                            .await?;
+                    }
+                    Ok(())
+                })
+            })
+            .connect_with(options)
+            .await?;
+
+        pool.execute(CREATE_SCHEMA).await?;
+
+        Ok(DatabaseSQLite{pool})
+    }
+
+    // Loads the fixture tables, but only into an empty database so restarts keep local edits.
+    pub async fn seed(&self, fixtures: &FixtureData) -> Result<(), Error> {
+        // Categories are checked on their own, databases seeded before the table existed lack them
+        let categories: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM Production.Categories")
+            .fetch_one(&self.pool)
+            .await?;
+        if categories == 0 {
+            for c in &fixtures.categories {
+                sqlx::query("INSERT INTO Production.Categories (categoryid, categoryname, description) VALUES (?, ?, ?)")
+                    .bind(c.categoryid).bind(&c.categoryname).bind(&c.description)
+                    .execute(&self.pool).await?;
+            }
+        }
+
+        let orders: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM Sales.Orders")
+            .fetch_one(&self.pool)
+            .await?;
+        if orders > 0 {
+            return Ok(());
+        }
+
+        let mut tx = self.pool.begin().await?;
+
+        for e in &fixtures.employees {
+            sqlx::query("INSERT INTO HR.Employees (empid, lastname, firstname, title, hiredate, country, mgrid) VALUES (?, ?, ?, ?, ?, ?, ?)")
+                .bind(e.empid).bind(&e.lastname).bind(&e.firstname).bind(&e.title)
+                .bind(&e.hiredate).bind(&e.country).bind(e.mgrid)
+                .execute(&mut *tx).await?;
+        }
+        for c in &fixtures.customers {
+            sqlx::query("INSERT INTO Sales.Customers (custid, companyname, contactname, contacttitle, city, country, phone) VALUES (?, ?, ?, ?, ?, ?, ?)")
+                .bind(c.custid).bind(&c.companyname).bind(&c.contactname).bind(&c.contacttitle)
+                .bind(&c.city).bind(&c.country).bind(&c.phone)
+                .execute(&mut *tx).await?;
+        }
+        for s in &fixtures.shippers {
+            sqlx::query("INSERT INTO Sales.Shippers (shipperid, companyname, phone) VALUES (?, ?, ?)")
+                .bind(s.shipperid).bind(&s.companyname).bind(&s.phone)
+                .execute(&mut *tx).await?;
+        }
+        for p in &fixtures.products {
+            sqlx::query("INSERT INTO Production.Products (productid, productname, categoryid, unitprice, discontinued) VALUES (?, ?, ?, ?, ?)")
+                .bind(p.productid).bind(&p.productname).bind(p.categoryid).bind(p.unitprice).bind(p.discontinued)
+                .execute(&mut *tx).await?;
+        }
+        for o in &fixtures.orders {
+            sqlx::query("INSERT INTO Sales.Orders (orderid, custid, empid, orderdate, requireddate, shippeddate, shipperid, freight, shipname, shipcity, shipcountry) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)")
+                .bind(o.orderid).bind(o.custid).bind(o.empid).bind(&o.orderdate).bind(&o.requireddate)
+                .bind(&o.shippeddate).bind(o.shipperid).bind(o.freight).bind(&o.shipname)
+                .bind(&o.shipcity).bind(&o.shipcountry)
+                .execute(&mut *tx).await?;
+        }
+        for od in &fixtures.order_details {
+            sqlx::query("INSERT INTO Sales.OrderDetails (orderid, productid, unitprice, qty, discount) VALUES (?, ?, ?, ?, ?)")
+                .bind(od.orderid).bind(od.productid).bind(od.unitprice).bind(od.qty).bind(od.discount)
+                .execute(&mut *tx).await?;
+        }
+
+        tx.commit().await?;
+        Ok(())
+    }
+}
+
+fn schema_file(directory: &Path, schema: &str) -> String {
+    directory.join(format!("{}.db", schema.to_lowercase())).to_string_lossy().into_owned()
+}
+
+// Restricts the orders behind a report to the ReportFilter values, bound as ?1..?6 by
+// bind_filter. Queries using it need the o (Sales.Orders) and c (Sales.Customers) aliases.
+pub(crate) const ORDER_FILTER: &str = "(?1 IS NULL OR o.orderdate >= ?1)
+                    AND (?2 IS NULL OR o.orderdate <= ?2)
+                    AND (?3 IS NULL OR c.country = ?3)
+                    AND (?4 IS NULL OR o.custid = ?4)
+                    AND (?5 IS NULL OR o.empid = ?5)
+                    AND (?6 IS NULL OR o.shipperid = ?6)";
+
+fn bind_grid<'q>(query: Query<'q, Sqlite, SqliteArguments<'q>>, params: &[SqlParam]) -> Query<'q, Sqlite, SqliteArguments<'q>> {
+    params.iter().fold(query, |query, param| match param {
+        SqlParam::Text(text) => query.bind(text.clone()),
+        SqlParam::Number(number) => query.bind(*number),
+        SqlParam::Int(int) => query.bind(*int),
+    })
+}
+
+// Period label of an order, "2023", "2023-Q2" or "2023-05" like ReportPeriod::label.
+fn period_sql(period: ReportPeriod) -> &'static str {
+    match period {
+        ReportPeriod::Year => "strftime('%Y', o.orderdate)",
+        ReportPeriod::Quarter => "strftime('%Y', o.orderdate) || '-Q' || ((CAST(strftime('%m', o.orderdate) AS INTEGER) + 2) / 3)",
+        ReportPeriod::Month => "strftime('%Y-%m', o.orderdate)",
+    }
+}
+
+// The orders report, one row per order filtered by ORDER_FILTER. Streamed as is, or wrapped
+// by the grid clauses for a single page.
+fn orders_report_sql() -> String {
+    format!("
+                SELECT o.orderid as order_id
+                    , c.companyname as customer_name
+                    , c.contactname as customer_contact
+                    , c.country as customer_country
+                    , e.lastname || ', ' || e.firstname as employee_name
+                    , e.title as employee_title
+                    , sh.companyname as shipper_name
+                    , o.shipname as ship_name
+                    , o.orderdate as order_date
+                    , o.requireddate as delivery_date
+                    , CAST(MAX(o.freight) as REAL) as freight_value
+                    , CAST(SUM(od.unitprice*od.qty*(1-od.discount)) as REAL) as order_value
+                    , CAST(MAX(o.freight) + SUM(od.unitprice*od.qty*(1-od.discount)) as REAL) as billable_value
+                FROM Sales.Orders as o
+                JOIN Sales.Customers as c on o.custid = c.custid
+                JOIN HR.Employees as e on o.empid = e.empid
+                JOIN Sales.Shippers as sh on o.shipperid = sh.shipperid
+                JOIN Sales.OrderDetails as od on o.orderid = od.orderid
+                JOIN Production.Products as p on od.productid = p.productid
+                WHERE {ORDER_FILTER}
+                GROUP BY o.orderid
+                    , c.companyname
+                    , c.contactname
+                    , c.country
+                    , e.lastname || ', ' || e.firstname
+                    , e.title
+                    , sh.companyname
+                    , o.shipname
+                    , o.orderdate
+                    , o.requireddate")
+}
+
+// One row per product of each order that passes ORDER_FILTER, the baskets behind the
+// market basket reports.
+fn order_products_sql() -> String {
+    format!("
+                SELECT DISTINCT od.orderid AS order_id
+                    , od.productid AS product_id
+                    , p.productname AS product_name
+                FROM Sales.Orders AS o
+                JOIN Sales.OrderDetails AS od ON o.orderid = od.orderid
+                JOIN Production.Products AS p ON od.productid = p.productid
+                JOIN Sales.Customers AS c ON o.custid = c.custid
+                WHERE {ORDER_FILTER}")
+}
+
+#[async_trait]
+impl ReportRepository for DatabaseSQLite {
+
+    fn stream_orders_report(&self, filter: &ReportFilter) -> RowStream<OrdersReport> {
+        let pool = self.pool.clone();
+        let filter = filter.clone();
+        spawn_row_stream(move |tx| async move {
+            let sql = orders_report_sql();
+            let mut rows = bind_filter(sqlx::query(&sql), &filter).fetch(&pool);
+            while let Some(row) = rows.try_next().await? {
+                if !send_row(&tx, OrdersReport::from_row(&row)?).await {
+                    break;
+                }
+            }
+            Ok(())
+        })
+    }
+
+    async fn get_orders_report_page(&self, filter: &ReportFilter, query: &GridQuery) -> Result<Page<OrdersReport>, Error> {
+        // The grid's parameters follow the six filter parameters
+        let grid = query.to_sql(Dialect::Sqlite, 7);
+        let report_sql = orders_report_sql();
+
+        let count_sql = grid.count(&report_sql);
+        let count = bind_grid(bind_filter(sqlx::query(&count_sql), filter), &grid.params)
+            .fetch_one(&self.pool)
+            .await?;
+
+        let select_sql = grid.select(&report_sql);
+        let rows = bind_grid(bind_grid(bind_filter(sqlx::query(&select_sql), filter), &grid.params), &grid.page_params)
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(Page { rows: map_rows(&rows)?, total: get_column(&count, "total")? })
+    }
+
+    async fn get_order_detail(&self, order_id: i32) -> Result<Option<OrderDetail>, Error> {
+        let header = sqlx::query("
+            SELECT o.orderid AS order_id
+                , c.custid AS customer_id
+                , c.companyname AS customer_name
+                , c.contactname AS customer_contact
+                , c.country AS customer_country
+                , e.empid AS employee_id
+                , e.lastname || ', ' || e.firstname AS employee_name
+                , e.title AS employee_title
+                , sh.shipperid AS shipper_id
+                , sh.companyname AS shipper_name
+                , o.shipname AS ship_name
+                , o.shipcity AS ship_city
+                , o.shipcountry AS ship_country
+                , o.orderdate AS order_date
+                , o.requireddate AS required_date
+                , o.shippeddate AS shipped_date
+                , CAST(o.freight AS REAL) AS freight
+            FROM Sales.Orders AS o
+            JOIN Sales.Customers AS c ON o.custid = c.custid
+            JOIN HR.Employees AS e ON o.empid = e.empid
+            JOIN Sales.Shippers AS sh ON o.shipperid = sh.shipperid
+            WHERE o.orderid = ?1")
+            .bind(order_id)
+            .fetch_optional(&self.pool)
+            .await?;
+        let Some(header) = header else {
+            return Ok(None);
+        };
+        let header = OrderHeader::from_row(&header)?;
+
+        let lines = sqlx::query("
+            SELECT p.productid AS product_id
+                , p.productname AS product_name
+                , CAST(od.unitprice AS REAL) AS unit_price
+                , od.qty AS quantity
+                , CAST(od.discount AS REAL) AS discount
+                , CAST(od.unitprice * od.qty * (1 - od.discount) AS REAL) AS line_total
+            FROM Sales.OrderDetails AS od
+            JOIN Production.Products AS p ON od.productid = p.productid
+            WHERE od.orderid = ?1
+            ORDER BY p.productid")
+            .bind(order_id)
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(Some(OrderDetail::new(header, map_rows(&lines)?)))
+    }
+
+    async fn get_customer_sales_by_year(&self, filter: &ReportFilter, years: &YearRange) -> Result<Vec<CustomerByYear>, Error> {
+        let rows = bind_filter(sqlx::query(&format!(
+            "SELECT c.companyname as customer_name
+                    , CAST(strftime('%Y', o.orderdate) as INTEGER) as order_year
+                    , CAST(SUM(od.unitprice * od.qty * (1-od.discount)) as REAL) as sales
+                FROM Sales.Orders as o
+                JOIN Sales.OrderDetails as od on o.orderid = od.orderid
+                JOIN Sales.Customers as c on o.custid = c.custid
+                WHERE {ORDER_FILTER}
+                    AND (?7 IS NULL OR CAST(strftime('%Y', o.orderdate) as INTEGER) >= ?7)
+                    AND (?8 IS NULL OR CAST(strftime('%Y', o.orderdate) as INTEGER) <= ?8)
+                GROUP BY c.companyname, strftime('%Y', o.orderdate)")), filter)
+            .bind(years.from_year)
+            .bind(years.to_year)
+            .fetch_all(&self.pool)
+            .await?;
+
+        let rows: Vec<CustomerYearSales> = map_rows(&rows)?;
+        Ok(CustomerByYear::pivot(rows, years))
+    }
+
+    async fn get_customer_profile(&self, customer_id: i32, filter: &ReportFilter, query: &CustomerProfileQuery) -> Result<Option<CustomerProfile>, Error> {
+        let customer = sqlx::query("
+            SELECT custid AS customer_id
+                , companyname AS customer_name
+                , contactname AS contact_name
+                , contacttitle AS contact_title
+                , city
+                , country
+                , phone
+            FROM Sales.Customers
+            WHERE custid = ?1")
+            .bind(customer_id)
+            .fetch_optional(&self.pool)
+            .await?;
+        let Some(customer) = customer else {
+            return Ok(None);
+        };
+        let customer = CustomerInfo::from_row(&customer)?;
+
+        // Totals, trend and employees come from one row per order, products from one row per product
+        let orders = bind_filter(sqlx::query(&format!(
+            "SELECT o.orderdate AS order_date
+                    , e.empid AS employee_id
+                    , e.lastname || ', ' || e.firstname AS employee_name
+                    , CAST(SUM(od.unitprice * od.qty * (1 - od.discount)) AS REAL) AS order_value
+                FROM Sales.Orders AS o
+                JOIN Sales.OrderDetails AS od ON o.orderid = od.orderid
+                JOIN Sales.Customers AS c ON o.custid = c.custid
+                JOIN HR.Employees AS e ON o.empid = e.empid
+                WHERE {ORDER_FILTER}
+                    AND o.custid = ?7
+                GROUP BY o.orderid, o.orderdate, e.empid, e.lastname, e.firstname")), filter)
+            .bind(customer_id)
+            .fetch_all(&self.pool)
+            .await?;
+
+        let products = bind_filter(sqlx::query(&format!(
+            "SELECT p.productid AS product_id
+                    , p.productname AS product_name
+                    , COUNT(DISTINCT o.orderid) AS order_count
+                    , SUM(od.qty) AS quantity
+                    , CAST(SUM(od.unitprice * od.qty * (1 - od.discount)) AS REAL) AS revenue
+                FROM Sales.Orders AS o
+                JOIN Sales.OrderDetails AS od ON o.orderid = od.orderid
+                JOIN Production.Products AS p ON od.productid = p.productid
+                JOIN Sales.Customers AS c ON o.custid = c.custid
+                WHERE {ORDER_FILTER}
+                    AND o.custid = ?7
+                GROUP BY p.productid, p.productname")), filter)
+            .bind(customer_id)
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(Some(CustomerProfile::build(customer, map_rows(&orders)?, map_rows(&products)?, query)))
+    }
+
+    async fn get_top_performers(&self, filter: &ReportFilter, query: &TopPerformersQuery) -> Result<Vec<TopPerformers>, Error> {
+        let rows = bind_filter(sqlx::query(&format!(
+            "SELECT c.custid AS customer_id
+                    , c.companyname AS customer_name
+                    , e.lastname || ', ' || e.firstname AS employee_name
+                    , CAST(strftime('%Y', o.orderdate) AS INTEGER) AS order_year
+                    , COUNT(DISTINCT o.orderid) AS order_count
+                    , SUM(od.qty) AS quantity
+                    , CAST(SUM(od.unitprice * od.qty * (1 - od.discount)) AS REAL) AS sales
+                FROM Sales.Orders AS o
+                JOIN Sales.OrderDetails AS od ON o.orderid = od.orderid
+                JOIN Sales.Customers AS c ON o.custid = c.custid
+                JOIN HR.Employees AS e ON o.empid = e.empid
+                WHERE {ORDER_FILTER}
+                    AND (?7 IS NULL OR CAST(strftime('%Y', o.orderdate) AS INTEGER) = ?7)
+                GROUP BY c.custid, c.companyname, e.empid, e.lastname, e.firstname, strftime('%Y', o.orderdate)")), filter)
+            .bind(query.year)
+            .fetch_all(&self.pool)
+            .await?;
+
+        let rows: Vec<CustomerEmployeeSales> = map_rows(&rows)?;
+        Ok(TopPerformers::rank(rows, query))
+    }
+
+    async fn get_employee_performance(&self, filter: &ReportFilter, query: &EmployeePerformanceQuery) -> Result<Vec<EmployeePerformance>, Error> {
+        let period = period_sql(query.period);
+
+        let employees = sqlx::query("
+            SELECT empid AS employee_id
+                , lastname || ', ' || firstname AS employee_name
+                , title
+                , mgrid AS manager_id
+            FROM HR.Employees")
+            .fetch_all(&self.pool)
+            .await?;
+
+        let own = bind_filter(sqlx::query(&format!(
+            "SELECT o.empid AS employee_id
+                    , {period} AS period
+                    , CAST(SUM(od.unitprice * od.qty * (1 - od.discount)) AS REAL) AS revenue
+                    , COUNT(DISTINCT o.orderid) AS order_count
+                    , COUNT(DISTINCT o.custid) AS customers_served
+                    , CAST(AVG(od.discount) AS REAL) AS average_discount
+                FROM Sales.Orders AS o
+                JOIN Sales.OrderDetails AS od ON o.orderid = od.orderid
+                JOIN Sales.Customers AS c ON o.custid = c.custid
+                WHERE {ORDER_FILTER}
+                GROUP BY o.empid, {period}")), filter)
+            .fetch_all(&self.pool)
+            .await?;
+
+        // Every employee is paired with everyone below them, themselves included, and the
+        // team's orders are summed per manager
+        let team = bind_filter(sqlx::query(&format!(
+            "WITH RECURSIVE team AS (
+                    SELECT empid AS manager_id, empid AS member_id
+                    FROM HR.Employees
+                    UNION ALL
+                    SELECT t.manager_id, e.empid
+                    FROM team AS t
+                    JOIN HR.Employees AS e ON e.mgrid = t.member_id
+                )
+                SELECT t.manager_id AS employee_id
+                    , {period} AS period
+                    , CAST(SUM(od.unitprice * od.qty * (1 - od.discount)) AS REAL) AS revenue
+                    , COUNT(DISTINCT o.orderid) AS order_count
+                    , COUNT(DISTINCT o.custid) AS customers_served
+                    , CAST(AVG(od.discount) AS REAL) AS average_discount
+                FROM team AS t
+                JOIN Sales.Orders AS o ON o.empid = t.member_id
+                JOIN Sales.OrderDetails AS od ON o.orderid = od.orderid
+                JOIN Sales.Customers AS c ON o.custid = c.custid
+                WHERE {ORDER_FILTER}
+                GROUP BY t.manager_id, {period}")), filter)
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(EmployeePerformance::build(map_rows(&employees)?, map_rows(&own)?, map_rows(&team)?))
+    }
+
+    async fn get_sales_choropleth(&self, filter: &ReportFilter, query: &ChoroplethQuery) -> Result<Vec<SalesChoropleth>, Error> {
+        let rows = bind_filter(sqlx::query(&format!(
+            "SELECT c.country AS country
+                    , c.custid AS customer_id
+                    , CAST(strftime('%Y', o.orderdate) AS INTEGER) AS order_year
+                    , COUNT(*) AS order_count
+                    , CAST(SUM(o.freight) AS REAL) AS freight
+                    , CAST(SUM(ov.order_value) AS REAL) AS revenue
+                FROM Sales.Orders AS o
+                JOIN (
+                    SELECT orderid, SUM(unitprice * qty * (1 - discount)) AS order_value
+                    FROM Sales.OrderDetails
+                    GROUP BY orderid
+                ) AS ov ON ov.orderid = o.orderid
+                JOIN Sales.Customers AS c ON o.custid = c.custid
+                WHERE {ORDER_FILTER}
+                    AND (?7 IS NULL OR CAST(strftime('%Y', o.orderdate) AS INTEGER) = ?7)
+                GROUP BY c.country, c.custid, strftime('%Y', o.orderdate)")), filter)
+            .bind(query.year)
+            .fetch_all(&self.pool)
+            .await?;
+
+        let rows: Vec<CountryCustomerSales> = map_rows(&rows)?;
+        let date_range = filter.from_date.is_some() || filter.to_date.is_some();
+        Ok(SalesChoropleth::aggregate(rows, query, date_range))
+    }
+
+    async fn get_shipper_performance(&self, filter: &ReportFilter, query: &ShipperPerformanceQuery) -> Result<Vec<ShipperPerformance>, Error> {
+        let period = period_sql(query.period.unwrap_or_default());
+
+        let rows = bind_filter(sqlx::query(&format!(
+            "SELECT sh.shipperid AS shipper_id
+                    , sh.companyname AS shipper_name
+                    , o.shipcountry AS ship_country
+                    , {period} AS period
+                    , COUNT(*) AS order_count
+                    , COUNT(o.shippeddate) AS shipped_count
+                    , SUM(CASE WHEN o.shippeddate <= o.requireddate THEN 1 ELSE 0 END) AS on_time_count
+                    , SUM(CAST(julianday(o.shippeddate) - julianday(o.requireddate) AS INTEGER)) AS days_late
+                    , CAST(SUM(o.freight) AS REAL) AS freight
+                    , CAST(SUM(ov.order_value) AS REAL) AS order_value
+                FROM Sales.Orders AS o
+                JOIN (
+                    SELECT orderid, SUM(unitprice * qty * (1 - discount)) AS order_value
+                    FROM Sales.OrderDetails
+                    GROUP BY orderid
+                ) AS ov ON ov.orderid = o.orderid
+                JOIN Sales.Shippers AS sh ON o.shipperid = sh.shipperid
+                JOIN Sales.Customers AS c ON o.custid = c.custid
+                WHERE {ORDER_FILTER}
+                GROUP BY sh.shipperid, sh.companyname, o.shipcountry, {period}")), filter)
+            .fetch_all(&self.pool)
+            .await?;
+
+        let rows: Vec<ShipmentTotals> = map_rows(&rows)?;
+        Ok(ShipperPerformance::aggregate(rows, query))
+    }
+
+    async fn get_product_sales(&self, filter: &ReportFilter, query: &ProductSalesQuery) -> Result<Vec<ProductSales>, Error> {
+        // Without a period the rows are not grouped by one, so customers and orders are counted once
+        let (period, group_period) = match query.period {
+            Some(period) => (period_sql(period), format!(", {}", period_sql(period))),
+            None => ("''", String::new()),
+        };
+
+        let rows = bind_filter(sqlx::query(&format!(
+            "SELECT p.productid AS product_id
+                    , p.productname AS product_name
+                    , p.categoryid AS category_id
+                    , cat.categoryname AS category_name
+                    , CAST(p.unitprice AS REAL) AS list_price
+                    , p.discontinued AS discontinued
+                    , {period} AS period
+                    , CAST(SUM(od.unitprice * od.qty * (1 - od.discount)) AS REAL) AS revenue
+                    , CAST(SUM(od.unitprice * od.qty) AS REAL) AS gross_revenue
+                    , CAST(SUM(p.unitprice * od.qty) AS REAL) AS list_revenue
+                    , SUM(od.qty) AS units
+                    , COUNT(DISTINCT o.orderid) AS order_count
+                    , COUNT(DISTINCT o.custid) AS customer_count
+                FROM Sales.Orders AS o
+                JOIN Sales.OrderDetails AS od ON o.orderid = od.orderid
+                JOIN Production.Products AS p ON od.productid = p.productid
+                JOIN Production.Categories AS cat ON p.categoryid = cat.categoryid
+                JOIN Sales.Customers AS c ON o.custid = c.custid
+                WHERE {ORDER_FILTER}
+                    AND (?7 IS NULL OR p.categoryid = ?7)
+                GROUP BY p.productid, p.productname, p.categoryid, cat.categoryname, p.unitprice, p.discontinued{group_period}")), filter)
+            .bind(query.category_id)
+            .fetch_all(&self.pool)
+            .await?;
+
+        let rows: Vec<ProductPeriodSales> = map_rows(&rows)?;
+
+        // Products without sales only matter for the bottom of the ranking
+        let catalogue: Vec<ProductInfo> = match query.bottom {
+            Some(_) => {
+                let products = sqlx::query("
+                    SELECT p.productid AS product_id
+                        , p.productname AS product_name
+                        , p.categoryid AS category_id
+                        , cat.categoryname AS category_name
+                        , CAST(p.unitprice AS REAL) AS list_price
+                        , p.discontinued AS discontinued
+                    FROM Production.Products AS p
+                    JOIN Production.Categories AS cat ON p.categoryid = cat.categoryid
+                    WHERE ?1 IS NULL OR p.categoryid = ?1")
+                    .bind(query.category_id)
+                    .fetch_all(&self.pool)
+                    .await?;
+                map_rows(&products)?
+            }
+            None => Vec::new(),
+        };
+        Ok(ProductSales::rank(rows, catalogue, query))
+    }
+
+    async fn get_category_sales(&self, filter: &ReportFilter, query: &ProductSalesQuery) -> Result<Vec<CategorySales>, Error> {
+        let (period, group_period) = match query.period {
+            Some(period) => (period_sql(period), format!(", {}", period_sql(period))),
+            None => ("''", String::new()),
+        };
+
+        let rows = bind_filter(sqlx::query(&format!(
+            "SELECT cat.categoryid AS category_id
+                    , cat.categoryname AS category_name
+                    , {period} AS period
+                    , COUNT(DISTINCT p.productid) AS product_count
+                    , CAST(SUM(od.unitprice * od.qty * (1 - od.discount)) AS REAL) AS revenue
+                    , CAST(SUM(od.unitprice * od.qty) AS REAL) AS gross_revenue
+                    , CAST(SUM(p.unitprice * od.qty) AS REAL) AS list_revenue
+                    , SUM(od.qty) AS units
+                    , COUNT(DISTINCT o.orderid) AS order_count
+                    , COUNT(DISTINCT o.custid) AS customer_count
+                FROM Sales.Orders AS o
+                JOIN Sales.OrderDetails AS od ON o.orderid = od.orderid
+                JOIN Production.Products AS p ON od.productid = p.productid
+                JOIN Production.Categories AS cat ON p.categoryid = cat.categoryid
+                JOIN Sales.Customers AS c ON o.custid = c.custid
+                WHERE {ORDER_FILTER}
+                    AND (?7 IS NULL OR p.categoryid = ?7)
+                GROUP BY cat.categoryid, cat.categoryname{group_period}")), filter)
+            .bind(query.category_id)
+            .fetch_all(&self.pool)
+            .await?;
+
+        let rows: Vec<CategoryPeriodSales> = map_rows(&rows)?;
+        Ok(CategorySales::rank(rows, query))
+    }
+
+    async fn get_sales_timeseries(&self, filter: &ReportFilter, query: &TimeSeriesQuery) -> Result<Vec<TimeSeriesPoint>, Error> {
+        let history = TimeSeriesPoint::history_filter(filter, query);
+
+        let rows = bind_filter(sqlx::query(&format!(
+            "SELECT o.orderdate AS order_date
+                    , COUNT(*) AS order_count
+                    , CAST(SUM(o.freight) AS REAL) AS freight
+                    , CAST(SUM(ov.order_value) AS REAL) AS revenue
+                FROM Sales.Orders AS o
+                JOIN (
+                    SELECT orderid, SUM(unitprice * qty * (1 - discount)) AS order_value
+                    FROM Sales.OrderDetails
+                    GROUP BY orderid
+                ) AS ov ON ov.orderid = o.orderid
+                JOIN Sales.Customers AS c ON o.custid = c.custid
+                WHERE {ORDER_FILTER}
+                GROUP BY o.orderdate")), &history)
+            .fetch_all(&self.pool)
+            .await?;
+
+        let rows: Vec<DailySales> = map_rows(&rows)?;
+        Ok(TimeSeriesPoint::build(rows, filter, query))
+    }
+
+    async fn get_rfm_segments(&self, filter: &ReportFilter, query: &RfmQuery) -> Result<Option<RfmReport>, Error> {
+        let orders = query.order_filter(filter);
+
+        let rows = bind_filter(sqlx::query(&format!(
+            "SELECT c.custid AS customer_id
+                    , c.companyname AS customer_name
+                    , c.country AS country
+                    , MAX(o.orderdate) AS last_order_date
+                    , COUNT(*) AS order_count
+                    , CAST(SUM(ov.order_value) AS REAL) AS monetary
+                FROM Sales.Orders AS o
+                JOIN (
+                    SELECT orderid, SUM(unitprice * qty * (1 - discount)) AS order_value
+                    FROM Sales.OrderDetails
+                    GROUP BY orderid
+                ) AS ov ON ov.orderid = o.orderid
+                JOIN Sales.Customers AS c ON o.custid = c.custid
+                WHERE {ORDER_FILTER}
+                GROUP BY c.custid, c.companyname, c.country")), &orders)
+            .fetch_all(&self.pool)
+            .await?;
+
+        let rows: Vec<CustomerOrderTotals> = map_rows(&rows)?;
+        Ok(RfmReport::build(rows, filter, query))
+    }
+
+    async fn get_cohort_retention(&self, filter: &ReportFilter, query: &CohortQuery) -> Result<Vec<CohortRetention>, Error> {
+        let orders = query.order_filter(filter);
+
+        let rows = bind_filter(sqlx::query(&format!(
+            "SELECT o.custid AS customer_id
+                    , o.orderdate AS order_date
+                    , CAST(ov.order_value AS REAL) AS order_value
+                FROM Sales.Orders AS o
+                JOIN (
+                    SELECT orderid, SUM(unitprice * qty * (1 - discount)) AS order_value
+                    FROM Sales.OrderDetails
+                    GROUP BY orderid
+                ) AS ov ON ov.orderid = o.orderid
+                JOIN Sales.Customers AS c ON o.custid = c.custid
+                WHERE {ORDER_FILTER}")), &orders)
+            .fetch_all(&self.pool)
+            .await?;
+
+        let rows: Vec<CustomerOrder> = map_rows(&rows)?;
+        Ok(CohortRetention::build(rows, filter, query))
+    }
+
+    async fn get_sales_forecast(&self, filter: &ReportFilter, query: &SalesForecastQuery) -> Result<Vec<SalesForecast>, Error> {
+        let series = match query.by {
+            ForecastGrouping::Total => "'Total'",
+            ForecastGrouping::Country => "c.country",
+            ForecastGrouping::Category => "cat.categoryname",
+        };
+        // A constant series name is not grouped by
+        let group_series = match query.by {
+            ForecastGrouping::Total => String::new(),
+            _ => format!("{}, ", series),
+        };
+        let period = period_sql(ReportPeriod::Month);
+
+        let rows = bind_filter(sqlx::query(&format!(
+            "SELECT {series} AS series
+                    , {period} AS period
+                    , CAST(SUM(od.unitprice * od.qty * (1 - od.discount)) AS REAL) AS revenue
+                FROM Sales.Orders AS o
+                JOIN Sales.OrderDetails AS od ON o.orderid = od.orderid
+                JOIN Production.Products AS p ON od.productid = p.productid
+                JOIN Production.Categories AS cat ON p.categoryid = cat.categoryid
+                JOIN Sales.Customers AS c ON o.custid = c.custid
+                WHERE {ORDER_FILTER}
+                GROUP BY {group_series}{period}")), filter)
+            .fetch_all(&self.pool)
+            .await?;
+
+        let rows: Vec<MonthlyRevenue> = map_rows(&rows)?;
+        Ok(SalesForecast::build(rows, filter, query))
+    }
+
+    async fn get_abc_analysis(&self, filter: &ReportFilter, query: &AbcQuery) -> Result<Option<AbcReport>, Error> {
+        let (id, name) = match query.dimension {
+            AbcDimension::Customer => ("c.custid", "c.companyname"),
+            AbcDimension::Product => ("p.productid", "p.productname"),
+        };
+
+        let rows = bind_filter(sqlx::query(&format!(
+            "SELECT {id} AS id
+                    , {name} AS name
+                    , CAST(SUM(od.unitprice * od.qty * (1 - od.discount)) AS REAL) AS revenue
+                FROM Sales.Orders AS o
+                JOIN Sales.OrderDetails AS od ON o.orderid = od.orderid
+                JOIN Production.Products AS p ON od.productid = p.productid
+                JOIN Sales.Customers AS c ON o.custid = c.custid
+                WHERE {ORDER_FILTER}
+                GROUP BY {id}, {name}")), filter)
+            .fetch_all(&self.pool)
+            .await?;
+
+        let rows: Vec<ItemRevenue> = map_rows(&rows)?;
+        Ok(AbcReport::build(rows, query))
+    }
+
+    async fn get_market_basket(&self, filter: &ReportFilter, query: &MarketBasketQuery) -> Result<Vec<ProductPair>, Error> {
+        let rows = bind_filter(sqlx::query(&order_products_sql()), filter)
+            .fetch_all(&self.pool)
+            .await?;
+
+        let rows: Vec<OrderProduct> = map_rows(&rows)?;
+        Ok(ProductPair::build(rows, query))
+    }
+
+    async fn get_bought_with(&self, product_id: i32, filter: &ReportFilter, query: &MarketBasketQuery) -> Result<Option<BoughtWithReport>, Error> {
+        let rows = bind_filter(sqlx::query(&order_products_sql()), filter)
+            .fetch_all(&self.pool)
+            .await?;
+
+        let rows: Vec<OrderProduct> = map_rows(&rows)?;
+        Ok(BoughtWithReport::build(rows, product_id, query))
+    }
+
+    fn pool_stats(&self) -> Option<PoolStats> {
+        Some(PoolStats::from_sqlx_pool(&self.pool))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn pool_stats_come_from_the_sqlx_pool() {
+        let directory = std::env::temp_dir().join(format!("mssqlapi-sqlite-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&directory);
+        let sqlite = DatabaseSQLite::init(directory.to_str().unwrap()).await.unwrap();
+
+        let stats = sqlite.pool_stats().unwrap();
+        assert_eq!(stats.max_size, sqlite.pool.options().get_max_connections());
+        assert!(stats.connections >= 1 && stats.idle_connections <= stats.connections);
+        assert!(stats.counters.is_none());
+
+        sqlite.pool.close().await;
+        let _ = fs::remove_dir_all(&directory);
+    }
+}
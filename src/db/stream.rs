@@ -0,0 +1,51 @@
+use anyhow::{Error, Result};
+use futures::stream::{self, BoxStream, StreamExt};
+use std::future::Future;
+use tokio::sync::mpsc;
+
+// Rows travel from a producer task to the HTTP response through a bounded channel, so a
+// streamed report holds at most STREAM_BUFFER_ROWS rows in memory however large it is.
+pub const STREAM_BUFFER_ROWS: usize = 256;
+
+pub type RowStream<T> = BoxStream<'static, Result<T, Error>>;
+pub type RowSender<T> = mpsc::Sender<Result<T, Error>>;
+
+// Runs `produce` on its own task and hands back the rows it sends. When the receiving side
+// goes away (client disconnected, report timed out) the producer is dropped at its next
+// await point, which drops the database connection and the query running on it.
+pub fn spawn_row_stream<T, F, Fut>(produce: F) -> RowStream<T>
+where
+    T: Send + 'static,
+    F: FnOnce(RowSender<T>) -> Fut,
+    Fut: Future<Output = Result<(), Error>> + Send + 'static,
+{
+    let (tx, rx) = mpsc::channel(STREAM_BUFFER_ROWS);
+    let errors = tx.clone();
+    let work = produce(tx);
+
+    tokio::spawn(async move {
+        tokio::select! {
+            result = work => {
+                if let Err(e) = result {
+                    let _ = errors.send(Err(e)).await;
+                }
+            }
+            _ = errors.closed() => {}
+        }
+    });
+
+    stream::unfold(rx, |mut rx| async move { rx.recv().await.map(|row| (row, rx)) }).boxed()
+}
+
+// Sends one row, returning false once nobody is listening any more.
+pub async fn send_row<T>(tx: &RowSender<T>, row: T) -> bool {
+    tx.send(Ok(row)).await.is_ok()
+}
+
+// For backends that already hold the whole report in memory.
+pub fn iter_stream<T: Send + 'static>(rows: Result<Vec<T>, Error>) -> RowStream<T> {
+    match rows {
+        Ok(rows) => stream::iter(rows.into_iter().map(Ok)).boxed(),
+        Err(e) => stream::iter([Err(e)]).boxed(),
+    }
+}
@@ -0,0 +1,198 @@
+use anyhow::{Error, Result};
+use futures::stream::{self, StreamExt};
+use std::collections::HashMap;
+use std::env;
+use std::fmt;
+use std::future::Future;
+use std::time::Duration;
+
+use crate::db::pool::env_or;
+use crate::db::stream::RowStream;
+
+// Returned when a report takes longer than its configured timeout, so the API can answer
+// 504 instead of 500 and the dashboard can tell a slow query from a broken one.
+#[derive(Debug)]
+pub struct QueryTimeout {
+    pub report: String,
+    pub after: Duration,
+}
+
+impl fmt::Display for QueryTimeout {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} did not finish within {} seconds", self.report, self.after.as_secs())
+    }
+}
+
+impl std::error::Error for QueryTimeout {}
+
+// REPORT_TIMEOUT_SECS applies to every report, REPORT_TIMEOUTS overrides single reports:
+// REPORT_TIMEOUTS="top_performers=120,orders_report=60"
+#[derive(Debug, Clone)]
+pub struct ReportTimeouts {
+    pub default: Duration,
+    pub overrides: HashMap<String, Duration>,
+}
+
+impl ReportTimeouts {
+    pub fn from_env() -> Result<Self, Error> {
+        let default = Duration::from_secs(env_or("REPORT_TIMEOUT_SECS", 30)?);
+
+        let mut overrides = HashMap::new();
+        for entry in env::var("REPORT_TIMEOUTS").unwrap_or_default().split(',').filter(|e| !e.trim().is_empty()) {
+            let (report, secs) = entry.split_once('=')
+                .ok_or_else(|| Error::msg(format!("REPORT_TIMEOUTS entry must look like report=seconds: {}", entry)))?;
+            let secs: u64 = secs.trim().parse()
+                .map_err(|_| Error::msg(format!("REPORT_TIMEOUTS has an invalid value for {}: {}", report, secs)))?;
+            overrides.insert(report.trim().to_string(), Duration::from_secs(secs));
+        }
+
+        Ok(ReportTimeouts { default, overrides })
+    }
+
+    pub fn timeout_for(&self, report: &str) -> Duration {
+        self.overrides.get(report).copied().unwrap_or(self.default)
+    }
+
+    // Dropping the report future on timeout cancels the database request with it.
+    pub async fn run<T, F>(&self, report: &str, future: F) -> Result<T, Error>
+    where
+        F: Future<Output = Result<T, Error>>,
+    {
+        let after = self.timeout_for(report);
+        match tokio::time::timeout(after, future).await {
+            Ok(result) => result,
+            Err(_) => Err(QueryTimeout { report: report.to_string(), after }.into()),
+        }
+    }
+
+    // Same deadline for a streamed report, counted from the start to the last row. Running out
+    // ends the stream with a QueryTimeout and drops the producer with its connection.
+    pub fn limit_stream<T: Send + 'static>(&self, report: &str, rows: RowStream<T>) -> RowStream<T> {
+        let after = self.timeout_for(report);
+        let deadline = tokio::time::Instant::now() + after;
+        let report = report.to_string();
+
+        stream::unfold(Some(rows), move |rows| {
+            let report = report.clone();
+            async move {
+                let mut rows = rows?;
+                match tokio::time::timeout_at(deadline, rows.next()).await {
+                    Ok(Some(row)) => Some((row, Some(rows))),
+                    Ok(None) => None,
+                    Err(_) => Some((Err(QueryTimeout { report, after }.into()), None)),
+                }
+            }
+        })
+        .boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::http::StatusCode;
+    use std::io;
+
+    use crate::api::mssqlapi::error_response;
+    use crate::db::stream::iter_stream;
+
+    fn timeouts(default_ms: u64) -> ReportTimeouts {
+        ReportTimeouts { default: Duration::from_millis(default_ms), overrides: HashMap::from([("slow_report".to_string(), Duration::from_secs(5))]) }
+    }
+
+    async fn slow_query(ms: u64) -> Result<i32, Error> {
+        tokio::time::sleep(Duration::from_millis(ms)).await;
+        Ok(1)
+    }
+
+    #[tokio::test]
+    async fn a_report_past_its_timeout_is_a_504() {
+        let timeouts = timeouts(20);
+        assert_eq!(timeouts.run("top_performers", slow_query(0)).await.unwrap(), 1);
+        assert_eq!(timeouts.run("slow_report", slow_query(50)).await.unwrap(), 1);
+
+        let e = timeouts.run("top_performers", slow_query(5000)).await.unwrap_err();
+        assert_eq!(e.downcast_ref::<QueryTimeout>().unwrap().report, "top_performers");
+        assert_eq!(error_response(e, "Error retrieving Top Performers").status(), StatusCode::GATEWAY_TIMEOUT);
+        assert_eq!(error_response(Error::msg("bad SQL"), "Error retrieving Top Performers").status(), StatusCode::INTERNAL_SERVER_ERROR);
+    }
+
+    #[tokio::test]
+    async fn a_streamed_report_ends_with_a_timeout() {
+        let rows = iter_stream(Ok(vec![1, 2])).chain(stream::once(slow_query(5000))).boxed();
+        let rows: Vec<Result<i32, Error>> = timeouts(20).limit_stream("orders_report", rows).collect().await;
+
+        assert_eq!(rows.len(), 3);
+        assert_eq!((rows[0].as_ref().unwrap(), rows[1].as_ref().unwrap()), (&1, &2));
+        assert!(rows[2].as_ref().unwrap_err().downcast_ref::<QueryTimeout>().is_some());
+    }
+
+    // A connection that flags a running query like TdsConnection does.
+    struct FlaggedConnection {
+        in_flight: bool,
+    }
+
+    struct FlaggedManager;
+
+    impl bb8::ManageConnection for FlaggedManager {
+        type Connection = FlaggedConnection;
+        type Error = io::Error;
+
+        async fn connect(&self) -> Result<Self::Connection, Self::Error> {
+            Ok(FlaggedConnection { in_flight: false })
+        }
+
+        async fn is_valid(&self, _conn: &mut Self::Connection) -> Result<(), Self::Error> {
+            Ok(())
+        }
+
+        fn has_broken(&self, conn: &mut Self::Connection) -> bool {
+            conn.in_flight
+        }
+    }
+
+    #[tokio::test]
+    async fn a_cancelled_query_discards_its_connection() {
+        let pool = bb8::Pool::builder().max_size(1).build(FlaggedManager).await.unwrap();
+        let query = |ms: u64| {
+            let pool = pool.clone();
+            async move {
+                let mut conn = pool.get().await.map_err(|e| Error::msg(e.to_string()))?;
+                conn.in_flight = true;
+                let rows = slow_query(ms).await;
+                conn.in_flight = false;
+                rows
+            }
+        };
+
+        let timeouts = timeouts(20);
+        timeouts.run("top_performers", query(0)).await.unwrap();
+        assert_eq!(pool.state().statistics.connections_closed_broken, 0);
+
+        let e = timeouts.run("top_performers", query(5000)).await.unwrap_err();
+        assert!(e.downcast_ref::<QueryTimeout>().is_some());
+        assert_eq!(pool.state().statistics.connections_closed_broken, 1);
+
+        // The next report gets a fresh connection
+        timeouts.run("top_performers", query(0)).await.unwrap();
+        assert_eq!(pool.state().statistics.connections_created, 2);
+    }
+
+    // The only test that sets the timeout variables
+    #[test]
+    fn timeouts_from_env() {
+        env::set_var("REPORT_TIMEOUT_SECS", "10");
+        env::set_var("REPORT_TIMEOUTS", "top_performers=120, orders_report = 60,");
+        let timeouts = ReportTimeouts::from_env().unwrap();
+        assert_eq!(timeouts.timeout_for("top_performers"), Duration::from_secs(120));
+        assert_eq!(timeouts.timeout_for("orders_report"), Duration::from_secs(60));
+        assert_eq!(timeouts.timeout_for("order_detail"), Duration::from_secs(10));
+
+        for invalid in ["top_performers", "top_performers=soon", "top_performers=-1"] {
+            env::set_var("REPORT_TIMEOUTS", invalid);
+            assert!(ReportTimeouts::from_env().is_err(), "{}", invalid);
+        }
+        env::remove_var("REPORT_TIMEOUTS");
+        env::remove_var("REPORT_TIMEOUT_SECS");
+    }
+}
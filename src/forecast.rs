@@ -0,0 +1,207 @@
+// Forecasting for monthly series, in plain Rust so it runs wherever the service does.
+// Additive Holt-Winters when there are two full seasons of history, a seasonal naive forecast
+// with drift when there is at least one, and normal prediction intervals around both.
+
+pub struct Forecast {
+    pub points: Vec<f64>,
+    // Standard deviation of the forecast error, per step ahead
+    pub errors: Vec<f64>,
+    // Standard deviation of the one-step-ahead errors in the history
+    pub residual_sd: f64,
+}
+
+// Smoothing weights of the level, trend and seasonal components.
+#[derive(Debug, Clone, Copy)]
+struct HoltWintersParams {
+    alpha: f64,
+    beta: f64,
+    gamma: f64,
+}
+
+struct HoltWintersFit {
+    level: f64,
+    trend: f64,
+    seasonal: Vec<f64>,
+    sse: f64,
+    errors: usize,
+}
+
+fn holt_winters_fit(series: &[f64], season: usize, params: HoltWintersParams) -> HoltWintersFit {
+    let HoltWintersParams { alpha, beta, gamma } = params;
+    let first = series[..season].iter().sum::<f64>() / season as f64;
+    let second = series[season..2 * season].iter().sum::<f64>() / season as f64;
+
+    let mut level = first;
+    let mut trend = (second - first) / season as f64;
+    let mut seasonal: Vec<f64> = series[..season].iter().map(|y| y - first).collect();
+    let mut sse = 0.0;
+
+    for (t, &y) in series.iter().enumerate().skip(season) {
+        let s = seasonal[t - season];
+        let error = y - (level + trend + s);
+        sse += error * error;
+
+        let previous = level;
+        level = alpha * (y - s) + (1.0 - alpha) * (level + trend);
+        trend = beta * (level - previous) + (1.0 - beta) * trend;
+        seasonal.push(gamma * (y - level) + (1.0 - gamma) * s);
+    }
+
+    HoltWintersFit { level, trend, seasonal, sse, errors: series.len() - season }
+}
+
+// Additive Holt-Winters with the smoothing weights picked from a 0.1 grid by the smallest
+// one-step-ahead squared error. Needs two full seasons.
+pub fn holt_winters(series: &[f64], season: usize, horizon: usize) -> Option<Forecast> {
+    if season == 0 || series.len() < 2 * season {
+        return None;
+    }
+
+    let grid: Vec<f64> = (1..10).map(|i| i as f64 / 10.0).collect();
+    let mut best: Option<(HoltWintersFit, HoltWintersParams)> = None;
+    for &alpha in &grid {
+        for &beta in &grid {
+            for &gamma in &grid {
+                let params = HoltWintersParams { alpha, beta, gamma };
+                let fit = holt_winters_fit(series, season, params);
+                if best.as_ref().is_none_or(|(b, _)| fit.sse < b.sse) {
+                    best = Some((fit, params));
+                }
+            }
+        }
+    }
+    let (fit, params) = best?;
+
+    let n = series.len();
+    let residual_sd = (fit.sse / fit.errors.max(1) as f64).sqrt();
+    let points = (1..=horizon)
+        .map(|h| fit.level + h as f64 * fit.trend + fit.seasonal[n - season + (h - 1) % season])
+        .collect();
+
+    // Variance of the h-step error of the additive model (Hyndman et al., 2008)
+    let errors = (1..=horizon)
+        .map(|h| {
+            let variance: f64 = (1..h)
+                .map(|j| {
+                    let seasonal = if j % season == 0 { params.gamma * (1.0 - params.alpha) } else { 0.0 };
+                    let c = params.alpha * (1.0 + j as f64 * params.beta) + seasonal;
+                    c * c
+                })
+                .sum();
+            residual_sd * (1.0 + variance).sqrt()
+        })
+        .collect();
+
+    Some(Forecast { points, errors, residual_sd })
+}
+
+// The value of the same month a year earlier, plus the average yearly change when there are
+// two seasons to measure it. Needs one full season and one more value.
+pub fn seasonal_naive(series: &[f64], season: usize, horizon: usize) -> Option<Forecast> {
+    let n = series.len();
+    if season == 0 || n <= season {
+        return None;
+    }
+
+    let drift = if n >= 2 * season {
+        let last: f64 = series[n - season..].iter().sum();
+        let previous: f64 = series[n - 2 * season..n - season].iter().sum();
+        (last - previous) / season as f64
+    } else {
+        0.0
+    };
+
+    let residuals: Vec<f64> = (season..n).map(|t| series[t] - series[t - season] - drift).collect();
+    let residual_sd = (residuals.iter().map(|e| e * e).sum::<f64>() / residuals.len() as f64).sqrt();
+
+    let mut points = Vec::with_capacity(horizon);
+    let mut errors = Vec::with_capacity(horizon);
+    for h in 1..=horizon {
+        let years = h.div_ceil(season);
+        points.push(series[n - season + (h - 1) % season] + years as f64 * drift);
+        errors.push(residual_sd * (years as f64).sqrt());
+    }
+
+    Some(Forecast { points, errors, residual_sd })
+}
+
+// Two-sided standard normal quantile for a confidence level in percent, 1.96 for 95.
+// Acklam's rational approximation of the inverse normal distribution.
+pub fn normal_quantile(level: f64) -> f64 {
+    const A: [f64; 6] = [-3.969683028665376e1, 2.209460984245205e2, -2.759285104469687e2, 1.38357751867269e2, -3.066479806614716e1, 2.506628277459239];
+    const B: [f64; 5] = [-5.447609879822406e1, 1.615858368580409e2, -1.556989798598866e2, 6.680131188771972e1, -1.328068155288572e1];
+    const C: [f64; 6] = [-7.784894002430293e-3, -3.223964580411365e-1, -2.400758277161838, -2.549732539343734, 4.374664141464968, 2.938163982698783];
+    const D: [f64; 4] = [7.784695709041462e-3, 3.224671290700398e-1, 2.445134137142996, 3.754408661907416];
+
+    let p = 0.5 + level / 200.0;
+    if p > 1.0 - 0.02425 {
+        let q = (-2.0 * (1.0 - p).ln()).sqrt();
+        -(((((C[0] * q + C[1]) * q + C[2]) * q + C[3]) * q + C[4]) * q + C[5]) / ((((D[0] * q + D[1]) * q + D[2]) * q + D[3]) * q + 1.0)
+    } else {
+        let q = p - 0.5;
+        let r = q * q;
+        (((((A[0] * r + A[1]) * r + A[2]) * r + A[3]) * r + A[4]) * r + A[5]) * q / (((((B[0] * r + B[1]) * r + B[2]) * r + B[3]) * r + B[4]) * r + 1.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: &[f64], expected: &[f64]) {
+        assert_eq!(actual.len(), expected.len());
+        for (a, e) in actual.iter().zip(expected) {
+            assert!((a - e).abs() < 1e-6, "{actual:?} != {expected:?}");
+        }
+    }
+
+    #[test]
+    fn normal_quantiles_match_the_tables() {
+        for (level, z) in [(50.0, 0.6745), (90.0, 1.6449), (95.0, 1.9600), (99.0, 2.5758)] {
+            assert!((normal_quantile(level) - z).abs() < 1e-4, "level {level}: {}", normal_quantile(level));
+        }
+    }
+
+    #[test]
+    fn flat_series_forecast_flat() {
+        let series = vec![100.0; 24];
+        for fit in [holt_winters(&series, 12, 6).unwrap(), seasonal_naive(&series, 12, 6).unwrap()] {
+            assert_close(&fit.points, &[100.0; 6]);
+            assert_close(&fit.errors, &[0.0; 6]);
+            assert_eq!(fit.residual_sd, 0.0);
+        }
+    }
+
+    #[test]
+    fn seasonal_naive_repeats_a_pure_season() {
+        let season = [10.0, 20.0, 30.0, 40.0];
+        let series: Vec<f64> = season.iter().cycle().take(12).copied().collect();
+        let fit = seasonal_naive(&series, 4, 6).unwrap();
+        assert_close(&fit.points, &[10.0, 20.0, 30.0, 40.0, 10.0, 20.0]);
+        assert_eq!(fit.residual_sd, 0.0);
+
+        let fit = holt_winters(&series, 4, 4).unwrap();
+        assert_close(&fit.points, &season);
+    }
+
+    #[test]
+    fn seasonal_naive_adds_the_yearly_drift() {
+        // The second year is 4 higher every month: drift 4 per year, one more year per season ahead
+        let series = [1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0, 8.0];
+        let fit = seasonal_naive(&series, 4, 5).unwrap();
+        assert_close(&fit.points, &[9.0, 10.0, 11.0, 12.0, 13.0]);
+        assert_eq!(fit.residual_sd, 0.0);
+
+        // One season and a value: no drift, the errors grow with the square root of the years ahead
+        let fit = seasonal_naive(&[1.0, 2.0, 3.0, 4.0, 3.0], 4, 5).unwrap();
+        assert_close(&fit.points, &[2.0, 3.0, 4.0, 3.0, 2.0]);
+        assert_close(&fit.errors, &[2.0, 2.0, 2.0, 2.0, 2.0 * 2f64.sqrt()]);
+    }
+
+    #[test]
+    fn short_series_are_not_forecast() {
+        assert!(holt_winters(&[1.0; 23], 12, 6).is_none());
+        assert!(seasonal_naive(&[1.0; 12], 12, 6).is_none());
+        assert!(seasonal_naive(&[1.0; 13], 0, 6).is_none());
+    }
+}
@@ -6,42 +6,67 @@ use std::io;
 use futures::future;
 
 mod api;
+mod countries;
 mod db;
+mod forecast;
 mod models;
 
-use crate::db::database::DatabaseMSSQL;
+use crate::db::init_repository;
+use crate::db::retry::{with_retry, RetryPolicy};
+use crate::db::timeout::ReportTimeouts;
 
-use api::mssqlapi::{ get_orders_report, get_customer_sales_by_year, get_top_performers, get_sales_choropleth};
+use api::mssqlapi::{ get_orders_report, query_orders_report, get_order_detail, get_customer_sales_by_year, get_customer_profile, get_top_performers, get_employee_performance, get_sales_choropleth, get_shipper_performance, get_product_sales, get_category_sales, get_sales_timeseries, get_rfm_segments, get_cohort_retention, get_sales_forecast, get_abc_analysis, get_market_basket, get_bought_with, get_pool_stats};
 
 #[actix_web::main]
 async fn main() -> io::Result<()> {
-    // Initialize the database
-    let db = match DatabaseMSSQL::init().await {
-        Ok(_) => {
-            println!("MSSQL database connection successful!");
-            Some(DatabaseMSSQL::init().await.unwrap())
+    // Initialize the database, waiting for it if it is still starting up
+    dotenv::dotenv().ok();
+    let startup_policy = RetryPolicy::startup_from_env().map_err(io::Error::other)?;
+    let db = match with_retry(&startup_policy, "Database initialization", init_repository).await {
+        Ok(db) => {
+            println!("Database connection successful!");
+            Some(db)
         }
-        Err(_) => {
-            println!("Failed to initialize MSSQL database");
+        Err(e) => {
+            println!("Failed to initialize database: {}", e);
             None
         }
     };
 
+    // Per-report query timeouts, the whole report is cancelled when one runs out
+    let timeouts = web::Data::new(ReportTimeouts::from_env().map_err(io::Error::other)?);
+
     let backend_server = if let Some(db) = db {
         HttpServer::new(move || {
             App::new()
                 .wrap(Cors::permissive())
-                .app_data(web::Data::new(db.clone()))
+                .app_data(web::Data::from(db.clone()))
+                .app_data(timeouts.clone())
                 // .wrap(Logger::default())
                 .service(get_orders_report)
+                .service(query_orders_report)
+                .service(get_order_detail)
                 .service(get_customer_sales_by_year)
+                .service(get_customer_profile)
                 .service(get_top_performers)
+                .service(get_employee_performance)
                 .service(get_sales_choropleth)
+                .service(get_shipper_performance)
+                .service(get_product_sales)
+                .service(get_category_sales)
+                .service(get_sales_timeseries)
+                .service(get_rfm_segments)
+                .service(get_cohort_retention)
+                .service(get_sales_forecast)
+                .service(get_abc_analysis)
+                .service(get_market_basket)
+                .service(get_bought_with)
+                .service(get_pool_stats)
         })
         .bind("127.0.0.1:8080")?
         .run()
     } else {
-        return Err(std::io::Error::new(std::io::ErrorKind::Other, "Failed to start MSSQL server"));
+        return Err(std::io::Error::other("Failed to start database server"));
     };
     println!("BACKEND server is running at http://127.0.0.1:8080");
 
@@ -0,0 +1,214 @@
+use mssqlapi_derive::FromRow;
+use serde::{Deserialize, Serialize};
+use validator::{Validate, ValidationError};
+
+// Customers or products ranked by revenue with their cumulative share, classified A, B or C.
+// The items are in rank order, so `revenue` and `cumulative_share` plot as a Pareto chart.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AbcReport {
+    pub dimension: AbcDimension,
+    pub a_threshold: f64,
+    pub b_threshold: f64,
+    pub total_revenue: f64,
+    pub classes: Vec<AbcClassSummary>,
+    pub items: Vec<AbcItem>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AbcItem {
+    pub rank: u32,
+    pub id: i32,
+    pub name: String,
+    pub revenue: f64,
+    // Percent of the total revenue, and of everything up to and including this item
+    pub share: f64,
+    pub cumulative_share: f64,
+    pub class: AbcClass,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AbcClassSummary {
+    pub class: AbcClass,
+    pub item_count: i64,
+    pub item_share: f64,
+    pub revenue: f64,
+    pub revenue_share: f64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum AbcClass {
+    A,
+    B,
+    C,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum AbcDimension {
+    #[default]
+    Customer,
+    Product,
+}
+
+// ?dimension=customer|product&a=80&b=95. An item is in class A while the revenue ranked
+// before it is below a% of the total, in B while it is below b%, and in C after that.
+#[derive(Deserialize, Validate, Debug, Default, Clone)]
+#[validate(schema(function = "validate_thresholds"))]
+pub struct AbcQuery {
+    #[serde(default)]
+    pub dimension: AbcDimension,
+    #[validate(range(min = 1.0, max = 99.0))]
+    pub a: Option<f64>,
+    #[validate(range(min = 1.0, max = 99.0))]
+    pub b: Option<f64>,
+}
+
+fn validate_thresholds(query: &AbcQuery) -> Result<(), ValidationError> {
+    if query.a() >= query.b() {
+        return Err(ValidationError::new("a_not_below_b"));
+    }
+    Ok(())
+}
+
+impl AbcQuery {
+    fn a(&self) -> f64 {
+        self.a.unwrap_or(80.0)
+    }
+
+    fn b(&self) -> f64 {
+        self.b.unwrap_or(95.0)
+    }
+}
+
+// Revenue per customer or product, as every backend returns them.
+#[derive(Debug, FromRow)]
+pub struct ItemRevenue {
+    pub id: i32,
+    pub name: String,
+    #[row(default)]
+    pub revenue: f64,
+}
+
+impl AbcReport {
+    pub fn build(mut rows: Vec<ItemRevenue>, query: &AbcQuery) -> Option<AbcReport> {
+        if rows.is_empty() {
+            return None;
+        }
+        rows.sort_by(|a, b| b.revenue.total_cmp(&a.revenue).then(a.id.cmp(&b.id)));
+
+        let total_revenue: f64 = rows.iter().map(|r| r.revenue).sum();
+        let percent = |revenue: f64| if total_revenue > 0.0 { revenue / total_revenue * 100.0 } else { 0.0 };
+
+        let mut cumulative = 0.0;
+        let items: Vec<AbcItem> = rows.into_iter()
+            .enumerate()
+            .map(|(i, row)| {
+                let before = percent(cumulative);
+                cumulative += row.revenue;
+                let class = if before < query.a() {
+                    AbcClass::A
+                } else if before < query.b() {
+                    AbcClass::B
+                } else {
+                    AbcClass::C
+                };
+                AbcItem {
+                    rank: i as u32 + 1,
+                    id: row.id,
+                    name: row.name,
+                    revenue: row.revenue,
+                    share: percent(row.revenue),
+                    cumulative_share: percent(cumulative),
+                    class,
+                }
+            })
+            .collect();
+
+        let classes = [AbcClass::A, AbcClass::B, AbcClass::C].into_iter()
+            .map(|class| {
+                let members: Vec<&AbcItem> = items.iter().filter(|i| i.class == class).collect();
+                let revenue = members.iter().map(|i| i.revenue).sum();
+                AbcClassSummary {
+                    class,
+                    item_count: members.len() as i64,
+                    item_share: members.len() as f64 / items.len() as f64 * 100.0,
+                    revenue,
+                    revenue_share: percent(revenue),
+                }
+            })
+            .collect();
+
+        Some(AbcReport {
+            dimension: query.dimension,
+            a_threshold: query.a(),
+            b_threshold: query.b(),
+            total_revenue,
+            classes,
+            items,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rows(revenues: &[(i32, f64)]) -> Vec<ItemRevenue> {
+        revenues.iter().map(|&(id, revenue)| ItemRevenue { id, name: format!("Item {id}"), revenue }).collect()
+    }
+
+    #[test]
+    fn items_are_classified_by_the_revenue_ranked_before_them() {
+        let report = AbcReport::build(rows(&[(6, 20.0), (1, 500.0), (4, 50.0), (2, 300.0), (5, 30.0), (3, 100.0)]), &AbcQuery::default()).unwrap();
+
+        assert_eq!(report.total_revenue, 1000.0);
+        let items: Vec<(u32, i32, f64, f64, AbcClass)> = report.items.iter()
+            .map(|i| (i.rank, i.id, i.share, i.cumulative_share, i.class))
+            .collect();
+        // Item 3 starts at exactly 80% and item 5 at exactly 95%, so both fall in the next class
+        assert_eq!(items, vec![
+            (1, 1, 50.0, 50.0, AbcClass::A),
+            (2, 2, 30.0, 80.0, AbcClass::A),
+            (3, 3, 10.0, 90.0, AbcClass::B),
+            (4, 4, 5.0, 95.0, AbcClass::B),
+            (5, 5, 3.0, 98.0, AbcClass::C),
+            (6, 6, 2.0, 100.0, AbcClass::C),
+        ]);
+
+        let classes: Vec<(AbcClass, i64, f64, f64)> = report.classes.iter()
+            .map(|c| (c.class, c.item_count, c.revenue, c.revenue_share))
+            .collect();
+        assert_eq!(classes, vec![(AbcClass::A, 2, 800.0, 80.0), (AbcClass::B, 2, 150.0, 15.0), (AbcClass::C, 2, 50.0, 5.0)]);
+        assert!(report.classes.iter().all(|c| (c.item_share - 100.0 / 3.0).abs() < 1e-9));
+    }
+
+    #[test]
+    fn thresholds_move_the_cut_offs() {
+        let query = AbcQuery { a: Some(50.0), b: Some(90.0), ..Default::default() };
+        let report = AbcReport::build(rows(&[(1, 500.0), (2, 300.0), (3, 100.0), (4, 100.0)]), &query).unwrap();
+        let classes: Vec<AbcClass> = report.items.iter().map(|i| i.class).collect();
+        assert_eq!(classes, vec![AbcClass::A, AbcClass::B, AbcClass::B, AbcClass::C]);
+        assert_eq!((report.a_threshold, report.b_threshold), (50.0, 90.0));
+    }
+
+    #[test]
+    fn ties_are_ranked_by_id() {
+        let report = AbcReport::build(rows(&[(3, 10.0), (1, 10.0), (2, 10.0)]), &AbcQuery::default()).unwrap();
+        assert_eq!(report.items.iter().map(|i| i.id).collect::<Vec<_>>(), vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn empty_and_zero_revenue() {
+        assert!(AbcReport::build(Vec::new(), &AbcQuery::default()).is_none());
+
+        let report = AbcReport::build(rows(&[(1, 0.0), (2, 0.0)]), &AbcQuery::default()).unwrap();
+        assert!(report.items.iter().all(|i| i.class == AbcClass::A && i.cumulative_share == 0.0));
+    }
+
+    #[test]
+    fn a_must_be_below_b() {
+        assert!(AbcQuery::default().validate().is_ok());
+        assert!(AbcQuery { a: Some(95.0), ..Default::default() }.validate().is_err());
+        assert!(AbcQuery { a: Some(60.0), b: Some(60.0), ..Default::default() }.validate().is_err());
+    }
+}
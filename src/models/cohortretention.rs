@@ -0,0 +1,180 @@
+use chrono::{Datelike, NaiveDate};
+use mssqlapi_derive::FromRow;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use validator::{Validate, ValidationErrors};
+
+use crate::models::reportfilter::{ReportFilter, ReportPeriod};
+
+// The customers whose first order fell in one period, and how many of them ordered again in
+// each period after it. Period 0 is the cohort's own period.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CohortRetention {
+    // "2023-05", "2023-Q2" or "2023"
+    pub cohort: String,
+    pub customers: i64,
+    pub revenue: f64,
+    pub periods: Vec<CohortPeriod>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CohortPeriod {
+    pub period: u32,
+    pub label: String,
+    pub active_customers: i64,
+    // Share of the cohort's customers who ordered in the period
+    pub retention_rate: f64,
+    pub revenue: f64,
+    // Revenue of the period as a share of the cohort's revenue in period 0
+    pub revenue_retention: f64,
+}
+
+// ?cohort=month|quarter|year&max_periods=12. The from_date/to_date range picks the cohorts by
+// first order date; a customer's first order is looked up over all their orders, so customers
+// who ordered before from_date do not show up as new. Each cohort is followed for max_periods
+// periods (12 by default, at most 120).
+#[derive(Deserialize, Validate, Debug, Default, Clone)]
+pub struct CohortQuery {
+    pub cohort: Option<ReportPeriod>,
+    #[validate(range(min = 1, max = 120))]
+    pub max_periods: Option<u32>,
+}
+
+// One order with its net value, as every backend returns them.
+#[derive(Debug, FromRow)]
+pub struct CustomerOrder {
+    pub customer_id: i32,
+    pub order_date: NaiveDate,
+    #[row(default)]
+    pub order_value: f64,
+}
+
+// Periods counted from year 0, so the difference of two is the number of periods between them.
+fn period_index(period: ReportPeriod, date: NaiveDate) -> i32 {
+    match period {
+        ReportPeriod::Year => date.year(),
+        ReportPeriod::Quarter => date.year() * 4 + (date.month0() / 3) as i32,
+        ReportPeriod::Month => date.year() * 12 + date.month0() as i32,
+    }
+}
+
+fn period_label(period: ReportPeriod, index: i32) -> String {
+    match period {
+        ReportPeriod::Year => index.to_string(),
+        ReportPeriod::Quarter => format!("{}-Q{}", index.div_euclid(4), index.rem_euclid(4) + 1),
+        ReportPeriod::Month => format!("{}-{:02}", index.div_euclid(12), index.rem_euclid(12) + 1),
+    }
+}
+
+const DEFAULT_MAX_PERIODS: u32 = 12;
+
+impl CohortQuery {
+    // The periods from_date to to_date span, capped like every report that fills in empty periods.
+    pub fn validate_range(&self, filter: &ReportFilter) -> Result<(), ValidationErrors> {
+        let period = self.cohort.unwrap_or(ReportPeriod::Month);
+        filter.validate_span(|from, to| (period_index(period, to) - period_index(period, from) + 1) as i64)
+    }
+
+    // The filter the backends load the orders with: everything before to_date, so first
+    // orders are found even when they precede from_date.
+    pub fn order_filter(&self, filter: &ReportFilter) -> ReportFilter {
+        ReportFilter { from_date: None, ..filter.clone() }
+    }
+}
+
+impl CohortRetention {
+    pub fn build(orders: Vec<CustomerOrder>, filter: &ReportFilter, query: &CohortQuery) -> Vec<CohortRetention> {
+        let period = query.cohort.unwrap_or(ReportPeriod::Month);
+        let Some(last) = filter.to_date.or_else(|| orders.iter().map(|o| o.order_date).max()) else {
+            return Vec::new();
+        };
+        let last = period_index(period, last);
+
+        let mut first_orders = BTreeMap::<i32, NaiveDate>::new();
+        for order in &orders {
+            let first = first_orders.entry(order.customer_id).or_insert(order.order_date);
+            *first = (*first).min(order.order_date);
+        }
+
+        // Customers and revenue per cohort and period offset
+        let mut cohorts = BTreeMap::<i32, BTreeMap<u32, (BTreeSet<i32>, f64)>>::new();
+        for order in orders {
+            let first = first_orders[&order.customer_id];
+            if filter.from_date.is_some_and(|from| first < from) {
+                continue;
+            }
+            let cohort = period_index(period, first);
+            let offset = (period_index(period, order.order_date) - cohort) as u32;
+            let cell = cohorts.entry(cohort).or_default().entry(offset).or_default();
+            cell.0.insert(order.customer_id);
+            cell.1 += order.order_value;
+        }
+
+        cohorts.into_iter()
+            .map(|(cohort, cells)| {
+                let (customers, revenue) = cells.get(&0).map(|(c, r)| (c.len() as i64, *r)).unwrap_or_default();
+                let span = (last - cohort + 1).max(1) as u32;
+                let periods = (0..span.min(query.max_periods.unwrap_or(DEFAULT_MAX_PERIODS)))
+                    .map(|offset| {
+                        let (active, period_revenue) = cells.get(&offset).map(|(c, r)| (c.len() as i64, *r)).unwrap_or_default();
+                        CohortPeriod {
+                            period: offset,
+                            label: period_label(period, cohort + offset as i32),
+                            active_customers: active,
+                            retention_rate: if customers > 0 { active as f64 / customers as f64 } else { 0.0 },
+                            revenue: period_revenue,
+                            revenue_retention: if revenue > 0.0 { period_revenue / revenue } else { 0.0 },
+                        }
+                    })
+                    .collect();
+                CohortRetention { cohort: period_label(period, cohort), customers, revenue, periods }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(s: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(s, "%Y-%m-%d").unwrap()
+    }
+
+    fn order(customer_id: i32, order_date: &str, order_value: f64) -> CustomerOrder {
+        CustomerOrder { customer_id, order_date: date(order_date), order_value }
+    }
+
+    #[test]
+    fn cohorts_track_their_customers() {
+        let orders = vec![
+            order(1, "2023-01-05", 100.0),
+            order(2, "2023-01-20", 300.0),
+            order(1, "2023-03-02", 50.0),
+            order(3, "2023-02-10", 80.0),
+            order(3, "2023-03-15", 40.0),
+        ];
+        let filter = ReportFilter { to_date: Some(date("2023-03-31")), ..Default::default() };
+        let cohorts = CohortRetention::build(orders, &filter, &CohortQuery::default());
+
+        assert_eq!(cohorts.iter().map(|c| (c.cohort.as_str(), c.customers, c.revenue)).collect::<Vec<_>>(),
+            vec![("2023-01", 2, 400.0), ("2023-02", 1, 80.0)]);
+        let january: Vec<(&str, i64, f64, f64)> = cohorts[0].periods.iter()
+            .map(|p| (p.label.as_str(), p.active_customers, p.retention_rate, p.revenue_retention))
+            .collect();
+        assert_eq!(january, vec![("2023-01", 2, 1.0, 1.0), ("2023-02", 0, 0.0, 0.0), ("2023-03", 1, 0.5, 0.125)]);
+        assert_eq!(cohorts[1].periods.len(), 2);
+    }
+
+    #[test]
+    fn periods_default_to_twelve() {
+        let orders = vec![order(1, "2020-01-05", 100.0)];
+        let filter = ReportFilter { to_date: Some(date("2023-12-31")), ..Default::default() };
+        assert_eq!(CohortRetention::build(orders, &filter, &CohortQuery::default())[0].periods.len(), 12);
+
+        let far = ReportFilter { to_date: Some(date("2500-12-31")), ..Default::default() };
+        assert!(CohortQuery::default().validate_range(&far).is_err());
+        let yearly = CohortQuery { cohort: Some(ReportPeriod::Year), max_periods: None };
+        assert!(yearly.validate_range(&far).is_ok());
+    }
+}
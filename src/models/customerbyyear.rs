@@ -1,9 +1,112 @@
+use mssqlapi_derive::FromRow;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+use crate::models::reportfilter::{YearRange, MAX_YEAR_SPAN};
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct CustomerByYear {
     pub customer_name: String,
-    pub sales_2021: f64,
-    pub sales_2022: f64,
-    pub sales_2023: f64,
-}
\ No newline at end of file
+    // One entry per year of the requested range, in year order
+    pub sales: Vec<YearSales>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct YearSales {
+    pub year: i32,
+    pub sales: f64,
+}
+
+// Customer sales grouped by year, as every backend returns them before the pivot.
+#[derive(Debug, FromRow)]
+pub struct CustomerYearSales {
+    pub customer_name: String,
+    pub order_year: i32,
+    #[row(default)]
+    pub sales: f64,
+}
+
+impl CustomerByYear {
+    // Turns the (customer, year) rows into one entry per customer with a value for every year
+    // of the range, 0 where a customer bought nothing. Without bounds the range runs from the
+    // first to the last year in the rows, cut to the MAX_YEAR_SPAN years next to the bound that
+    // was given (the latest ones without either). Sorted by the last year's sales, highest first.
+    pub fn pivot(rows: Vec<CustomerYearSales>, years: &YearRange) -> Vec<CustomerByYear> {
+        let from = years.from_year.or_else(|| rows.iter().map(|r| r.order_year).min());
+        let to = years.to_year.or_else(|| rows.iter().map(|r| r.order_year).max());
+        let (Some(mut from), Some(mut to)) = (from, to) else {
+            return Vec::new();
+        };
+        if years.from_year.is_none() {
+            from = from.max(to - MAX_YEAR_SPAN + 1);
+        }
+        if years.to_year.is_none() {
+            to = to.min(from + MAX_YEAR_SPAN - 1);
+        }
+
+        let mut customers = BTreeMap::<String, BTreeMap<i32, f64>>::new();
+        for row in rows {
+            *customers.entry(row.customer_name).or_default().entry(row.order_year).or_insert(0.0) += row.sales;
+        }
+
+        let mut customer_data: Vec<CustomerByYear> = customers.into_iter()
+            .map(|(customer_name, by_year)| CustomerByYear {
+                customer_name,
+                sales: (from..=to)
+                    .map(|year| YearSales { year, sales: by_year.get(&year).copied().unwrap_or(0.0) })
+                    .collect(),
+            })
+            .collect();
+
+        let last_year = |c: &CustomerByYear| c.sales.last().map(|s| s.sales).unwrap_or(0.0);
+        customer_data.sort_by(|a, b| last_year(b).total_cmp(&last_year(a)));
+
+        customer_data
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rows(sales: &[(&str, i32, f64)]) -> Vec<CustomerYearSales> {
+        sales.iter().map(|&(name, order_year, sales)| CustomerYearSales { customer_name: name.to_string(), order_year, sales }).collect()
+    }
+
+    fn years(from_year: Option<i32>, to_year: Option<i32>) -> YearRange {
+        YearRange { from_year, to_year }
+    }
+
+    fn year_list(customer: &CustomerByYear) -> Vec<(i32, f64)> {
+        customer.sales.iter().map(|s| (s.year, s.sales)).collect()
+    }
+
+    #[test]
+    fn every_year_of_the_range_is_filled() {
+        let customers = CustomerByYear::pivot(rows(&[("A", 2021, 10.0), ("B", 2023, 5.0), ("A", 2021, 2.5), ("B", 2021, 1.0)]), &years(None, None));
+
+        // B sold more in the last year, so it comes first
+        assert_eq!(customers.iter().map(|c| c.customer_name.as_str()).collect::<Vec<_>>(), vec!["B", "A"]);
+        assert_eq!(year_list(&customers[0]), vec![(2021, 1.0), (2022, 0.0), (2023, 5.0)]);
+        assert_eq!(year_list(&customers[1]), vec![(2021, 12.5), (2022, 0.0), (2023, 0.0)]);
+
+        let customers = CustomerByYear::pivot(rows(&[("A", 2021, 10.0)]), &years(Some(2020), Some(2022)));
+        assert_eq!(year_list(&customers[0]), vec![(2020, 0.0), (2021, 10.0), (2022, 0.0)]);
+        assert!(CustomerByYear::pivot(Vec::new(), &years(None, None)).is_empty());
+    }
+
+    #[test]
+    fn a_bound_taken_from_the_data_keeps_the_span_capped() {
+        let sales = || rows(&[("A", 1990, 1.0), ("A", 2023, 2.0)]);
+
+        let to_only = CustomerByYear::pivot(sales(), &years(None, Some(2060)));
+        assert_eq!(to_only[0].sales.len(), MAX_YEAR_SPAN as usize);
+        assert_eq!((to_only[0].sales[0].year, to_only[0].sales.last().unwrap().year), (2011, 2060));
+
+        let from_only = CustomerByYear::pivot(sales(), &years(Some(1960), None));
+        assert_eq!((from_only[0].sales[0].year, from_only[0].sales.last().unwrap().year), (1960, 2009));
+
+        let neither = CustomerByYear::pivot(rows(&[("A", 1950, 1.0), ("A", 2023, 2.0)]), &years(None, None));
+        assert_eq!((neither[0].sales[0].year, neither[0].sales.last().unwrap().year), (1974, 2023));
+    }
+}
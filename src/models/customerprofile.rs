@@ -0,0 +1,198 @@
+use mssqlapi_derive::FromRow;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use validator::Validate;
+
+use crate::models::customerbyyear::YearSales;
+
+// Everything an account manager needs about one customer on a single page.
+// Order values are net of discount and exclude freight, like the other sales figures.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CustomerProfile {
+    #[serde(flatten)]
+    pub customer: CustomerInfo,
+    pub first_order_date: Option<String>,
+    pub last_order_date: Option<String>,
+    pub order_count: i64,
+    pub lifetime_revenue: f64,
+    pub average_order_value: f64,
+    // One entry per year from the first to the last order, 0 for years without orders
+    pub yearly_sales: Vec<YearSales>,
+    pub top_products: Vec<CustomerProductSales>,
+    pub employees: Vec<ServingEmployee>,
+}
+
+#[derive(Debug, Serialize, Deserialize, FromRow)]
+pub struct CustomerInfo {
+    pub customer_id: i32,
+    pub customer_name: String,
+    pub contact_name: Option<String>,
+    pub contact_title: Option<String>,
+    pub city: String,
+    #[row(with = "crate::countries::display_name")]
+    pub country: String,
+    pub phone: Option<String>,
+}
+
+// Employees who took the customer's orders, the one with the highest sales first.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ServingEmployee {
+    pub employee_id: i32,
+    pub employee_name: String,
+    pub order_count: i64,
+    pub sales: f64,
+}
+
+// ?top_products=5, plus the ReportFilter to restrict the orders the profile is built from.
+#[derive(Deserialize, Validate, Debug, Default, Clone)]
+pub struct CustomerProfileQuery {
+    #[validate(range(min = 1, max = 50))]
+    pub top_products: Option<u32>,
+}
+
+// One row per order of the customer, as every backend returns them.
+#[derive(Debug, FromRow)]
+pub struct CustomerOrderValue {
+    pub order_date: String,
+    pub employee_id: i32,
+    pub employee_name: String,
+    #[row(default)]
+    pub order_value: f64,
+}
+
+// The customer's sales per product.
+#[derive(Debug, Serialize, Deserialize, FromRow)]
+pub struct CustomerProductSales {
+    pub product_id: i32,
+    pub product_name: String,
+    #[row(default)]
+    pub order_count: i64,
+    #[row(default)]
+    pub quantity: i64,
+    #[row(default)]
+    pub revenue: f64,
+}
+
+impl CustomerProfile {
+    pub fn build(customer: CustomerInfo, orders: Vec<CustomerOrderValue>, mut products: Vec<CustomerProductSales>, query: &CustomerProfileQuery) -> CustomerProfile {
+        let first_order_date = orders.iter().map(|o| o.order_date.clone()).min();
+        let last_order_date = orders.iter().map(|o| o.order_date.clone()).max();
+        let order_count = orders.len() as i64;
+        let lifetime_revenue: f64 = orders.iter().map(|o| o.order_value).sum();
+        let average_order_value = if order_count > 0 { lifetime_revenue / order_count as f64 } else { 0.0 };
+
+        let mut by_year = BTreeMap::<i32, f64>::new();
+        let mut by_employee = BTreeMap::<i32, ServingEmployee>::new();
+        for order in orders {
+            let year = order.order_date.get(..4).and_then(|y| y.parse().ok()).unwrap_or_default();
+            *by_year.entry(year).or_insert(0.0) += order.order_value;
+
+            let employee = by_employee.entry(order.employee_id).or_insert_with(|| ServingEmployee {
+                employee_id: order.employee_id,
+                employee_name: order.employee_name,
+                order_count: 0,
+                sales: 0.0,
+            });
+            employee.order_count += 1;
+            employee.sales += order.order_value;
+        }
+
+        let yearly_sales = match (by_year.keys().next(), by_year.keys().last()) {
+            (Some(&from), Some(&to)) => (from..=to)
+                .map(|year| YearSales { year, sales: by_year.get(&year).copied().unwrap_or(0.0) })
+                .collect(),
+            _ => Vec::new(),
+        };
+
+        let mut employees: Vec<ServingEmployee> = by_employee.into_values().collect();
+        employees.sort_by(|a, b| b.sales.total_cmp(&a.sales));
+
+        products.sort_by(|a, b| b.revenue.total_cmp(&a.revenue).then_with(|| a.product_id.cmp(&b.product_id)));
+        products.truncate(query.top_products.unwrap_or(5) as usize);
+
+        CustomerProfile {
+            customer,
+            first_order_date,
+            last_order_date,
+            order_count,
+            lifetime_revenue,
+            average_order_value,
+            yearly_sales,
+            top_products: products,
+            employees,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn customer() -> CustomerInfo {
+        CustomerInfo {
+            customer_id: 5,
+            customer_name: "Customer HGVLZ".to_string(),
+            contact_name: Some("Higginbotham, Tom".to_string()),
+            contact_title: None,
+            city: "Bräcke".to_string(),
+            country: "Sweden".to_string(),
+            phone: None,
+        }
+    }
+
+    fn order(order_date: &str, employee_id: i32, employee_name: &str, order_value: f64) -> CustomerOrderValue {
+        CustomerOrderValue { order_date: order_date.to_string(), employee_id, employee_name: employee_name.to_string(), order_value }
+    }
+
+    fn product(product_id: i32, revenue: f64) -> CustomerProductSales {
+        CustomerProductSales { product_id, product_name: format!("Product {product_id}"), order_count: 1, quantity: 2, revenue }
+    }
+
+    fn products() -> Vec<CustomerProductSales> {
+        vec![product(1, 10.0), product(2, 90.0), product(3, 40.0), product(4, 40.0), product(5, 5.0), product(6, 60.0), product(7, 1.0)]
+    }
+
+    #[test]
+    fn totals_trend_products_and_employees() {
+        let orders = vec![
+            order("2021-03-01", 1, "Davis, Sara", 100.0),
+            order("2023-05-02", 2, "King, Russell", 250.0),
+            order("2023-01-10", 1, "Davis, Sara", 50.0),
+        ];
+        let profile = CustomerProfile::build(customer(), orders, products(), &CustomerProfileQuery::default());
+
+        assert_eq!((profile.first_order_date.as_deref(), profile.last_order_date.as_deref()), (Some("2021-03-01"), Some("2023-05-02")));
+        assert_eq!((profile.order_count, profile.lifetime_revenue), (3, 400.0));
+        assert!((profile.average_order_value - 400.0 / 3.0).abs() < 1e-9);
+
+        let years: Vec<(i32, f64)> = profile.yearly_sales.iter().map(|y| (y.year, y.sales)).collect();
+        assert_eq!(years, vec![(2021, 100.0), (2022, 0.0), (2023, 300.0)]);
+
+        let employees: Vec<(&str, i64, f64)> = profile.employees.iter().map(|e| (e.employee_name.as_str(), e.order_count, e.sales)).collect();
+        assert_eq!(employees, vec![("King, Russell", 1, 250.0), ("Davis, Sara", 2, 150.0)]);
+
+        // Five by default, equal revenue ordered by id
+        let top: Vec<i32> = profile.top_products.iter().map(|p| p.product_id).collect();
+        assert_eq!(top, vec![2, 6, 3, 4, 1]);
+    }
+
+    #[test]
+    fn top_products_is_configurable() {
+        let profile = CustomerProfile::build(customer(), Vec::new(), products(), &CustomerProfileQuery { top_products: Some(2) });
+        assert_eq!(profile.top_products.iter().map(|p| p.product_id).collect::<Vec<_>>(), vec![2, 6]);
+    }
+
+    #[test]
+    fn a_customer_without_orders_has_an_empty_profile() {
+        let profile = CustomerProfile::build(customer(), Vec::new(), Vec::new(), &CustomerProfileQuery::default());
+        let value = serde_json::to_value(&profile).unwrap();
+
+        assert_eq!(value["customer_id"], 5);
+        assert_eq!(value["contact_title"], json!(null));
+        assert_eq!(value["first_order_date"], json!(null));
+        assert_eq!(value["last_order_date"], json!(null));
+        assert_eq!((value["order_count"].clone(), value["lifetime_revenue"].clone(), value["average_order_value"].clone()), (json!(0), json!(0.0), json!(0.0)));
+        assert_eq!((value["yearly_sales"].clone(), value["top_products"].clone(), value["employees"].clone()), (json!([]), json!([]), json!([])));
+    }
+}
@@ -0,0 +1,215 @@
+use mssqlapi_derive::FromRow;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use crate::models::reportfilter::ReportPeriod;
+
+// One employee in one period: the employee's own sales, and the totals of the whole team
+// below them in the manager hierarchy, the employee included.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct EmployeePerformance {
+    pub employee_id: i32,
+    pub employee_name: String,
+    pub title: Option<String>,
+    pub manager_id: Option<i32>,
+    // 0 for the top of the hierarchy, 1 for their direct reports and so on
+    pub level: u32,
+    pub period: String,
+    pub revenue: f64,
+    pub order_count: i64,
+    pub customers_served: i64,
+    pub average_discount: f64,
+    pub team: TeamTotals,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct TeamTotals {
+    // Direct and indirect reports
+    pub team_size: i64,
+    pub revenue: f64,
+    pub order_count: i64,
+    pub customers_served: i64,
+    pub average_discount: f64,
+}
+
+// ?period=year|quarter|month
+#[derive(Deserialize, Debug, Default, Clone)]
+pub struct EmployeePerformanceQuery {
+    #[serde(default)]
+    pub period: ReportPeriod,
+}
+
+#[derive(Debug, FromRow)]
+pub struct EmployeeInfo {
+    pub employee_id: i32,
+    pub employee_name: String,
+    pub title: Option<String>,
+    pub manager_id: Option<i32>,
+}
+
+// Sales per employee and period. The backends return them twice: for the employee's own
+// orders, and rolled up over the employee's team with a recursive query on mgrid.
+#[derive(Debug, FromRow)]
+pub struct EmployeePeriodSales {
+    pub employee_id: i32,
+    pub period: String,
+    #[row(default)]
+    pub revenue: f64,
+    #[row(default)]
+    pub order_count: i64,
+    #[row(default)]
+    pub customers_served: i64,
+    #[row(default)]
+    pub average_discount: f64,
+}
+
+impl EmployeePerformance {
+    // Joins own and team sales per employee and period. Employees show up for every period in
+    // which they or their team sold something, ordered by period and then down the hierarchy.
+    pub fn build(employees: Vec<EmployeeInfo>, own: Vec<EmployeePeriodSales>, team: Vec<EmployeePeriodSales>) -> Vec<EmployeePerformance> {
+        let managers: HashMap<i32, Option<i32>> = employees.iter().map(|e| (e.employee_id, e.manager_id)).collect();
+        let level = |employee_id: i32| {
+            let mut level = 0;
+            let mut current = managers.get(&employee_id).copied().flatten();
+            // Bounded by the number of employees, so a cycle in mgrid cannot loop forever
+            while let Some(manager) = current.filter(|_| level < managers.len() as u32) {
+                level += 1;
+                current = managers.get(&manager).copied().flatten();
+            }
+            level
+        };
+
+        let mut team_sizes = HashMap::<i32, i64>::new();
+        for employee in &employees {
+            let mut current = employee.manager_id;
+            let mut seen = BTreeSet::new();
+            while let Some(manager) = current.filter(|m| seen.insert(*m)) {
+                *team_sizes.entry(manager).or_default() += 1;
+                current = managers.get(&manager).copied().flatten();
+            }
+        }
+
+        let own: HashMap<(i32, String), EmployeePeriodSales> = own.into_iter().map(|s| ((s.employee_id, s.period.clone()), s)).collect();
+        let team: BTreeMap<(i32, String), EmployeePeriodSales> = team.into_iter().map(|s| ((s.employee_id, s.period.clone()), s)).collect();
+        let info: HashMap<i32, &EmployeeInfo> = employees.iter().map(|e| (e.employee_id, e)).collect();
+
+        let mut performance = Vec::new();
+        for ((employee_id, period), team) in team {
+            let Some(employee) = info.get(&employee_id) else {
+                continue;
+            };
+            let own = own.get(&(employee_id, period.clone()));
+            performance.push(EmployeePerformance {
+                employee_id,
+                employee_name: employee.employee_name.clone(),
+                title: employee.title.clone(),
+                manager_id: employee.manager_id,
+                level: level(employee_id),
+                period,
+                revenue: own.map(|s| s.revenue).unwrap_or(0.0),
+                order_count: own.map(|s| s.order_count).unwrap_or(0),
+                customers_served: own.map(|s| s.customers_served).unwrap_or(0),
+                average_discount: own.map(|s| s.average_discount).unwrap_or(0.0),
+                team: TeamTotals {
+                    team_size: team_sizes.get(&employee_id).copied().unwrap_or(0),
+                    revenue: team.revenue,
+                    order_count: team.order_count,
+                    customers_served: team.customers_served,
+                    average_discount: team.average_discount,
+                },
+            });
+        }
+
+        performance.sort_by(|a, b| {
+            a.period.cmp(&b.period)
+                .then(a.level.cmp(&b.level))
+                .then(a.employee_id.cmp(&b.employee_id))
+        });
+        performance
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::row::tests::FakeRow;
+    use crate::db::row::{FromRow, Value};
+
+    #[test]
+    fn employee_without_title_or_manager_serializes_as_null() {
+        let row = FakeRow(vec![
+            ("employee_id", Value::Int(1)),
+            ("employee_name", Value::Text("Davis, Sara".to_string())),
+            ("title", Value::Null),
+            ("manager_id", Value::Null),
+        ]);
+        let employee = EmployeeInfo::from_row(&row).unwrap();
+        assert_eq!((employee.title.as_deref(), employee.manager_id), (None, None));
+
+        let sales = || EmployeePeriodSales { employee_id: 1, period: "2023".to_string(), revenue: 100.0, order_count: 1, customers_served: 1, average_discount: 0.0 };
+        let performance = EmployeePerformance::build(vec![employee], vec![sales()], vec![sales()]);
+        let json = serde_json::to_value(&performance).unwrap();
+        assert_eq!(json[0]["title"], serde_json::Value::Null);
+        assert_eq!(json[0]["manager_id"], serde_json::Value::Null);
+        assert_eq!(json[0]["level"], 0);
+    }
+
+    fn employee(employee_id: i32, manager_id: Option<i32>) -> EmployeeInfo {
+        EmployeeInfo { employee_id, employee_name: format!("Employee {employee_id}"), title: None, manager_id }
+    }
+
+    fn sales(employee_id: i32, period: &str, revenue: f64, order_count: i64) -> EmployeePeriodSales {
+        EmployeePeriodSales { employee_id, period: period.to_string(), revenue, order_count, customers_served: order_count, average_discount: 0.1 }
+    }
+
+    // 1 manages 2 and 3, 2 manages 4
+    fn hierarchy() -> Vec<EmployeeInfo> {
+        vec![employee(4, Some(2)), employee(1, None), employee(3, Some(1)), employee(2, Some(1))]
+    }
+
+    #[test]
+    fn teams_roll_up_the_hierarchy() {
+        // 1 sells nothing itself; the team rows are what the backends roll up along mgrid
+        let own = vec![sales(2, "2023", 100.0, 1), sales(3, "2023", 50.0, 2), sales(4, "2023", 30.0, 1)];
+        let team = vec![sales(1, "2023", 180.0, 4), sales(2, "2023", 130.0, 2), sales(3, "2023", 50.0, 2), sales(4, "2023", 30.0, 1)];
+        let performance = EmployeePerformance::build(hierarchy(), own, team);
+
+        let rows: Vec<(i32, u32, i64, f64, f64)> = performance.iter()
+            .map(|p| (p.employee_id, p.level, p.team.team_size, p.revenue, p.team.revenue))
+            .collect();
+        assert_eq!(rows, vec![
+            (1, 0, 3, 0.0, 180.0),
+            (2, 1, 1, 100.0, 130.0),
+            (3, 1, 0, 50.0, 50.0),
+            (4, 2, 0, 30.0, 30.0),
+        ]);
+        assert_eq!((performance[0].order_count, performance[0].team.order_count), (0, 4));
+        assert_eq!((performance[1].manager_id, performance[3].manager_id), (Some(1), Some(2)));
+    }
+
+    #[test]
+    fn rows_are_ordered_by_period_then_down_the_hierarchy() {
+        let team = vec![sales(4, "2023", 1.0, 1), sales(2, "2022", 1.0, 1), sales(1, "2023", 1.0, 1), sales(9, "2023", 1.0, 1)];
+        let performance = EmployeePerformance::build(hierarchy(), Vec::new(), team);
+
+        // Employee 9 is not in HR.Employees and is left out
+        let rows: Vec<(&str, i32)> = performance.iter().map(|p| (p.period.as_str(), p.employee_id)).collect();
+        assert_eq!(rows, vec![("2022", 2), ("2023", 1), ("2023", 4)]);
+    }
+
+    #[test]
+    fn a_cycle_in_the_hierarchy_terminates() {
+        // 5 and 6 manage each other, 7 reports to 5
+        let employees = vec![employee(5, Some(6)), employee(6, Some(5)), employee(7, Some(5))];
+        let team = vec![sales(5, "2023", 1.0, 1), sales(6, "2023", 1.0, 1), sales(7, "2023", 1.0, 1)];
+        let performance = EmployeePerformance::build(employees, Vec::new(), team);
+
+        assert_eq!(performance.len(), 3);
+        for p in &performance {
+            assert!(p.level <= 3, "{p:?}");
+            assert!(p.team.team_size <= 3, "{p:?}");
+        }
+        let seven = performance.iter().find(|p| p.employee_id == 7).unwrap();
+        assert_eq!(seven.team.team_size, 0);
+    }
+}
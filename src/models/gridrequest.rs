@@ -0,0 +1,87 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+use std::collections::BTreeMap;
+
+// The block request ag-Grid's server-side and infinite row models send: which rows to
+// load, how to sort them and the column filters, all in ag-Grid's own JSON shape.
+#[derive(Deserialize, Debug, Default, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct GridRequest {
+    #[serde(default)]
+    pub start_row: u32,
+    pub end_row: Option<u32>,
+    #[serde(default)]
+    pub sort_model: Vec<SortModel>,
+    #[serde(default)]
+    pub filter_model: BTreeMap<String, ColumnFilter>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct SortModel {
+    pub col_id: String,
+    pub sort: SortDirection,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum SortDirection {
+    Asc,
+    Desc,
+}
+
+// A single condition, or several joined by `operator` when the user adds a second one.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ColumnFilter {
+    pub filter_type: FilterType,
+    #[serde(rename = "type")]
+    pub kind: Option<FilterKind>,
+    pub filter: Option<JsonValue>,
+    pub filter_to: Option<JsonValue>,
+    pub date_from: Option<String>,
+    pub date_to: Option<String>,
+    pub operator: Option<JoinOperator>,
+    #[serde(default)]
+    pub conditions: Vec<ColumnFilter>,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum FilterType {
+    Text,
+    Number,
+    Date,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum FilterKind {
+    Equals,
+    NotEqual,
+    Contains,
+    NotContains,
+    StartsWith,
+    EndsWith,
+    LessThan,
+    LessThanOrEqual,
+    GreaterThan,
+    GreaterThanOrEqual,
+    InRange,
+    Blank,
+    NotBlank,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum JoinOperator {
+    And,
+    Or,
+}
+
+// One page of a report and the number of rows matching the filters across all pages.
+#[derive(Serialize, Debug)]
+pub struct Page<T> {
+    pub rows: Vec<T>,
+    pub total: i64,
+}
@@ -0,0 +1,249 @@
+use mssqlapi_derive::FromRow;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use validator::Validate;
+
+// Two products bought in the same orders. Support is the share of all orders holding both,
+// confidence the share of the orders holding one that also hold the other, and lift how much
+// more often they sell together than they would if they sold independently.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ProductPair {
+    pub product_id: i32,
+    pub product_name: String,
+    pub paired_product_id: i32,
+    pub paired_product_name: String,
+    pub order_count: i64,
+    pub support: f64,
+    // product -> paired product, and paired product -> product
+    pub confidence: f64,
+    pub reverse_confidence: f64,
+    pub lift: f64,
+}
+
+// One product with the products most often found in its orders.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BoughtWithReport {
+    pub product_id: i32,
+    pub product_name: String,
+    pub order_count: i64,
+    pub support: f64,
+    pub bought_with: Vec<BoughtWith>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BoughtWith {
+    pub product_id: i32,
+    pub product_name: String,
+    pub order_count: i64,
+    pub support: f64,
+    // Share of the orders holding the report's product that also hold this one
+    pub confidence: f64,
+    pub lift: f64,
+}
+
+// ?min_support=0.01&top=20. Support is a fraction of the orders that pass the filter; pairs
+// default to the top 50, "bought with" lists every product that meets min_support.
+#[derive(Deserialize, Validate, Debug, Default, Clone)]
+pub struct MarketBasketQuery {
+    #[validate(range(min = 0.0, max = 1.0))]
+    pub min_support: Option<f64>,
+    #[validate(range(min = 1, max = 1000))]
+    pub top: Option<u32>,
+}
+
+// One product of one order, as every backend returns them.
+#[derive(Debug, FromRow)]
+pub struct OrderProduct {
+    pub order_id: i32,
+    pub product_id: i32,
+    pub product_name: String,
+}
+
+// Order counts per product and per product pair, the pair keyed with the lower id first.
+struct Baskets {
+    orders: i64,
+    products: BTreeMap<i32, (String, i64)>,
+    pairs: BTreeMap<(i32, i32), i64>,
+}
+
+impl Baskets {
+    fn count(lines: Vec<OrderProduct>) -> Baskets {
+        let mut products = BTreeMap::<i32, (String, i64)>::new();
+        let mut orders = BTreeMap::<i32, BTreeSet<i32>>::new();
+        for line in lines {
+            if orders.entry(line.order_id).or_default().insert(line.product_id) {
+                products.entry(line.product_id).or_insert((line.product_name, 0)).1 += 1;
+            }
+        }
+
+        let mut pairs = BTreeMap::<(i32, i32), i64>::new();
+        for basket in orders.values() {
+            let basket: Vec<i32> = basket.iter().copied().collect();
+            for (i, &a) in basket.iter().enumerate() {
+                for &b in &basket[i + 1..] {
+                    *pairs.entry((a, b)).or_default() += 1;
+                }
+            }
+        }
+
+        Baskets { orders: orders.len() as i64, products, pairs }
+    }
+
+    fn support(&self, count: i64) -> f64 {
+        count as f64 / self.orders as f64
+    }
+
+    fn name(&self, product_id: i32) -> String {
+        self.products[&product_id].0.clone()
+    }
+
+    fn orders_with(&self, product_id: i32) -> i64 {
+        self.products[&product_id].1
+    }
+
+    fn lift(&self, count: i64, a: i32, b: i32) -> f64 {
+        (count * self.orders) as f64 / (self.orders_with(a) * self.orders_with(b)) as f64
+    }
+}
+
+impl ProductPair {
+    // Pairs by support, highest first, ties broken by lift.
+    pub fn build(lines: Vec<OrderProduct>, query: &MarketBasketQuery) -> Vec<ProductPair> {
+        let baskets = Baskets::count(lines);
+        let min_support = query.min_support.unwrap_or(0.0);
+
+        let mut pairs: Vec<ProductPair> = baskets.pairs.iter()
+            .filter(|(_, &count)| baskets.support(count) >= min_support)
+            .map(|(&(a, b), &count)| ProductPair {
+                product_id: a,
+                product_name: baskets.name(a),
+                paired_product_id: b,
+                paired_product_name: baskets.name(b),
+                order_count: count,
+                support: baskets.support(count),
+                confidence: count as f64 / baskets.orders_with(a) as f64,
+                reverse_confidence: count as f64 / baskets.orders_with(b) as f64,
+                lift: baskets.lift(count, a, b),
+            })
+            .collect();
+
+        pairs.sort_by(|x, y| y.order_count.cmp(&x.order_count)
+            .then(y.lift.total_cmp(&x.lift))
+            .then((x.product_id, x.paired_product_id).cmp(&(y.product_id, y.paired_product_id))));
+        pairs.truncate(query.top.unwrap_or(50) as usize);
+        pairs
+    }
+}
+
+impl BoughtWithReport {
+    // The products sold with `product_id` by confidence, highest first, ties broken by lift.
+    // None when the product is not in any order that passes the filter.
+    pub fn build(lines: Vec<OrderProduct>, product_id: i32, query: &MarketBasketQuery) -> Option<BoughtWithReport> {
+        let baskets = Baskets::count(lines);
+        let order_count = baskets.products.get(&product_id)?.1;
+        let min_support = query.min_support.unwrap_or(0.0);
+
+        let mut bought_with: Vec<BoughtWith> = baskets.pairs.iter()
+            .filter_map(|(&(a, b), &count)| match product_id {
+                id if id == a => Some((b, count)),
+                id if id == b => Some((a, count)),
+                _ => None,
+            })
+            .filter(|&(_, count)| baskets.support(count) >= min_support)
+            .map(|(other, count)| BoughtWith {
+                product_id: other,
+                product_name: baskets.name(other),
+                order_count: count,
+                support: baskets.support(count),
+                confidence: count as f64 / order_count as f64,
+                lift: baskets.lift(count, product_id, other),
+            })
+            .collect();
+
+        bought_with.sort_by(|x, y| y.order_count.cmp(&x.order_count)
+            .then(y.lift.total_cmp(&x.lift))
+            .then(x.product_id.cmp(&y.product_id)));
+        if let Some(top) = query.top {
+            bought_with.truncate(top as usize);
+        }
+
+        Some(BoughtWithReport {
+            product_id,
+            product_name: baskets.name(product_id),
+            order_count,
+            support: baskets.support(order_count),
+            bought_with,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Five orders: {1, 2, 3}, {1, 2}, {1, 3}, {2} and {4}, with product 1 listed twice in the first
+    fn lines() -> Vec<OrderProduct> {
+        [(10, 1), (10, 2), (10, 3), (10, 1), (11, 1), (11, 2), (12, 3), (12, 1), (13, 2), (14, 4)].into_iter()
+            .map(|(order_id, product_id)| OrderProduct { order_id, product_id, product_name: format!("Product {product_id}") })
+            .collect()
+    }
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!((actual - expected).abs() < 1e-9, "{actual} != {expected}");
+    }
+
+    #[test]
+    fn pairs_have_support_confidence_and_lift() {
+        let pairs = ProductPair::build(lines(), &MarketBasketQuery::default());
+
+        // Products 1 and 3 are in two orders, like 1 and 2, but 3 is rarer so the lift is higher
+        let ids: Vec<(i32, i32, i64)> = pairs.iter().map(|p| (p.product_id, p.paired_product_id, p.order_count)).collect();
+        assert_eq!(ids, vec![(1, 3, 2), (1, 2, 2), (2, 3, 1)]);
+        assert_eq!(pairs[0].paired_product_name, "Product 3");
+
+        // support = pair orders / 5, confidence = pair orders / orders with the product,
+        // lift = pair orders * 5 / (orders with one * orders with the other)
+        let expected = [(0.4, 2.0 / 3.0, 1.0, 5.0 / 3.0), (0.4, 2.0 / 3.0, 2.0 / 3.0, 10.0 / 9.0), (0.2, 1.0 / 3.0, 0.5, 5.0 / 6.0)];
+        for (pair, (support, confidence, reverse_confidence, lift)) in pairs.iter().zip(expected) {
+            assert_close(pair.support, support);
+            assert_close(pair.confidence, confidence);
+            assert_close(pair.reverse_confidence, reverse_confidence);
+            assert_close(pair.lift, lift);
+        }
+    }
+
+    #[test]
+    fn pairs_are_filtered_by_min_support_and_top() {
+        let query = MarketBasketQuery { min_support: Some(0.3), top: None };
+        assert_eq!(ProductPair::build(lines(), &query).len(), 2);
+
+        let query = MarketBasketQuery { min_support: Some(0.4), top: Some(1) };
+        let pairs = ProductPair::build(lines(), &query);
+        assert_eq!(pairs.iter().map(|p| (p.product_id, p.paired_product_id)).collect::<Vec<_>>(), vec![(1, 3)]);
+    }
+
+    #[test]
+    fn bought_with_lists_the_other_products_of_its_orders() {
+        let report = BoughtWithReport::build(lines(), 2, &MarketBasketQuery::default()).unwrap();
+        assert_eq!((report.product_name.as_str(), report.order_count), ("Product 2", 3));
+        assert_close(report.support, 0.6);
+
+        let others: Vec<(i32, i64)> = report.bought_with.iter().map(|b| (b.product_id, b.order_count)).collect();
+        assert_eq!(others, vec![(1, 2), (3, 1)]);
+        assert_close(report.bought_with[0].confidence, 2.0 / 3.0);
+        assert_close(report.bought_with[0].lift, 10.0 / 9.0);
+        assert_close(report.bought_with[1].confidence, 1.0 / 3.0);
+        assert_close(report.bought_with[1].lift, 5.0 / 6.0);
+
+        let query = MarketBasketQuery { min_support: Some(0.3), top: None };
+        assert_eq!(BoughtWithReport::build(lines(), 2, &query).unwrap().bought_with.len(), 1);
+    }
+
+    #[test]
+    fn bought_with_needs_the_product_in_an_order() {
+        let alone = BoughtWithReport::build(lines(), 4, &MarketBasketQuery::default()).unwrap();
+        assert!(alone.bought_with.is_empty());
+        assert!(BoughtWithReport::build(lines(), 99, &MarketBasketQuery::default()).is_none());
+        assert!(BoughtWithReport::build(Vec::new(), 1, &MarketBasketQuery::default()).is_none());
+    }
+}
@@ -1,4 +1,24 @@
+// NULL policy for the report models:
+// - Descriptive columns the schema allows to be NULL (contact names, titles, ship names,
+//   pivot cells without a value) are `Option<String>` and serialize as JSON `null`.
+//   Fields are never skipped, so every row has the same keys.
+// - Aggregated amounts use `#[row(default)]`: a NULL sum means "no sales" and becomes 0.
+// - Key columns (names, countries, dates) stay required. A NULL there is a mapping error
+//   that fails the request with a 500 instead of panicking the worker.
 pub mod ordersreport;
+pub mod orderdetail;
 pub mod customerbyyear;
+pub mod customerprofile;
+pub mod employeeperformance;
 pub mod topperformers;
 pub mod saleschoropleth;
+pub mod shipperperformance;
+pub mod productsales;
+pub mod salestimeseries;
+pub mod rfmsegmentation;
+pub mod cohortretention;
+pub mod salesforecast;
+pub mod abcanalysis;
+pub mod marketbasket;
+pub mod reportfilter;
+pub mod gridrequest;
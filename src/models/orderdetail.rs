@@ -0,0 +1,98 @@
+use mssqlapi_derive::FromRow;
+use serde::{Deserialize, Serialize};
+
+// One order with its lines, for drilling down from a row of the orders report.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct OrderDetail {
+    #[serde(flatten)]
+    pub header: OrderHeader,
+    pub lines: Vec<OrderLine>,
+    pub order_value: f64,
+    pub billable_value: f64,
+}
+
+#[derive(Debug, Serialize, Deserialize, FromRow)]
+pub struct OrderHeader {
+    pub order_id: i32,
+    pub customer_id: i32,
+    pub customer_name: String,
+    #[row(rename = "customer_contact")]
+    pub customer_contact_name: Option<String>,
+    #[row(with = "crate::countries::display_name")]
+    pub customer_country: String,
+    pub employee_id: i32,
+    pub employee_name: String,
+    pub employee_title: Option<String>,
+    pub shipper_id: i32,
+    pub shipper_name: String,
+    pub ship_name: Option<String>,
+    pub ship_city: String,
+    #[row(with = "crate::countries::display_name")]
+    pub ship_country: String,
+    pub order_date: String,
+    pub required_date: String,
+    pub shipped_date: Option<String>,
+    #[row(default)]
+    pub freight: f64,
+}
+
+// An order line joined to its product. `line_total` is unit_price * quantity * (1 - discount).
+#[derive(Debug, Serialize, Deserialize, FromRow)]
+pub struct OrderLine {
+    pub product_id: i32,
+    pub product_name: String,
+    #[row(default)]
+    pub unit_price: f64,
+    pub quantity: i32,
+    #[row(default)]
+    pub discount: f64,
+    #[row(default)]
+    pub line_total: f64,
+}
+
+impl OrderDetail {
+    // Totals are summed from the lines, the same way the orders report computes them.
+    pub fn new(header: OrderHeader, lines: Vec<OrderLine>) -> OrderDetail {
+        let order_value = lines.iter().map(|line| line.line_total).sum();
+        let billable_value = header.freight + order_value;
+        OrderDetail { header, lines, order_value, billable_value }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::row::tests::FakeRow;
+    use crate::db::row::{FromRow, Value};
+
+    #[test]
+    fn header_with_null_columns_serializes_as_null() {
+        let row = FakeRow(vec![
+            ("order_id", Value::Int(10248)),
+            ("customer_id", Value::Int(85)),
+            ("customer_name", Value::Text("Customer ENQZT".to_string())),
+            ("customer_contact", Value::Null),
+            ("customer_country", Value::Text("France".to_string())),
+            ("employee_id", Value::Int(5)),
+            ("employee_name", Value::Text("Buck, Sven".to_string())),
+            ("employee_title", Value::Null),
+            ("shipper_id", Value::Int(3)),
+            ("shipper_name", Value::Text("Shipper ZHISN".to_string())),
+            ("ship_name", Value::Null),
+            ("ship_city", Value::Text("Reims".to_string())),
+            ("ship_country", Value::Text("France".to_string())),
+            ("order_date", Value::Text("2014-07-04".to_string())),
+            ("required_date", Value::Text("2014-08-01".to_string())),
+            ("shipped_date", Value::Null),
+            ("freight", Value::Null),
+        ]);
+
+        let header = OrderHeader::from_row(&row).unwrap();
+        assert_eq!(header.freight, 0.0);
+        let json = serde_json::to_value(OrderDetail::new(header, Vec::new())).unwrap();
+        for field in ["customer_contact_name", "employee_title", "ship_name", "shipped_date"] {
+            assert_eq!(json[field], serde_json::Value::Null, "{}", field);
+        }
+        assert_eq!(json["billable_value"], 0.0);
+    }
+}
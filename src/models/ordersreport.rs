@@ -1,18 +1,95 @@
+use mssqlapi_derive::FromRow;
 use serde::{Deserialize,Serialize};
 
+use crate::db::grid::GridColumn;
+use crate::models::gridrequest::FilterType;
 
-#[derive(Serialize, Deserialize, Debug)]
+
+#[derive(Serialize, Deserialize, Debug, FromRow)]
 pub struct OrdersReport {
+    pub order_id: i32,
     pub customer_name: String,
-    pub customer_contact_name: String,
+    #[row(rename = "customer_contact")]
+    pub customer_contact_name: Option<String>,
+    #[row(with = "crate::countries::display_name")]
     pub customer_country: String,
     pub employee_name: String,
-    pub employee_title: String,
+    pub employee_title: Option<String>,
     pub shipper_name: String,
-    pub ship_name: String,
+    pub ship_name: Option<String>,
     pub order_date: String,
     pub delivery_date: String,
     pub freight_value: f64,
     pub order_value: f64,
     pub billable_value: f64,
-}
\ No newline at end of file
+}
+
+// Columns the paged orders report sorts and filters on, by JSON field and result set column.
+// Countries are filtered on the stored spelling, equality accepts any known spelling.
+pub const ORDERS_GRID_COLUMNS: &[GridColumn] = &[
+    GridColumn::new("order_id", "order_id", FilterType::Number),
+    GridColumn::new("customer_name", "customer_name", FilterType::Text),
+    GridColumn::new("customer_contact_name", "customer_contact", FilterType::Text),
+    GridColumn { stored: Some(crate::countries::stored_name), ..GridColumn::new("customer_country", "customer_country", FilterType::Text) },
+    GridColumn::new("employee_name", "employee_name", FilterType::Text),
+    GridColumn::new("employee_title", "employee_title", FilterType::Text),
+    GridColumn::new("shipper_name", "shipper_name", FilterType::Text),
+    GridColumn::new("ship_name", "ship_name", FilterType::Text),
+    GridColumn::new("order_date", "order_date", FilterType::Date),
+    GridColumn::new("delivery_date", "delivery_date", FilterType::Date),
+    GridColumn::new("freight_value", "freight_value", FilterType::Number),
+    GridColumn::new("order_value", "order_value", FilterType::Number),
+    GridColumn::new("billable_value", "billable_value", FilterType::Number),
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::row::tests::FakeRow;
+    use crate::db::row::{FromRow, MappingError, Value};
+
+    fn row(customer_contact: Value, employee_title: Value, ship_name: Value) -> FakeRow {
+        FakeRow(vec![
+            ("order_id", Value::Int(10248)),
+            ("customer_name", Value::Text("Customer AHPOP".to_string())),
+            ("customer_contact", customer_contact),
+            ("customer_country", Value::Text("UK".to_string())),
+            ("employee_name", Value::Text("Davis, Sara".to_string())),
+            ("employee_title", employee_title),
+            ("shipper_name", Value::Text("Shipper GVSUA".to_string())),
+            ("ship_name", ship_name),
+            ("order_date", Value::Text("2023-07-04".to_string())),
+            ("delivery_date", Value::Text("2023-08-01".to_string())),
+            ("freight_value", Value::Float(32.38)),
+            ("order_value", Value::Float(440.0)),
+            ("billable_value", Value::Float(472.38)),
+        ])
+    }
+
+    #[test]
+    fn null_descriptive_columns_serialize_as_null() {
+        let report = OrdersReport::from_row(&row(Value::Null, Value::Null, Value::Null)).unwrap();
+        let json = serde_json::to_value(&report).unwrap();
+        assert_eq!(json["customer_contact_name"], serde_json::Value::Null);
+        assert_eq!(json["employee_title"], serde_json::Value::Null);
+        assert_eq!(json["ship_name"], serde_json::Value::Null);
+        assert_eq!(json["customer_country"], "United Kingdom");
+        assert_eq!(json.as_object().unwrap().len(), 13);
+    }
+
+    #[test]
+    fn present_values_are_kept() {
+        let text = |s: &str| Value::Text(s.to_string());
+        let report = OrdersReport::from_row(&row(text("Allen, Michael"), text("Sales Manager"), text("Ship to 85-B"))).unwrap();
+        assert_eq!(report.customer_contact_name.as_deref(), Some("Allen, Michael"));
+        assert_eq!(report.employee_title.as_deref(), Some("Sales Manager"));
+        assert_eq!(report.ship_name.as_deref(), Some("Ship to 85-B"));
+    }
+
+    #[test]
+    fn null_key_column_is_a_mapping_error() {
+        let mut row = row(Value::Null, Value::Null, Value::Null);
+        row.0[1].1 = Value::Null;
+        assert_eq!(OrdersReport::from_row(&row).unwrap_err(), MappingError::UnexpectedNull { column: "customer_name".to_string() });
+    }
+}
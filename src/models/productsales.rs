@@ -0,0 +1,420 @@
+use mssqlapi_derive::FromRow;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashSet};
+use validator::{Validate, ValidationError};
+
+use crate::models::reportfilter::ReportPeriod;
+
+// One product, ranked within its period by the requested metric.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ProductSales {
+    pub rank: u32,
+    pub period: Option<String>,
+    pub product_id: i32,
+    pub product_name: String,
+    pub category_id: i32,
+    pub category_name: String,
+    pub list_price: f64,
+    pub discontinued: bool,
+    #[serde(flatten)]
+    pub sales: SalesFigures,
+}
+
+// One category, ranked within its period by the requested metric.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CategorySales {
+    pub rank: u32,
+    pub period: Option<String>,
+    pub category_id: i32,
+    pub category_name: String,
+    // Products of the category sold in the period
+    pub product_count: i64,
+    #[serde(flatten)]
+    pub sales: SalesFigures,
+}
+
+// Figures shared by the product and category reports. Revenue is net of discount. Prices are
+// per unit sold: the list price comes from Production.Products, the realized price is what
+// the customers paid after discount.
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct SalesFigures {
+    pub revenue: f64,
+    pub units: i64,
+    pub order_count: i64,
+    pub customer_count: i64,
+    pub average_list_price: f64,
+    pub average_realized_price: f64,
+    // Realized price as a fraction of the list price
+    pub price_realization: Option<f64>,
+    // Average discount weighted by line value, 0.05 for 5%
+    pub discount_depth: Option<f64>,
+}
+
+#[derive(Deserialize, Debug, Default, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ProductMetric {
+    #[default]
+    Revenue,
+    Units,
+    OrderCount,
+    CustomerCount,
+}
+
+// ?top=10 or ?bottom=10, metric=revenue|units|order_count|customer_count,
+// period=year|quarter|month and category_id, every parameter optional. Without top or
+// bottom every product (or category) sold is returned, ranked. The bottom products also
+// include the ones that sold nothing in a period, with zero figures.
+#[derive(Deserialize, Validate, Debug, Default, Clone)]
+#[validate(schema(function = "validate_top_bottom"))]
+pub struct ProductSalesQuery {
+    #[validate(range(min = 1, max = 100))]
+    pub top: Option<u32>,
+    #[validate(range(min = 1, max = 100))]
+    pub bottom: Option<u32>,
+    #[serde(default)]
+    pub metric: ProductMetric,
+    pub period: Option<ReportPeriod>,
+    #[validate(range(min = 1))]
+    pub category_id: Option<i32>,
+}
+
+fn validate_top_bottom(query: &ProductSalesQuery) -> Result<(), ValidationError> {
+    match (query.top, query.bottom) {
+        (Some(_), Some(_)) => Err(ValidationError::new("top_and_bottom")),
+        _ => Ok(()),
+    }
+}
+
+// Sales per product and period, as every backend returns them. Without a requested period
+// the backends do not group by one and `period` is empty.
+#[derive(Debug, FromRow)]
+pub struct ProductPeriodSales {
+    pub product_id: i32,
+    pub product_name: String,
+    pub category_id: i32,
+    pub category_name: String,
+    #[row(default)]
+    pub list_price: f64,
+    pub discontinued: bool,
+    pub period: String,
+    #[row(default)]
+    pub revenue: f64,
+    // Before discount, at the price on the order line
+    #[row(default)]
+    pub gross_revenue: f64,
+    // At the current list price
+    #[row(default)]
+    pub list_revenue: f64,
+    #[row(default)]
+    pub units: i64,
+    #[row(default)]
+    pub order_count: i64,
+    #[row(default)]
+    pub customer_count: i64,
+}
+
+// A product of the catalogue, so products without sales can be ranked at the bottom.
+#[derive(Debug, FromRow)]
+pub struct ProductInfo {
+    pub product_id: i32,
+    pub product_name: String,
+    pub category_id: i32,
+    pub category_name: String,
+    #[row(default)]
+    pub list_price: f64,
+    pub discontinued: bool,
+}
+
+// Sales per category and period, with the same columns as ProductPeriodSales.
+#[derive(Debug, FromRow)]
+pub struct CategoryPeriodSales {
+    pub category_id: i32,
+    pub category_name: String,
+    pub period: String,
+    #[row(default)]
+    pub product_count: i64,
+    #[row(default)]
+    pub revenue: f64,
+    #[row(default)]
+    pub gross_revenue: f64,
+    #[row(default)]
+    pub list_revenue: f64,
+    #[row(default)]
+    pub units: i64,
+    #[row(default)]
+    pub order_count: i64,
+    #[row(default)]
+    pub customer_count: i64,
+}
+
+impl SalesFigures {
+    fn new(revenue: f64, gross_revenue: f64, list_revenue: f64, units: i64, order_count: i64, customer_count: i64) -> SalesFigures {
+        let per_unit = |value: f64| if units > 0 { value / units as f64 } else { 0.0 };
+        SalesFigures {
+            revenue,
+            units,
+            order_count,
+            customer_count,
+            average_list_price: per_unit(list_revenue),
+            average_realized_price: per_unit(revenue),
+            price_realization: (list_revenue > 0.0).then(|| revenue / list_revenue),
+            discount_depth: (gross_revenue > 0.0).then(|| 1.0 - revenue / gross_revenue),
+        }
+    }
+
+    fn metric(&self, metric: ProductMetric) -> f64 {
+        match metric {
+            ProductMetric::Revenue => self.revenue,
+            ProductMetric::Units => self.units as f64,
+            ProductMetric::OrderCount => self.order_count as f64,
+            ProductMetric::CustomerCount => self.customer_count as f64,
+        }
+    }
+}
+
+trait RankedSales {
+    fn id(&self) -> i32;
+    fn period(&self) -> &Option<String>;
+    fn sales(&self) -> &SalesFigures;
+    fn set_rank(&mut self, rank: u32);
+}
+
+impl RankedSales for ProductSales {
+    fn id(&self) -> i32 { self.product_id }
+    fn period(&self) -> &Option<String> { &self.period }
+    fn sales(&self) -> &SalesFigures { &self.sales }
+    fn set_rank(&mut self, rank: u32) { self.rank = rank }
+}
+
+impl RankedSales for CategorySales {
+    fn id(&self) -> i32 { self.category_id }
+    fn period(&self) -> &Option<String> { &self.period }
+    fn sales(&self) -> &SalesFigures { &self.sales }
+    fn set_rank(&mut self, rank: u32) { self.rank = rank }
+}
+
+// Ranks the items within each period, highest first, or lowest first for `bottom`, and keeps
+// the first N ranks. Ties share a rank and are all kept, like TOP N WITH TIES.
+fn rank<T: RankedSales>(mut items: Vec<T>, query: &ProductSalesQuery) -> Vec<T> {
+    let limit = query.top.or(query.bottom).unwrap_or(u32::MAX);
+    let value = |item: &T| item.sales().metric(query.metric);
+    items.sort_by(|a, b| {
+        let by_value = if query.bottom.is_some() { value(a).total_cmp(&value(b)) } else { value(b).total_cmp(&value(a)) };
+        a.period().cmp(b.period())
+            .then(by_value)
+            .then(a.id().cmp(&b.id()))
+    });
+
+    let mut ranked = Vec::new();
+    let mut position = 0;
+    let mut rank = 0;
+    let mut previous: Option<(Option<String>, f64)> = None;
+    for mut item in items {
+        let current = (item.period().clone(), value(&item));
+        match &previous {
+            Some((period, _)) if period != &current.0 => position = 0,
+            _ => {}
+        }
+        position += 1;
+        if previous.as_ref() != Some(&current) {
+            rank = position;
+        }
+        previous = Some(current);
+        if rank > limit {
+            continue;
+        }
+        item.set_rank(rank);
+        ranked.push(item);
+    }
+    ranked
+}
+
+impl ProductSales {
+    // `catalogue` holds the products that may have sold nothing; they are added with zero
+    // figures to every period that has sales, and only when ranking from the bottom.
+    pub fn rank(mut rows: Vec<ProductPeriodSales>, catalogue: Vec<ProductInfo>, query: &ProductSalesQuery) -> Vec<ProductSales> {
+        if query.bottom.is_some() {
+            let mut periods: BTreeSet<String> = rows.iter().map(|r| r.period.clone()).collect();
+            if query.period.is_none() {
+                periods.insert(String::new());
+            }
+            let sold: HashSet<(i32, String)> = rows.iter().map(|r| (r.product_id, r.period.clone())).collect();
+            for product in &catalogue {
+                for period in periods.iter().filter(|&period| !sold.contains(&(product.product_id, period.clone()))) {
+                    rows.push(ProductPeriodSales::unsold(product, period.clone()));
+                }
+            }
+        }
+
+        let products = rows.into_iter()
+            .map(|row| ProductSales {
+                rank: 0,
+                period: query.period.map(|_| row.period),
+                product_id: row.product_id,
+                product_name: row.product_name,
+                category_id: row.category_id,
+                category_name: row.category_name,
+                list_price: row.list_price,
+                discontinued: row.discontinued,
+                sales: SalesFigures::new(row.revenue, row.gross_revenue, row.list_revenue, row.units, row.order_count, row.customer_count),
+            })
+            .collect();
+        rank(products, query)
+    }
+}
+
+impl ProductPeriodSales {
+    fn unsold(product: &ProductInfo, period: String) -> ProductPeriodSales {
+        ProductPeriodSales {
+            product_id: product.product_id,
+            product_name: product.product_name.clone(),
+            category_id: product.category_id,
+            category_name: product.category_name.clone(),
+            list_price: product.list_price,
+            discontinued: product.discontinued,
+            period,
+            revenue: 0.0,
+            gross_revenue: 0.0,
+            list_revenue: 0.0,
+            units: 0,
+            order_count: 0,
+            customer_count: 0,
+        }
+    }
+}
+
+impl CategorySales {
+    pub fn rank(rows: Vec<CategoryPeriodSales>, query: &ProductSalesQuery) -> Vec<CategorySales> {
+        let categories = rows.into_iter()
+            .map(|row| CategorySales {
+                rank: 0,
+                period: query.period.map(|_| row.period),
+                category_id: row.category_id,
+                category_name: row.category_name,
+                product_count: row.product_count,
+                sales: SalesFigures::new(row.revenue, row.gross_revenue, row.list_revenue, row.units, row.order_count, row.customer_count),
+            })
+            .collect();
+        rank(categories, query)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // sales: revenue, gross revenue, list revenue; counts: units, orders, customers
+    fn sold(product_id: i32, period: &str, sales: [f64; 3], counts: [i64; 3]) -> ProductPeriodSales {
+        let product = info(product_id);
+        let [revenue, gross_revenue, list_revenue] = sales;
+        let [units, order_count, customer_count] = counts;
+        ProductPeriodSales { period: period.to_string(), revenue, gross_revenue, list_revenue, units, order_count, customer_count, ..ProductPeriodSales::unsold(&product, String::new()) }
+    }
+
+    fn info(product_id: i32) -> ProductInfo {
+        ProductInfo {
+            product_id,
+            product_name: format!("Product {product_id}"),
+            category_id: 1,
+            category_name: "Beverages".to_string(),
+            list_price: 12.0,
+            discontinued: false,
+        }
+    }
+
+    // Products 1 and 2 tie on revenue, 3 sold the most units, 4 sold nothing
+    fn rows() -> Vec<ProductPeriodSales> {
+        vec![
+            sold(1, "", [90.0, 100.0, 120.0], [10, 3, 2]),
+            sold(2, "", [90.0, 90.0, 90.0], [5, 1, 1]),
+            sold(3, "", [30.0, 30.0, 0.0], [20, 2, 2]),
+        ]
+    }
+
+    fn catalogue() -> Vec<ProductInfo> {
+        (1..=4).map(info).collect()
+    }
+
+    fn ranks(rows: Vec<ProductPeriodSales>, query: ProductSalesQuery) -> Vec<(Option<String>, u32, i32)> {
+        ProductSales::rank(rows, catalogue(), &query).into_iter().map(|p| (p.period, p.rank, p.product_id)).collect()
+    }
+
+    fn ranked(list: &[(u32, i32)]) -> Vec<(Option<String>, u32, i32)> {
+        list.iter().map(|&(rank, id)| (None, rank, id)).collect()
+    }
+
+    #[test]
+    fn top_and_bottom_rank_in_opposite_directions() {
+        assert_eq!(ranks(rows(), ProductSalesQuery::default()), ranked(&[(1, 1), (1, 2), (3, 3)]));
+        assert_eq!(ranks(rows(), ProductSalesQuery { top: Some(1), ..Default::default() }), ranked(&[(1, 1), (1, 2)]));
+        assert_eq!(ranks(rows(), ProductSalesQuery { metric: ProductMetric::Units, ..Default::default() }), ranked(&[(1, 3), (2, 1), (3, 2)]));
+        assert_eq!(ranks(rows(), ProductSalesQuery { metric: ProductMetric::CustomerCount, ..Default::default() }), ranked(&[(1, 1), (1, 3), (3, 2)]));
+
+        // The bottom ranks include product 4, which sold nothing
+        assert_eq!(ranks(rows(), ProductSalesQuery { bottom: Some(2), ..Default::default() }), ranked(&[(1, 4), (2, 3)]));
+        assert_eq!(ranks(rows(), ProductSalesQuery { bottom: Some(3), ..Default::default() }), ranked(&[(1, 4), (2, 3), (3, 1), (3, 2)]));
+        assert_eq!(ranks(rows(), ProductSalesQuery { bottom: Some(1), metric: ProductMetric::OrderCount, ..Default::default() }), ranked(&[(1, 4)]));
+    }
+
+    #[test]
+    fn ranks_restart_in_every_period() {
+        let rows = || vec![
+            sold(1, "2022", [50.0, 50.0, 50.0], [1, 1, 1]),
+            sold(2, "2022", [20.0, 20.0, 20.0], [1, 1, 1]),
+            sold(2, "2023", [70.0, 70.0, 70.0], [1, 1, 1]),
+            sold(1, "2023", [10.0, 10.0, 10.0], [1, 1, 1]),
+        ];
+        let period = |id: &str, rank, product| (Some(id.to_string()), rank, product);
+
+        let by_year = ProductSalesQuery { period: Some(ReportPeriod::Year), ..Default::default() };
+        assert_eq!(ranks(rows(), by_year.clone()), vec![period("2022", 1, 1), period("2022", 2, 2), period("2023", 1, 2), period("2023", 2, 1)]);
+
+        // Unsold products are added to each period that has sales
+        let bottom = ProductSalesQuery { bottom: Some(1), ..by_year };
+        assert_eq!(ranks(rows(), bottom), vec![period("2022", 1, 3), period("2022", 1, 4), period("2023", 1, 3), period("2023", 1, 4)]);
+    }
+
+    #[test]
+    fn realized_price_against_list_price() {
+        let products = ProductSales::rank(rows(), Vec::new(), &ProductSalesQuery::default());
+        let first = &products[0].sales;
+        // 90 paid for 10 units listed at 12, after a 10% discount
+        assert_eq!((first.average_list_price, first.average_realized_price), (12.0, 9.0));
+        assert_eq!(first.price_realization, Some(0.75));
+        assert!((first.discount_depth.unwrap() - 0.1).abs() < 1e-9);
+
+        // No list revenue means no realization
+        assert_eq!(products[2].sales.price_realization, None);
+
+        let unsold = ProductSales::rank(Vec::new(), catalogue(), &ProductSalesQuery { bottom: Some(5), ..Default::default() });
+        assert_eq!(unsold.len(), 4);
+        let figures = &unsold[0].sales;
+        assert_eq!((figures.revenue, figures.units, figures.average_realized_price), (0.0, 0, 0.0));
+        assert_eq!((figures.price_realization, figures.discount_depth), (None, None));
+        assert_eq!(unsold[0].list_price, 12.0);
+    }
+
+    #[test]
+    fn categories_rank_like_products() {
+        let category = |category_id, revenue| CategoryPeriodSales {
+            category_id,
+            category_name: format!("Category {category_id}"),
+            period: String::new(),
+            product_count: 2,
+            revenue,
+            gross_revenue: revenue,
+            list_revenue: revenue,
+            units: 1,
+            order_count: 1,
+            customer_count: 1,
+        };
+        let categories = CategorySales::rank(vec![category(1, 10.0), category(2, 30.0), category(3, 10.0)], &ProductSalesQuery { bottom: Some(1), ..Default::default() });
+        assert_eq!(categories.iter().map(|c| (c.rank, c.category_id)).collect::<Vec<_>>(), vec![(1, 1), (1, 3)]);
+    }
+
+    #[test]
+    fn top_and_bottom_exclude_each_other() {
+        assert!(ProductSalesQuery { top: Some(5), bottom: Some(5), ..Default::default() }.validate().is_err());
+        assert!(ProductSalesQuery { bottom: Some(5), ..Default::default() }.validate().is_ok());
+    }
+}
@@ -0,0 +1,196 @@
+use chrono::{Datelike, Local, NaiveDate};
+use serde::{Deserialize, Deserializer, Serialize};
+use validator::{Validate, ValidationError, ValidationErrors};
+
+use crate::countries;
+
+// Query-string filters accepted by every report endpoint. Each one is optional and
+// restricts the orders a report is built from; the values are only ever bound as
+// query parameters, never formatted into the SQL.
+#[derive(Deserialize, Validate, Debug, Default, Clone)]
+#[validate(schema(function = "validate_date_range"))]
+pub struct ReportFilter {
+    pub from_date: Option<NaiveDate>,
+    pub to_date: Option<NaiveDate>,
+    // Customer country in any spelling the countries module knows ("UK", "United Kingdom",
+    // "GB", "GBR"), turned into the one the database stores
+    #[serde(default, deserialize_with = "stored_country")]
+    #[validate(length(min = 1, max = 15))]
+    pub country: Option<String>,
+    #[validate(range(min = 1))]
+    pub customer_id: Option<i32>,
+    #[validate(range(min = 1))]
+    pub employee_id: Option<i32>,
+    #[validate(range(min = 1))]
+    pub shipper_id: Option<i32>,
+}
+
+fn stored_country<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<String>, D::Error> {
+    Ok(Option::<String>::deserialize(deserializer)?.map(countries::stored_name))
+}
+
+fn validate_date_range(filter: &ReportFilter) -> Result<(), ValidationError> {
+    match (filter.from_date, filter.to_date) {
+        (Some(from), Some(to)) if from > to => Err(ValidationError::new("from_date_after_to_date")),
+        _ => Ok(()),
+    }
+}
+
+// Reports that fill every period of the requested range (time series, forecasts, cohorts)
+// are capped at this many periods, so a far-off date cannot ask for a response of megabytes.
+pub const MAX_RANGE_PERIODS: i64 = 1000;
+
+impl ReportFilter {
+    // Rejects a from_date/to_date range holding more than MAX_RANGE_PERIODS periods, counted by
+    // `periods(from, to)`. A missing bound stands for the first or last order, assumed to be
+    // no later than today, so one-sided ranges are capped too.
+    pub fn validate_span(&self, periods: impl Fn(NaiveDate, NaiveDate) -> i64) -> Result<(), ValidationErrors> {
+        let today = Local::now().date_naive();
+        let (from, to) = match (self.from_date, self.to_date) {
+            (None, None) => return Ok(()),
+            (Some(from), to) => (from, to.unwrap_or(from.max(today))),
+            (None, Some(to)) => (to.min(today), to),
+        };
+        if periods(from, to) > MAX_RANGE_PERIODS {
+            let mut errors = ValidationErrors::new();
+            errors.add("to_date", ValidationError::new("date_range_too_long"));
+            return Err(errors);
+        }
+        Ok(())
+    }
+}
+
+// Year columns of the customer sales report. Missing bounds default to the first and
+// last year with sales, and the span is capped so a typo cannot ask for ten thousand columns;
+// a one-sided range is measured against the current year, like ReportFilter::validate_span.
+#[derive(Deserialize, Validate, Debug, Default, Clone)]
+#[validate(schema(function = "validate_year_range"))]
+pub struct YearRange {
+    #[validate(range(min = 1900, max = 9999))]
+    pub from_year: Option<i32>,
+    #[validate(range(min = 1900, max = 9999))]
+    pub to_year: Option<i32>,
+}
+
+pub const MAX_YEAR_SPAN: i32 = 50;
+
+fn validate_year_range(years: &YearRange) -> Result<(), ValidationError> {
+    let this_year = Local::now().year();
+    let (from, to) = match (years.from_year, years.to_year) {
+        (None, None) => return Ok(()),
+        (Some(from), Some(to)) if from > to => return Err(ValidationError::new("from_year_after_to_year")),
+        (Some(from), to) => (from, to.unwrap_or(from.max(this_year))),
+        (None, Some(to)) => (to.min(this_year), to),
+    };
+    if to - from >= MAX_YEAR_SPAN {
+        return Err(ValidationError::new("year_range_too_long"));
+    }
+    Ok(())
+}
+
+// Period a report groups orders by, labelled "2023", "2023-Q2" and "2023-05".
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ReportPeriod {
+    #[default]
+    Year,
+    Quarter,
+    Month,
+}
+
+impl ReportPeriod {
+    // Label of the period an order date ("YYYY-MM-DD") falls in.
+    pub fn label(self, date: &str) -> String {
+        let year = date.get(..4).unwrap_or_default();
+        let month: u32 = date.get(5..7).and_then(|m| m.parse().ok()).unwrap_or(1);
+        match self {
+            ReportPeriod::Year => year.to_string(),
+            ReportPeriod::Quarter => format!("{}-Q{}", year, month.div_ceil(3)),
+            ReportPeriod::Month => format!("{}-{:02}", year, month),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::web::Query;
+
+    fn filter(query: &str) -> ReportFilter {
+        Query::<ReportFilter>::from_query(query).unwrap().into_inner()
+    }
+
+    fn error_codes(errors: ValidationErrors) -> Vec<String> {
+        let mut codes: Vec<String> = errors.errors().iter()
+            .flat_map(|(field, kind)| match kind {
+                validator::ValidationErrorsKind::Field(errors) => errors.iter().map(|e| format!("{}:{}", field, e.code)).collect(),
+                _ => Vec::new(),
+            })
+            .collect();
+        codes.sort();
+        codes
+    }
+
+    #[test]
+    fn filters_parse_from_the_query_string() {
+        let parsed = filter("from_date=2022-01-01&to_date=2022-12-31&country=United%20Kingdom&customer_id=5&employee_id=7&shipper_id=3");
+        assert_eq!((parsed.from_date, parsed.to_date), (NaiveDate::from_ymd_opt(2022, 1, 1), NaiveDate::from_ymd_opt(2022, 12, 31)));
+        assert_eq!(parsed.country.as_deref(), Some("UK"));
+        assert_eq!(filter("country=GBR").country.as_deref(), Some("UK"));
+        assert_eq!((parsed.customer_id, parsed.employee_id, parsed.shipper_id), (Some(5), Some(7), Some(3)));
+        assert!(parsed.validate().is_ok());
+
+        assert!(filter("").validate().is_ok());
+        assert!(Query::<ReportFilter>::from_query("from_date=2022-13-01").is_err());
+        assert!(Query::<ReportFilter>::from_query("customer_id=five").is_err());
+    }
+
+    #[test]
+    fn invalid_filters_are_rejected() {
+        assert_eq!(error_codes(filter("from_date=2023-01-01&to_date=2022-12-31").validate().unwrap_err()), vec!["__all__:from_date_after_to_date"]);
+        assert!(filter("from_date=2023-01-01&to_date=2023-01-01").validate().is_ok());
+
+        assert_eq!(error_codes(filter("customer_id=0&employee_id=-1&shipper_id=0").validate().unwrap_err()),
+            vec!["customer_id:range", "employee_id:range", "shipper_id:range"]);
+        assert_eq!(error_codes(filter("country=").validate().unwrap_err()), vec!["country:length"]);
+        assert_eq!(error_codes(filter("country=A%20country%20name%20too%20long").validate().unwrap_err()), vec!["country:length"]);
+    }
+
+    #[test]
+    fn year_ranges_are_capped() {
+        let years = |query: &str| Query::<YearRange>::from_query(query).unwrap().into_inner().validate();
+        let this_year = Local::now().year();
+
+        assert!(years("").is_ok());
+        assert!(years("from_year=2000&to_year=2049").is_ok());
+        assert_eq!(error_codes(years("from_year=2000&to_year=2050").unwrap_err()), vec!["__all__:year_range_too_long"]);
+        assert_eq!(error_codes(years("from_year=2023&to_year=2021").unwrap_err()), vec!["__all__:from_year_after_to_year"]);
+        assert_eq!(error_codes(years("to_year=10000").unwrap_err()), vec!["to_year:range"]);
+
+        // One bound is measured against the current year
+        assert!(years("to_year=9999").is_err());
+        assert!(years(&format!("to_year={}", this_year + 49)).is_ok());
+        assert!(years(&format!("to_year={}", this_year + 50)).is_err());
+        assert!(years("to_year=1990").is_ok());
+        assert!(years(&format!("from_year={}", this_year - 49)).is_ok());
+        assert!(years(&format!("from_year={}", this_year - 50)).is_err());
+        assert!(years("from_year=9000").is_ok());
+    }
+
+    #[test]
+    fn spans_are_measured_from_today_when_a_bound_is_missing() {
+        let days = |from: NaiveDate, to: NaiveDate| (to - from).num_days() + 1;
+        let today = Local::now().date_naive();
+
+        assert!(filter("from_date=2020-01-01&to_date=2022-09-26").validate_span(days).is_ok());
+        assert!(filter("from_date=2020-01-01&to_date=2022-09-27").validate_span(days).is_err());
+        assert!(filter("").validate_span(days).is_ok());
+
+        let to = (today + chrono::Days::new(1000)).format("to_date=%Y-%m-%d").to_string();
+        assert_eq!(error_codes(filter(&to).validate_span(days).unwrap_err()), vec!["to_date:date_range_too_long"]);
+        let from = (today - chrono::Days::new(1000)).format("from_date=%Y-%m-%d").to_string();
+        assert!(filter(&from).validate_span(days).is_err());
+        let from = (today - chrono::Days::new(998)).format("from_date=%Y-%m-%d").to_string();
+        assert!(filter(&from).validate_span(days).is_ok());
+    }
+}
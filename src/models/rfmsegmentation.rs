@@ -0,0 +1,337 @@
+use chrono::NaiveDate;
+use mssqlapi_derive::FromRow;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+use crate::models::reportfilter::ReportFilter;
+
+// Customers scored on recency, frequency and monetary value as of `as_of`, with one summary
+// per segment.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RfmReport {
+    pub as_of: NaiveDate,
+    pub segments: Vec<RfmSegmentSummary>,
+    pub customers: Vec<CustomerRfm>,
+}
+
+// Scores run from 1 to 5 by quintile of the customers, 5 being the most recent, most frequent
+// and highest spending. Customers with the same value get the same score.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CustomerRfm {
+    pub customer_id: i32,
+    pub customer_name: String,
+    pub country: String,
+    pub last_order_date: NaiveDate,
+    // Days from the last order to as_of
+    pub recency_days: i64,
+    pub frequency: i64,
+    pub monetary: f64,
+    pub r_score: u8,
+    pub f_score: u8,
+    pub m_score: u8,
+    // "545" for r_score 5, f_score 4, m_score 5
+    pub rfm_score: String,
+    pub segment: RfmSegment,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RfmSegmentSummary {
+    pub segment: RfmSegment,
+    pub customer_count: i64,
+    pub customer_share: f64,
+    pub revenue: f64,
+    pub revenue_share: f64,
+    pub average_recency_days: f64,
+    pub average_frequency: f64,
+    pub average_monetary: f64,
+}
+
+// Named segments of the usual recency by frequency grid. The frequency axis is the average
+// of the frequency and monetary scores, rounded up, so big spenders count as well.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum RfmSegment {
+    Champions,
+    LoyalCustomers,
+    PotentialLoyalists,
+    NewCustomers,
+    Promising,
+    NeedAttention,
+    AboutToSleep,
+    AtRisk,
+    CantLoseThem,
+    Hibernating,
+}
+
+// ?as_of=2023-12-31&segment=at_risk. as_of defaults to to_date, or to the latest order date;
+// orders after it are left out. `segment` only restricts the customer rows, the summaries
+// always cover every segment.
+#[derive(Deserialize, Debug, Default, Clone)]
+pub struct RfmQuery {
+    pub as_of: Option<NaiveDate>,
+    pub segment: Option<RfmSegment>,
+}
+
+// Order totals per customer, as every backend returns them.
+#[derive(Debug, FromRow)]
+pub struct CustomerOrderTotals {
+    pub customer_id: i32,
+    pub customer_name: String,
+    #[row(with = "crate::countries::display_name")]
+    pub country: String,
+    pub last_order_date: NaiveDate,
+    #[row(default)]
+    pub order_count: i64,
+    #[row(default)]
+    pub monetary: f64,
+}
+
+impl RfmSegment {
+    fn from_scores(r: u8, f: u8, m: u8) -> RfmSegment {
+        let fm = (f + m).div_ceil(2);
+        match (r, fm) {
+            (5, 4..=5) => RfmSegment::Champions,
+            (5, 2..=3) => RfmSegment::PotentialLoyalists,
+            (5, _) => RfmSegment::NewCustomers,
+            (4, 4..=5) => RfmSegment::LoyalCustomers,
+            (4, 2..=3) => RfmSegment::PotentialLoyalists,
+            (4, _) => RfmSegment::Promising,
+            (3, 4..=5) => RfmSegment::LoyalCustomers,
+            (3, 3) => RfmSegment::NeedAttention,
+            (3, _) => RfmSegment::AboutToSleep,
+            (1..=2, 5) => RfmSegment::CantLoseThem,
+            (1..=2, 3..=4) => RfmSegment::AtRisk,
+            _ => RfmSegment::Hibernating,
+        }
+    }
+}
+
+// Quintile score of every value, 5 for the highest. Equal values share the score of the
+// last of them in ascending order, so a tie at the top is not scored down.
+fn quintiles(values: &[f64]) -> Vec<u8> {
+    let mut sorted: Vec<f64> = values.to_vec();
+    sorted.sort_by(|a, b| a.total_cmp(b));
+    let n = sorted.len();
+    values.iter()
+        .map(|value| {
+            let last = sorted.partition_point(|v| v <= value).saturating_sub(1);
+            (last * 5 / n) as u8 + 1
+        })
+        .collect()
+}
+
+impl RfmQuery {
+    // The filter the backends load the order totals with, ending at as_of.
+    pub fn order_filter(&self, filter: &ReportFilter) -> ReportFilter {
+        let mut orders = filter.clone();
+        orders.to_date = match (filter.to_date, self.as_of) {
+            (Some(to), Some(as_of)) => Some(to.min(as_of)),
+            (to, as_of) => to.or(as_of),
+        };
+        orders
+    }
+}
+
+impl RfmReport {
+    pub fn build(rows: Vec<CustomerOrderTotals>, filter: &ReportFilter, query: &RfmQuery) -> Option<RfmReport> {
+        let as_of = query.as_of.or(filter.to_date).or_else(|| rows.iter().map(|r| r.last_order_date).max())?;
+        if rows.is_empty() {
+            return None;
+        }
+
+        // Fewer days since the last order is better, so recency is scored on the negated days
+        let recency: Vec<i64> = rows.iter().map(|r| (as_of - r.last_order_date).num_days()).collect();
+        let r_scores = quintiles(&recency.iter().map(|days| -(*days as f64)).collect::<Vec<_>>());
+        let f_scores = quintiles(&rows.iter().map(|r| r.order_count as f64).collect::<Vec<_>>());
+        let m_scores = quintiles(&rows.iter().map(|r| r.monetary).collect::<Vec<_>>());
+
+        let mut customers: Vec<CustomerRfm> = rows.into_iter()
+            .enumerate()
+            .map(|(i, row)| {
+                let (r, f, m) = (r_scores[i], f_scores[i], m_scores[i]);
+                CustomerRfm {
+                    customer_id: row.customer_id,
+                    customer_name: row.customer_name,
+                    country: row.country,
+                    last_order_date: row.last_order_date,
+                    recency_days: recency[i],
+                    frequency: row.order_count,
+                    monetary: row.monetary,
+                    r_score: r,
+                    f_score: f,
+                    m_score: m,
+                    rfm_score: format!("{}{}{}", r, f, m),
+                    segment: RfmSegment::from_scores(r, f, m),
+                }
+            })
+            .collect();
+        customers.sort_by(|a, b| {
+            a.segment.cmp(&b.segment)
+                .then(b.monetary.total_cmp(&a.monetary))
+                .then(a.customer_id.cmp(&b.customer_id))
+        });
+
+        let total_customers = customers.len() as f64;
+        let total_revenue: f64 = customers.iter().map(|c| c.monetary).sum();
+        let mut by_segment = BTreeMap::<RfmSegment, Vec<&CustomerRfm>>::new();
+        for customer in &customers {
+            by_segment.entry(customer.segment).or_default().push(customer);
+        }
+        let segments = by_segment.into_iter()
+            .map(|(segment, members)| {
+                let count = members.len() as f64;
+                let revenue: f64 = members.iter().map(|c| c.monetary).sum();
+                RfmSegmentSummary {
+                    segment,
+                    customer_count: members.len() as i64,
+                    customer_share: count / total_customers,
+                    revenue,
+                    revenue_share: if total_revenue > 0.0 { revenue / total_revenue } else { 0.0 },
+                    average_recency_days: members.iter().map(|c| c.recency_days as f64).sum::<f64>() / count,
+                    average_frequency: members.iter().map(|c| c.frequency as f64).sum::<f64>() / count,
+                    average_monetary: revenue / count,
+                }
+            })
+            .collect();
+
+        if let Some(segment) = query.segment {
+            customers.retain(|c| c.segment == segment);
+        }
+
+        Some(RfmReport { as_of, segments, customers })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(s: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(s, "%Y-%m-%d").unwrap()
+    }
+
+    fn totals(customer_id: i32, last_order_date: &str, order_count: i64, monetary: f64) -> CustomerOrderTotals {
+        CustomerOrderTotals {
+            customer_id,
+            customer_name: format!("Customer {}", customer_id),
+            country: "Germany".to_string(),
+            last_order_date: date(last_order_date),
+            order_count,
+            monetary,
+        }
+    }
+
+    #[test]
+    fn quintiles_split_evenly() {
+        assert_eq!(quintiles(&[30.0, 10.0, 50.0, 20.0, 40.0]), vec![3, 1, 5, 2, 4]);
+        let ten: Vec<f64> = (1..=10).map(f64::from).collect();
+        assert_eq!(quintiles(&ten), vec![1, 1, 2, 2, 3, 3, 4, 4, 5, 5]);
+    }
+
+    #[test]
+    fn tied_values_share_the_highest_score() {
+        assert_eq!(quintiles(&[5.0, 5.0, 5.0, 1.0, 9.0]), vec![4, 4, 4, 1, 5]);
+        assert_eq!(quintiles(&[9.0, 9.0, 1.0]), vec![4, 4, 1]);
+        assert_eq!(quintiles(&[3.0, 3.0, 3.0]), vec![4, 4, 4]);
+        assert_eq!(quintiles(&[7.0]), vec![1]);
+    }
+
+    #[test]
+    fn segments_follow_the_grid() {
+        let cases = [
+            ((5, 5, 5), RfmSegment::Champions),
+            ((5, 4, 3), RfmSegment::Champions),
+            ((5, 2, 3), RfmSegment::PotentialLoyalists),
+            ((5, 1, 2), RfmSegment::PotentialLoyalists),
+            ((5, 1, 1), RfmSegment::NewCustomers),
+            ((4, 5, 3), RfmSegment::LoyalCustomers),
+            ((4, 3, 2), RfmSegment::PotentialLoyalists),
+            ((4, 1, 1), RfmSegment::Promising),
+            ((3, 4, 4), RfmSegment::LoyalCustomers),
+            ((3, 2, 3), RfmSegment::NeedAttention),
+            ((3, 2, 2), RfmSegment::AboutToSleep),
+            ((2, 5, 5), RfmSegment::CantLoseThem),
+            ((1, 4, 5), RfmSegment::CantLoseThem),
+            ((2, 3, 4), RfmSegment::AtRisk),
+            ((1, 3, 2), RfmSegment::AtRisk),
+            ((2, 2, 2), RfmSegment::Hibernating),
+            ((1, 1, 1), RfmSegment::Hibernating),
+        ];
+        for ((r, f, m), segment) in cases {
+            assert_eq!(RfmSegment::from_scores(r, f, m), segment, "{}{}{}", r, f, m);
+        }
+    }
+
+    #[test]
+    fn build_scores_and_summarizes() {
+        let rows = vec![
+            totals(4, "2023-06-01", 4, 2000.0),
+            totals(1, "2023-12-31", 10, 5000.0),
+            totals(5, "2023-01-01", 2, 1000.0),
+            totals(3, "2023-11-01", 6, 3000.0),
+            totals(2, "2023-12-01", 8, 4000.0),
+        ];
+        let query = RfmQuery { as_of: Some(date("2023-12-31")), segment: None };
+        let report = RfmReport::build(rows, &ReportFilter::default(), &query).unwrap();
+
+        let scores: Vec<(i32, i64, &str, RfmSegment)> = report.customers.iter()
+            .map(|c| (c.customer_id, c.recency_days, c.rfm_score.as_str(), c.segment))
+            .collect();
+        assert_eq!(scores, vec![
+            (1, 0, "555", RfmSegment::Champions),
+            (2, 30, "444", RfmSegment::LoyalCustomers),
+            (3, 60, "333", RfmSegment::NeedAttention),
+            (4, 213, "222", RfmSegment::Hibernating),
+            (5, 364, "111", RfmSegment::Hibernating),
+        ]);
+
+        let hibernating = report.segments.iter().find(|s| s.segment == RfmSegment::Hibernating).unwrap();
+        assert_eq!(hibernating.customer_count, 2);
+        assert_eq!(hibernating.customer_share, 0.4);
+        assert_eq!(hibernating.revenue, 3000.0);
+        assert_eq!(hibernating.revenue_share, 0.2);
+        assert_eq!(hibernating.average_recency_days, 288.5);
+        assert_eq!(hibernating.average_frequency, 3.0);
+        assert_eq!(hibernating.average_monetary, 1500.0);
+        assert_eq!(report.segments.len(), 4);
+    }
+
+    #[test]
+    fn tied_customers_get_the_same_scores() {
+        let rows = vec![totals(1, "2023-12-31", 3, 900.0), totals(2, "2023-12-31", 3, 900.0), totals(3, "2023-01-01", 1, 100.0)];
+        let query = RfmQuery { as_of: Some(date("2023-12-31")), segment: None };
+        let report = RfmReport::build(rows, &ReportFilter::default(), &query).unwrap();
+
+        // Two of three customers tie at the top, both take the score of the top position
+        let scores: Vec<(i32, &str, RfmSegment)> = report.customers.iter()
+            .map(|c| (c.customer_id, c.rfm_score.as_str(), c.segment))
+            .collect();
+        assert_eq!(scores, vec![
+            (1, "444", RfmSegment::LoyalCustomers),
+            (2, "444", RfmSegment::LoyalCustomers),
+            (3, "111", RfmSegment::Hibernating),
+        ]);
+    }
+
+    #[test]
+    fn segment_filter_keeps_every_summary() {
+        let rows = vec![
+            totals(1, "2023-12-31", 10, 5000.0),
+            totals(2, "2023-12-01", 8, 4000.0),
+            totals(3, "2023-11-01", 6, 3000.0),
+            totals(4, "2023-06-01", 4, 2000.0),
+            totals(5, "2023-01-01", 2, 1000.0),
+        ];
+        let query = RfmQuery { as_of: Some(date("2023-12-31")), segment: Some(RfmSegment::Hibernating) };
+        let report = RfmReport::build(rows, &ReportFilter::default(), &query).unwrap();
+
+        assert_eq!(report.customers.iter().map(|c| c.customer_id).collect::<Vec<_>>(), vec![4, 5]);
+        assert_eq!(report.segments.iter().map(|s| s.customer_count).sum::<i64>(), 5);
+    }
+
+    #[test]
+    fn no_customers_means_no_report() {
+        let query = RfmQuery { as_of: Some(date("2023-12-31")), segment: None };
+        assert!(RfmReport::build(Vec::new(), &ReportFilter::default(), &query).is_none());
+    }
+}
@@ -1,7 +1,182 @@
+use mssqlapi_derive::FromRow;
 use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use validator::Validate;
 
+use crate::countries;
+
+// One country on the map. `value` is the requested metric, the other totals are always included
+// so the frontend can switch metrics without another request.
 #[derive(Debug, Serialize, Deserialize)]
 pub struct SalesChoropleth {
     pub country: String,
-    pub sales_2023: f64
-}
\ No newline at end of file
+    pub iso_alpha2: Option<String>,
+    pub iso_alpha3: Option<String>,
+    pub year: Option<i32>,
+    pub metric: ChoroplethMetric,
+    pub value: f64,
+    pub revenue: f64,
+    pub order_count: i64,
+    pub freight: f64,
+    pub distinct_customers: i64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ChoroplethMetric {
+    #[default]
+    Revenue,
+    OrderCount,
+    Freight,
+    DistinctCustomers,
+}
+
+// ?year=2023&metric=freight. Without a year and without from_date/to_date the latest year
+// with orders is shown; with a date range the map covers the whole range.
+#[derive(Deserialize, Validate, Debug, Default, Clone)]
+pub struct ChoroplethQuery {
+    #[validate(range(min = 1900, max = 9999))]
+    pub year: Option<i32>,
+    #[serde(default)]
+    pub metric: ChoroplethMetric,
+}
+
+// Per customer and year totals, as every backend returns them before they are summed per country.
+#[derive(Debug, FromRow)]
+pub struct CountryCustomerSales {
+    pub country: String,
+    pub customer_id: i32,
+    pub order_year: i32,
+    #[row(default)]
+    pub order_count: i64,
+    #[row(default)]
+    pub freight: f64,
+    #[row(default)]
+    pub revenue: f64,
+}
+
+#[derive(Default)]
+struct CountryTotals {
+    revenue: f64,
+    order_count: i64,
+    freight: f64,
+    customers: BTreeSet<i32>,
+}
+
+impl SalesChoropleth {
+    // Sums the rows per normalized country. Customers are counted once per country even when
+    // the map spans several years, which is why the rows come per customer and not per country.
+    pub fn aggregate(rows: Vec<CountryCustomerSales>, query: &ChoroplethQuery, date_range: bool) -> Vec<SalesChoropleth> {
+        let year = match query.year {
+            Some(year) => Some(year),
+            None if date_range => None,
+            None => match rows.iter().map(|r| r.order_year).max() {
+                Some(latest) => Some(latest),
+                None => return Vec::new(),
+            },
+        };
+
+        let mut totals = BTreeMap::<String, CountryTotals>::new();
+        for row in rows.into_iter().filter(|r| year.is_none_or(|y| r.order_year == y)) {
+            let entry = totals.entry(countries::display_name(row.country)).or_default();
+            entry.revenue += row.revenue;
+            entry.order_count += row.order_count;
+            entry.freight += row.freight;
+            entry.customers.insert(row.customer_id);
+        }
+
+        let mut sales_choropleth_data: Vec<SalesChoropleth> = totals.into_iter()
+            .map(|(country, totals)| {
+                let iso = countries::lookup(&country);
+                let distinct_customers = totals.customers.len() as i64;
+                let value = match query.metric {
+                    ChoroplethMetric::Revenue => totals.revenue,
+                    ChoroplethMetric::OrderCount => totals.order_count as f64,
+                    ChoroplethMetric::Freight => totals.freight,
+                    ChoroplethMetric::DistinctCustomers => distinct_customers as f64,
+                };
+                SalesChoropleth {
+                    iso_alpha2: iso.map(|c| c.alpha2.to_string()),
+                    iso_alpha3: iso.map(|c| c.alpha3.to_string()),
+                    country,
+                    year,
+                    metric: query.metric,
+                    value,
+                    revenue: totals.revenue,
+                    order_count: totals.order_count,
+                    freight: totals.freight,
+                    distinct_customers,
+                }
+            })
+            .collect();
+        sales_choropleth_data.sort_by(|a, b| b.value.total_cmp(&a.value));
+
+        sales_choropleth_data
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sales(country: &str, customer_id: i32, order_year: i32, order_count: i64, freight: f64, revenue: f64) -> CountryCustomerSales {
+        CountryCustomerSales { country: country.to_string(), customer_id, order_year, order_count, freight, revenue }
+    }
+
+    fn rows() -> Vec<CountryCustomerSales> {
+        vec![
+            sales("Germany", 1, 2023, 2, 10.0, 100.0),
+            sales("Germany", 2, 2023, 1, 5.0, 300.0),
+            sales("UK", 3, 2023, 3, 30.0, 150.0),
+            sales("Germany", 1, 2022, 4, 40.0, 1000.0),
+            sales("Atlantis", 9, 2023, 1, 1.0, 10.0),
+        ]
+    }
+
+    fn values(metric: ChoroplethMetric) -> Vec<(String, f64)> {
+        SalesChoropleth::aggregate(rows(), &ChoroplethQuery { year: None, metric }, false).into_iter()
+            .map(|c| (c.country, c.value))
+            .collect()
+    }
+
+    fn countries(list: &[(&str, f64)]) -> Vec<(String, f64)> {
+        list.iter().map(|&(country, value)| (country.to_string(), value)).collect()
+    }
+
+    #[test]
+    fn value_follows_the_metric() {
+        assert_eq!(values(ChoroplethMetric::Revenue), countries(&[("Germany", 400.0), ("United Kingdom", 150.0), ("Atlantis", 10.0)]));
+        assert_eq!(values(ChoroplethMetric::OrderCount), countries(&[("Germany", 3.0), ("United Kingdom", 3.0), ("Atlantis", 1.0)]));
+        assert_eq!(values(ChoroplethMetric::Freight), countries(&[("United Kingdom", 30.0), ("Germany", 15.0), ("Atlantis", 1.0)]));
+        assert_eq!(values(ChoroplethMetric::DistinctCustomers), countries(&[("Germany", 2.0), ("Atlantis", 1.0), ("United Kingdom", 1.0)]));
+    }
+
+    #[test]
+    fn every_total_is_included_with_iso_codes() {
+        let map = SalesChoropleth::aggregate(rows(), &ChoroplethQuery::default(), false);
+        let germany = &map[0];
+        assert_eq!((germany.year, germany.revenue, germany.order_count, germany.freight, germany.distinct_customers), (Some(2023), 400.0, 3, 15.0, 2));
+        assert_eq!((germany.iso_alpha2.as_deref(), germany.iso_alpha3.as_deref()), (Some("DE"), Some("DEU")));
+        assert_eq!((map[1].iso_alpha2.as_deref(), map[1].iso_alpha3.as_deref()), (Some("GB"), Some("GBR")));
+
+        // An unknown country is still on the list, without codes
+        assert_eq!(map[2].country, "Atlantis");
+        assert_eq!((map[2].iso_alpha2.as_deref(), map[2].iso_alpha3.as_deref()), (None, None));
+    }
+
+    #[test]
+    fn a_date_range_covers_every_year() {
+        let map = SalesChoropleth::aggregate(rows(), &ChoroplethQuery::default(), true);
+        let germany = map.iter().find(|c| c.country == "Germany").unwrap();
+        // Customer 1 bought in both years and is counted once
+        assert_eq!((germany.year, germany.revenue, germany.order_count, germany.distinct_customers), (None, 1400.0, 7, 2));
+
+        // An explicit year wins over the range
+        let map = SalesChoropleth::aggregate(rows(), &ChoroplethQuery { year: Some(2022), ..Default::default() }, true);
+        assert_eq!(map.len(), 1);
+        assert_eq!((map[0].year, map[0].revenue), (Some(2022), 1000.0));
+
+        assert!(SalesChoropleth::aggregate(Vec::new(), &ChoroplethQuery::default(), false).is_empty());
+        assert!(SalesChoropleth::aggregate(Vec::new(), &ChoroplethQuery::default(), true).is_empty());
+    }
+}
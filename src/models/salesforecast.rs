@@ -0,0 +1,194 @@
+use chrono::Datelike;
+use mssqlapi_derive::FromRow;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use validator::{Validate, ValidationErrors};
+
+use crate::countries;
+use crate::forecast;
+use crate::models::reportfilter::ReportFilter;
+
+const SEASON: usize = 12;
+
+// Monthly revenue of one series, overall, for a country or for a category, followed by the
+// forecast for the next months with its prediction interval.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SalesForecast {
+    pub series: String,
+    // The method used, Holt-Winters falls back to seasonal naive on short histories
+    pub method: ForecastMethod,
+    pub level: u32,
+    pub residual_sd: f64,
+    pub history: Vec<MonthRevenue>,
+    pub forecast: Vec<ForecastPoint>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MonthRevenue {
+    pub period: String,
+    pub revenue: f64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ForecastPoint {
+    pub period: String,
+    pub revenue: f64,
+    pub lower: f64,
+    pub upper: f64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ForecastMethod {
+    // Holt-Winters with two years of history, seasonal naive with one
+    #[default]
+    Auto,
+    HoltWinters,
+    SeasonalNaive,
+}
+
+#[derive(Deserialize, Debug, Default, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ForecastGrouping {
+    #[default]
+    Total,
+    Country,
+    Category,
+}
+
+// ?horizon=6&by=total|country|category&method=auto|holt_winters|seasonal_naive&level=95
+#[derive(Deserialize, Validate, Debug, Default, Clone)]
+pub struct SalesForecastQuery {
+    #[validate(range(min = 1, max = 36))]
+    pub horizon: Option<u32>,
+    #[serde(default)]
+    pub by: ForecastGrouping,
+    #[serde(default)]
+    pub method: ForecastMethod,
+    // Confidence level of the prediction intervals, in percent
+    #[validate(range(min = 50, max = 99))]
+    pub level: Option<u32>,
+}
+
+impl SalesForecastQuery {
+    // The months from_date to to_date span, capped like the other reports that fill in empty months.
+    pub fn validate_range(&self, filter: &ReportFilter) -> Result<(), ValidationErrors> {
+        filter.validate_span(|from, to| {
+            (to.year() as i64 * 12 + to.month0() as i64) - (from.year() as i64 * 12 + from.month0() as i64) + 1
+        })
+    }
+}
+
+// Revenue per series and month ("YYYY-MM"), as every backend returns them.
+#[derive(Debug, FromRow)]
+pub struct MonthlyRevenue {
+    pub series: String,
+    pub period: String,
+    #[row(default)]
+    pub revenue: f64,
+}
+
+fn month_index(period: &str) -> Option<i32> {
+    let year: i32 = period.get(..4)?.parse().ok()?;
+    let month: i32 = period.get(5..7)?.parse().ok()?;
+    Some(year * 12 + month - 1)
+}
+
+fn month_label(index: i32) -> String {
+    format!("{}-{:02}", index.div_euclid(12), index.rem_euclid(12) + 1)
+}
+
+impl SalesForecast {
+    // Fills months without sales with zeros from each series' first month to the last month
+    // of the range, then forecasts every series with at least 13 months of history.
+    pub fn build(rows: Vec<MonthlyRevenue>, filter: &ReportFilter, query: &SalesForecastQuery) -> Vec<SalesForecast> {
+        let horizon = query.horizon.unwrap_or(6) as usize;
+        let level = query.level.unwrap_or(95);
+        let z = forecast::normal_quantile(level as f64);
+
+        let mut series = BTreeMap::<String, BTreeMap<i32, f64>>::new();
+        for row in rows {
+            let Some(month) = month_index(&row.period) else {
+                continue;
+            };
+            let name = match query.by {
+                ForecastGrouping::Country => countries::display_name(row.series),
+                _ => row.series,
+            };
+            *series.entry(name).or_default().entry(month).or_default() += row.revenue;
+        }
+
+        let last = filter.to_date
+            .map(|to| month_index(&to.format("%Y-%m").to_string()))
+            .unwrap_or_else(|| series.values().filter_map(|months| months.keys().last().copied()).max());
+        let Some(last) = last else {
+            return Vec::new();
+        };
+
+        let mut forecasts = Vec::new();
+        for (name, months) in series {
+            let Some(&first) = months.keys().next() else {
+                continue;
+            };
+            let values: Vec<f64> = (first..=last).map(|m| months.get(&m).copied().unwrap_or(0.0)).collect();
+
+            let holt_winters = match query.method {
+                ForecastMethod::SeasonalNaive => None,
+                _ => forecast::holt_winters(&values, SEASON, horizon),
+            };
+            let (method, fit) = match holt_winters {
+                Some(fit) => (ForecastMethod::HoltWinters, fit),
+                None => match forecast::seasonal_naive(&values, SEASON, horizon) {
+                    Some(fit) => (ForecastMethod::SeasonalNaive, fit),
+                    None => continue,
+                },
+            };
+
+            // Revenue cannot go below zero, so neither do the forecasts and their intervals
+            let points = fit.points.iter().zip(&fit.errors).enumerate()
+                .map(|(h, (point, error))| ForecastPoint {
+                    period: month_label(last + 1 + h as i32),
+                    revenue: point.max(0.0),
+                    lower: (point - z * error).max(0.0),
+                    upper: (point + z * error).max(0.0),
+                })
+                .collect();
+
+            forecasts.push(SalesForecast {
+                series: name,
+                method,
+                level,
+                residual_sd: fit.residual_sd,
+                history: values.iter().enumerate()
+                    .map(|(i, revenue)| MonthRevenue { period: month_label(first + i as i32), revenue: *revenue })
+                    .collect(),
+                forecast: points,
+            });
+        }
+
+        forecasts
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+
+    fn filter(from: &str, to: &str) -> ReportFilter {
+        ReportFilter {
+            from_date: Some(NaiveDate::parse_from_str(from, "%Y-%m-%d").unwrap()),
+            to_date: Some(NaiveDate::parse_from_str(to, "%Y-%m-%d").unwrap()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn long_ranges_are_rejected() {
+        let query = SalesForecastQuery::default();
+        // 1000 months from January 2000 to April 2083, inclusive
+        assert!(query.validate_range(&filter("2000-01-31", "2083-04-01")).is_ok());
+        assert!(query.validate_range(&filter("2000-01-31", "2083-05-01")).is_err());
+        assert!(query.validate_range(&ReportFilter::default()).is_ok());
+    }
+}
@@ -0,0 +1,278 @@
+use chrono::{Datelike, Days, Months, NaiveDate};
+use mssqlapi_derive::FromRow;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use validator::{Validate, ValidationErrors};
+
+use crate::models::reportfilter::ReportFilter;
+
+// One bucket of the time series. The optional figures are only filled in when requested
+// and are null otherwise, and also while a rolling window is not full yet or when there
+// is nothing to compare a year-over-year change with.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TimeSeriesPoint {
+    // "2023-05-14", "2023-W19", "2023-05", "2023-Q2" or "2023"
+    pub period: String,
+    pub start_date: NaiveDate,
+    pub revenue: f64,
+    pub order_count: i64,
+    pub freight: f64,
+    pub rolling_average: Option<SeriesValues>,
+    pub cumulative: Option<SeriesValues>,
+    // Percent change against the same bucket a year earlier
+    pub yoy_change: Option<SeriesChange>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default, Clone, Copy)]
+pub struct SeriesValues {
+    pub revenue: f64,
+    pub order_count: f64,
+    pub freight: f64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SeriesChange {
+    pub revenue: Option<f64>,
+    pub order_count: Option<f64>,
+    pub freight: Option<f64>,
+}
+
+// Weeks start on Monday and are labelled with their ISO week.
+#[derive(Deserialize, Debug, Default, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum TimeBucket {
+    Day,
+    Week,
+    #[default]
+    Month,
+    Quarter,
+    Year,
+}
+
+// ?bucket=month&rolling=3&cumulative=true&yoy=true over the from_date/to_date range. Without
+// a range the series runs from the first to the last order the other filters leave.
+#[derive(Deserialize, Validate, Debug, Default, Clone)]
+pub struct TimeSeriesQuery {
+    #[serde(default)]
+    pub bucket: TimeBucket,
+    // Window of the rolling averages, in buckets
+    #[validate(range(min = 2, max = 60))]
+    pub rolling: Option<u32>,
+    #[serde(default)]
+    pub cumulative: bool,
+    #[serde(default)]
+    pub yoy: bool,
+}
+
+// Sales per order date, as every backend returns them before bucketing.
+#[derive(Debug, FromRow)]
+pub struct DailySales {
+    pub order_date: NaiveDate,
+    #[row(default)]
+    pub order_count: i64,
+    #[row(default)]
+    pub freight: f64,
+    #[row(default)]
+    pub revenue: f64,
+}
+
+impl TimeBucket {
+    fn start(self, date: NaiveDate) -> NaiveDate {
+        let month_start = |month: u32| NaiveDate::from_ymd_opt(date.year(), month, 1).unwrap_or(date);
+        match self {
+            TimeBucket::Day => date,
+            TimeBucket::Week => date - Days::new(date.weekday().num_days_from_monday() as u64),
+            TimeBucket::Month => month_start(date.month()),
+            TimeBucket::Quarter => month_start((date.month() - 1) / 3 * 3 + 1),
+            TimeBucket::Year => month_start(1),
+        }
+    }
+
+    fn next(self, start: NaiveDate) -> Option<NaiveDate> {
+        match self {
+            TimeBucket::Day => start.checked_add_days(Days::new(1)),
+            TimeBucket::Week => start.checked_add_days(Days::new(7)),
+            TimeBucket::Month => start.checked_add_months(Months::new(1)),
+            TimeBucket::Quarter => start.checked_add_months(Months::new(3)),
+            TimeBucket::Year => start.checked_add_months(Months::new(12)),
+        }
+    }
+
+    // The bucket a year before, 52 weeks before for weeks so it starts on a Monday as well.
+    fn year_before(self, start: NaiveDate) -> Option<NaiveDate> {
+        match self {
+            TimeBucket::Week => start.checked_sub_days(Days::new(364)),
+            _ => start.with_year(start.year() - 1),
+        }
+    }
+
+    // Number of buckets from the one holding `from` to the one holding `to`.
+    fn count(self, from: NaiveDate, to: NaiveDate) -> i64 {
+        let months = |date: NaiveDate| date.year() as i64 * 12 + date.month0() as i64;
+        let (from, to) = (self.start(from), self.start(to));
+        let buckets = match self {
+            TimeBucket::Day => (to - from).num_days(),
+            TimeBucket::Week => (to - from).num_days() / 7,
+            TimeBucket::Month => months(to) - months(from),
+            TimeBucket::Quarter => (months(to) - months(from)) / 3,
+            TimeBucket::Year => (to.year() - from.year()) as i64,
+        };
+        buckets + 1
+    }
+
+    fn label(self, start: NaiveDate) -> String {
+        match self {
+            TimeBucket::Day => start.format("%Y-%m-%d").to_string(),
+            TimeBucket::Week => format!("{}-W{:02}", start.iso_week().year(), start.iso_week().week()),
+            TimeBucket::Month => start.format("%Y-%m").to_string(),
+            TimeBucket::Quarter => format!("{}-Q{}", start.year(), start.month().div_ceil(3)),
+            TimeBucket::Year => start.year().to_string(),
+        }
+    }
+}
+
+impl SeriesValues {
+    fn of(point: &TimeSeriesPoint) -> SeriesValues {
+        SeriesValues { revenue: point.revenue, order_count: point.order_count as f64, freight: point.freight }
+    }
+
+    fn add(&mut self, other: SeriesValues) {
+        self.revenue += other.revenue;
+        self.order_count += other.order_count;
+        self.freight += other.freight;
+    }
+}
+
+impl TimeSeriesQuery {
+    // Every bucket of the range is returned, empty or not, so the range is capped in buckets.
+    pub fn validate_range(&self, filter: &ReportFilter) -> Result<(), ValidationErrors> {
+        filter.validate_span(|from, to| self.bucket.count(from, to))
+    }
+}
+
+impl TimeSeriesPoint {
+    // The filter the backends load the daily sales with. Year-over-year changes need the year
+    // before the requested range as well.
+    pub fn history_filter(filter: &ReportFilter, query: &TimeSeriesQuery) -> ReportFilter {
+        let mut history = filter.clone();
+        if query.yoy {
+            history.from_date = filter.from_date
+                .map(|from| query.bucket.start(from))
+                .and_then(|from| from.checked_sub_months(Months::new(12)))
+                .and_then(|from| from.checked_sub_days(Days::new(7)));
+        }
+        history
+    }
+
+    // Buckets the daily sales, with a zero bucket for every one without orders. The first and
+    // last bucket only cover the part of them inside the requested range.
+    pub fn build(rows: Vec<DailySales>, filter: &ReportFilter, query: &TimeSeriesQuery) -> Vec<TimeSeriesPoint> {
+        let bucket = query.bucket;
+        let from = filter.from_date.or_else(|| rows.iter().map(|r| r.order_date).min());
+        let to = filter.to_date.or_else(|| rows.iter().map(|r| r.order_date).max());
+        let (Some(from), Some(to)) = (from, to) else {
+            return Vec::new();
+        };
+
+        // Rows before the range are only there for the year-over-year comparison
+        let mut buckets = BTreeMap::<NaiveDate, (f64, i64, f64)>::new();
+        let mut history = BTreeMap::<NaiveDate, (f64, i64, f64)>::new();
+        for row in rows {
+            let totals = if row.order_date < from { &mut history } else { &mut buckets };
+            let totals = totals.entry(bucket.start(row.order_date)).or_default();
+            totals.0 += row.revenue;
+            totals.1 += row.order_count;
+            totals.2 += row.freight;
+        }
+
+        let mut points = Vec::new();
+        let mut start = Some(bucket.start(from));
+        while let Some(current) = start.filter(|s| *s <= to) {
+            let (revenue, order_count, freight) = buckets.get(&current).copied().unwrap_or_default();
+            let yoy_change = query.yoy.then(|| {
+                let before = bucket.year_before(current)
+                    .map(|b| {
+                        let (h, b) = (history.get(&b).copied().unwrap_or_default(), buckets.get(&b).copied().unwrap_or_default());
+                        (h.0 + b.0, h.1 + b.1, h.2 + b.2)
+                    })
+                    .unwrap_or_default();
+                let change = |now: f64, before: f64| (before != 0.0).then(|| (now - before) / before * 100.0);
+                SeriesChange {
+                    revenue: change(revenue, before.0),
+                    order_count: change(order_count as f64, before.1 as f64),
+                    freight: change(freight, before.2),
+                }
+            });
+            points.push(TimeSeriesPoint {
+                period: bucket.label(current),
+                start_date: current,
+                revenue,
+                order_count,
+                freight,
+                rolling_average: None,
+                cumulative: None,
+                yoy_change,
+            });
+            start = bucket.next(current);
+        }
+
+        if let Some(window) = query.rolling.map(|w| w as usize) {
+            for i in (window - 1)..points.len() {
+                let mut sum = SeriesValues::default();
+                for point in &points[i + 1 - window..=i] {
+                    sum.add(SeriesValues::of(point));
+                }
+                let n = window as f64;
+                points[i].rolling_average = Some(SeriesValues { revenue: sum.revenue / n, order_count: sum.order_count / n, freight: sum.freight / n });
+            }
+        }
+
+        if query.cumulative {
+            let mut running = SeriesValues::default();
+            for point in &mut points {
+                running.add(SeriesValues::of(point));
+                point.cumulative = Some(running);
+            }
+        }
+
+        points
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::reportfilter::MAX_RANGE_PERIODS;
+
+    fn date(s: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(s, "%Y-%m-%d").unwrap()
+    }
+
+    #[test]
+    fn buckets_are_counted_inclusively() {
+        let (from, to) = (date("2023-01-30"), date("2023-04-02"));
+        assert_eq!(TimeBucket::Day.count(from, to), 63);
+        assert_eq!(TimeBucket::Week.count(from, to), 9);
+        assert_eq!(TimeBucket::Week.count(from, date("2023-04-03")), 10);
+        assert_eq!(TimeBucket::Month.count(from, to), 4);
+        assert_eq!(TimeBucket::Quarter.count(from, to), 2);
+        assert_eq!(TimeBucket::Year.count(from, date("2025-01-01")), 3);
+    }
+
+    #[test]
+    fn long_ranges_are_rejected() {
+        let query = |bucket| TimeSeriesQuery { bucket, ..Default::default() };
+        let range = |from: &str, to: &str| ReportFilter { from_date: Some(date(from)), to_date: Some(date(to)), ..Default::default() };
+
+        let last_day = date("2020-01-01") + Days::new(MAX_RANGE_PERIODS as u64 - 1);
+        assert!(query(TimeBucket::Day).validate_range(&range("2020-01-01", &last_day.to_string())).is_ok());
+        assert!(query(TimeBucket::Day).validate_range(&range("2020-01-01", &(last_day + Days::new(1)).to_string())).is_err());
+        assert!(query(TimeBucket::Day).validate_range(&range("1900-01-01", "2500-12-31")).is_err());
+        assert!(query(TimeBucket::Month).validate_range(&range("1990-01-01", "2030-12-31")).is_ok());
+
+        // A single far-off bound is measured against today
+        let to_only = ReportFilter { to_date: Some(date("2500-12-31")), ..Default::default() };
+        assert!(query(TimeBucket::Month).validate_range(&to_only).is_err());
+        assert!(query(TimeBucket::Day).validate_range(&ReportFilter::default()).is_ok());
+    }
+}
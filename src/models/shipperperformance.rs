@@ -0,0 +1,232 @@
+use mssqlapi_derive::FromRow;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+use crate::countries;
+use crate::models::reportfilter::ReportPeriod;
+
+// Delivery figures for one group of orders: a shipper, a destination country or both,
+// optionally per period. An order is on time when it shipped on or before its required
+// date; orders not shipped yet are counted but left out of the rates and averages.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ShipperPerformance {
+    pub shipper_id: Option<i32>,
+    pub shipper_name: Option<String>,
+    pub ship_country: Option<String>,
+    pub period: Option<String>,
+    pub order_count: i64,
+    pub shipped_count: i64,
+    pub unshipped_count: i64,
+    pub on_time_count: i64,
+    pub late_count: i64,
+    pub on_time_rate: Option<f64>,
+    // Shipped date minus required date in days, negative when orders shipped early
+    pub average_days_late: Option<f64>,
+    pub freight: f64,
+    pub freight_per_order: f64,
+    pub order_value: f64,
+    // Freight as a percent of the net order value
+    pub freight_percent: Option<f64>,
+}
+
+#[derive(Deserialize, Debug, Default, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ShipmentGrouping {
+    #[default]
+    Shipper,
+    Country,
+    ShipperCountry,
+}
+
+// ?group_by=shipper|country|shipper_country&period=year|quarter|month. Without a period the
+// figures cover the whole from_date/to_date range.
+#[derive(Deserialize, Debug, Default, Clone)]
+pub struct ShipperPerformanceQuery {
+    #[serde(default)]
+    pub group_by: ShipmentGrouping,
+    pub period: Option<ReportPeriod>,
+}
+
+// Totals per shipper, destination country and period as every backend returns them.
+// Without a requested period the backends group by year and the years are summed here.
+#[derive(Debug, FromRow)]
+pub struct ShipmentTotals {
+    pub shipper_id: i32,
+    pub shipper_name: String,
+    pub ship_country: String,
+    pub period: String,
+    #[row(default)]
+    pub order_count: i64,
+    #[row(default)]
+    pub shipped_count: i64,
+    #[row(default)]
+    pub on_time_count: i64,
+    // Sum of shipped date minus required date over the shipped orders
+    #[row(default)]
+    pub days_late: i64,
+    #[row(default)]
+    pub freight: f64,
+    #[row(default)]
+    pub order_value: f64,
+}
+
+#[derive(Default)]
+struct GroupTotals {
+    shipper_name: Option<String>,
+    order_count: i64,
+    shipped_count: i64,
+    on_time_count: i64,
+    days_late: i64,
+    freight: f64,
+    order_value: f64,
+}
+
+impl ShipperPerformance {
+    // Sums the rows into the requested grouping. Countries are grouped by display name, so the
+    // stored spellings of one country end up together.
+    pub fn aggregate(rows: Vec<ShipmentTotals>, query: &ShipperPerformanceQuery) -> Vec<ShipperPerformance> {
+        let mut groups = BTreeMap::<(Option<String>, Option<i32>, Option<String>), GroupTotals>::new();
+        for row in rows {
+            let shipper = match query.group_by {
+                ShipmentGrouping::Country => None,
+                _ => Some(row.shipper_id),
+            };
+            let country = match query.group_by {
+                ShipmentGrouping::Shipper => None,
+                _ => Some(countries::display_name(row.ship_country)),
+            };
+            let period = query.period.map(|_| row.period);
+
+            let totals = groups.entry((period, shipper, country)).or_default();
+            if shipper.is_some() {
+                totals.shipper_name = Some(row.shipper_name);
+            }
+            totals.order_count += row.order_count;
+            totals.shipped_count += row.shipped_count;
+            totals.on_time_count += row.on_time_count;
+            totals.days_late += row.days_late;
+            totals.freight += row.freight;
+            totals.order_value += row.order_value;
+        }
+
+        groups.into_iter()
+            .map(|((period, shipper_id, ship_country), t)| {
+                let shipped = (t.shipped_count > 0).then_some(t.shipped_count as f64);
+                ShipperPerformance {
+                    shipper_id,
+                    shipper_name: t.shipper_name,
+                    ship_country,
+                    period,
+                    order_count: t.order_count,
+                    shipped_count: t.shipped_count,
+                    unshipped_count: t.order_count - t.shipped_count,
+                    on_time_count: t.on_time_count,
+                    late_count: t.shipped_count - t.on_time_count,
+                    on_time_rate: shipped.map(|s| t.on_time_count as f64 / s),
+                    average_days_late: shipped.map(|s| t.days_late as f64 / s),
+                    freight: t.freight,
+                    freight_per_order: if t.order_count > 0 { t.freight / t.order_count as f64 } else { 0.0 },
+                    order_value: t.order_value,
+                    freight_percent: (t.order_value > 0.0).then(|| t.freight / t.order_value * 100.0),
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // counts: orders, shipped, on time and the summed days late
+    fn totals(shipper_id: i32, ship_country: &str, period: &str, counts: [i64; 4], freight: f64, order_value: f64) -> ShipmentTotals {
+        let [order_count, shipped_count, on_time_count, days_late] = counts;
+        ShipmentTotals {
+            shipper_id,
+            shipper_name: format!("Shipper {shipper_id}"),
+            ship_country: ship_country.to_string(),
+            period: period.to_string(),
+            order_count,
+            shipped_count,
+            on_time_count,
+            days_late,
+            freight,
+            order_value,
+        }
+    }
+
+    // Shipper 2 has shipped none of its German orders yet
+    fn rows() -> Vec<ShipmentTotals> {
+        vec![
+            totals(1, "UK", "2022", [4, 4, 3, 2], 40.0, 800.0),
+            totals(1, "United Kingdom", "2023", [2, 1, 0, 5], 10.0, 200.0),
+            totals(2, "Germany", "2023", [2, 0, 0, 0], 6.0, 0.0),
+            totals(2, "UK", "2023", [2, 2, 2, -4], 4.0, 100.0),
+        ]
+    }
+
+    fn aggregate(group_by: ShipmentGrouping, period: Option<ReportPeriod>) -> Vec<ShipperPerformance> {
+        ShipperPerformance::aggregate(rows(), &ShipperPerformanceQuery { group_by, period })
+    }
+
+    #[test]
+    fn rates_and_averages_per_shipper() {
+        let shippers = aggregate(ShipmentGrouping::Shipper, None);
+        assert_eq!(shippers.len(), 2);
+
+        let first = &shippers[0];
+        assert_eq!((first.shipper_id, first.shipper_name.as_deref(), first.ship_country.as_deref(), first.period.as_deref()), (Some(1), Some("Shipper 1"), None, None));
+        assert_eq!((first.order_count, first.shipped_count, first.unshipped_count, first.on_time_count, first.late_count), (6, 5, 1, 3, 2));
+        assert_eq!((first.on_time_rate, first.average_days_late), (Some(0.6), Some(1.4)));
+        assert_eq!((first.freight, first.order_value, first.freight_percent), (50.0, 1000.0, Some(5.0)));
+        assert!((first.freight_per_order - 50.0 / 6.0).abs() < 1e-9);
+
+        // Shipping early gives a negative average
+        let second = &shippers[1];
+        assert_eq!((second.late_count, second.on_time_rate, second.average_days_late), (0, Some(1.0), Some(-2.0)));
+        assert_eq!((second.freight_per_order, second.freight_percent), (2.5, Some(10.0)));
+    }
+
+    #[test]
+    fn countries_are_grouped_by_display_name() {
+        let countries = aggregate(ShipmentGrouping::Country, None);
+        let names: Vec<Option<&str>> = countries.iter().map(|c| c.ship_country.as_deref()).collect();
+        assert_eq!(names, vec![Some("Germany"), Some("United Kingdom")]);
+        assert!(countries.iter().all(|c| c.shipper_id.is_none() && c.shipper_name.is_none()));
+
+        let uk = &countries[1];
+        assert_eq!((uk.order_count, uk.shipped_count, uk.on_time_count, uk.late_count), (8, 7, 5, 2));
+        assert!((uk.average_days_late.unwrap() - 3.0 / 7.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn nothing_shipped_means_no_rates() {
+        let germany = &aggregate(ShipmentGrouping::Country, None)[0];
+        assert_eq!((germany.order_count, germany.shipped_count, germany.unshipped_count, germany.late_count), (2, 0, 2, 0));
+        assert_eq!((germany.on_time_rate, germany.average_days_late, germany.freight_percent), (None, None, None));
+        assert_eq!(germany.freight_per_order, 3.0);
+    }
+
+    #[test]
+    fn shipper_and_country_pairs_and_periods() {
+        let pairs: Vec<(Option<i32>, Option<String>, i64)> = aggregate(ShipmentGrouping::ShipperCountry, None).into_iter()
+            .map(|p| (p.shipper_id, p.ship_country, p.order_count))
+            .collect();
+        assert_eq!(pairs, vec![
+            (Some(1), Some("United Kingdom".to_string()), 6),
+            (Some(2), Some("Germany".to_string()), 2),
+            (Some(2), Some("United Kingdom".to_string()), 2),
+        ]);
+
+        let periods: Vec<(Option<String>, Option<i32>, i64)> = aggregate(ShipmentGrouping::Shipper, Some(ReportPeriod::Year)).into_iter()
+            .map(|p| (p.period, p.shipper_id, p.order_count))
+            .collect();
+        assert_eq!(periods, vec![
+            (Some("2022".to_string()), Some(1), 4),
+            (Some("2023".to_string()), Some(1), 2),
+            (Some("2023".to_string()), Some(2), 4),
+        ]);
+
+        assert!(ShipperPerformance::aggregate(Vec::new(), &ShipperPerformanceQuery::default()).is_empty());
+    }
+}
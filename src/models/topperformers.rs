@@ -1,17 +1,197 @@
+use mssqlapi_derive::FromRow;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use validator::Validate;
 
+// One ranked customer with the employees who took its orders in that year,
+// the employee with the highest sales to the customer first.
 #[derive(Debug, Serialize, Deserialize)]
 pub struct TopPerformers {
-    pub customer_thhdp: String,
-    pub customer_cyztn: String,
-    pub customer_ibvrg: String,
-    pub customer_frxzl: String,
-    pub customer_gllag: String,
-    pub customer_irrvl: String,
-    pub customer_nyuhs: String,
-    pub customer_lcouj: String,
-    pub customer_sfogw: String,
-    pub customer_ybqti: String,
+    pub rank: u32,
+    pub customer_id: i32,
+    pub customer: String,
+    pub year: i32,
+    pub sales: f64,
+    pub order_count: i64,
+    pub quantity: i64,
+    pub employees: Vec<String>,
+}
+
+#[derive(Deserialize, Debug, Default, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum RankingMetric {
+    #[default]
+    Revenue,
+    OrderCount,
+    Quantity,
+}
+
+// ?top=10&year=2023&metric=revenue, every parameter optional.
+#[derive(Deserialize, Validate, Debug, Default, Clone)]
+pub struct TopPerformersQuery {
+    #[validate(range(min = 1, max = 100))]
+    pub top: Option<u32>,
+    #[validate(range(min = 1900, max = 9999))]
+    pub year: Option<i32>,
+    #[serde(default)]
+    pub metric: RankingMetric,
+}
+
+// Sales per customer, employee and year, as every backend returns them before ranking.
+#[derive(Debug, FromRow)]
+pub struct CustomerEmployeeSales {
+    pub customer_id: i32,
+    pub customer_name: String,
+    pub employee_name: String,
+    pub order_year: i32,
+    #[row(default)]
+    pub order_count: i64,
+    #[row(default)]
+    pub quantity: i64,
+    #[row(default)]
+    pub sales: f64,
+}
+
+#[derive(Default)]
+struct CustomerTotals {
+    customer: String,
+    sales: f64,
+    order_count: i64,
+    quantity: i64,
+    employees: Vec<(String, f64)>,
+}
+
+impl TopPerformers {
+    // Ranks the customers of the requested year (the latest year in the rows by default) by
+    // the chosen metric and keeps the top N. Ties share a rank and are all kept, like
+    // TOP N WITH TIES, so the result can be longer than N.
+    pub fn rank(rows: Vec<CustomerEmployeeSales>, query: &TopPerformersQuery) -> Vec<TopPerformers> {
+        let top = query.top.unwrap_or(10);
+        let Some(year) = query.year.or_else(|| rows.iter().map(|r| r.order_year).max()) else {
+            return Vec::new();
+        };
+
+        let mut customers = BTreeMap::<i32, CustomerTotals>::new();
+        for row in rows.into_iter().filter(|r| r.order_year == year) {
+            let totals = customers.entry(row.customer_id).or_default();
+            totals.customer = row.customer_name;
+            totals.sales += row.sales;
+            totals.order_count += row.order_count;
+            totals.quantity += row.quantity;
+            totals.employees.push((row.employee_name, row.sales));
+        }
+
+        let metric = |t: &CustomerTotals| match query.metric {
+            RankingMetric::Revenue => t.sales,
+            RankingMetric::OrderCount => t.order_count as f64,
+            RankingMetric::Quantity => t.quantity as f64,
+        };
+
+        let mut ranked: Vec<(i32, CustomerTotals)> = customers.into_iter().collect();
+        ranked.sort_by(|a, b| metric(&b.1).total_cmp(&metric(&a.1)));
 
+        let mut top_performers = Vec::new();
+        let mut rank = 0;
+        let mut previous = None;
+        for (position, (customer_id, mut totals)) in ranked.into_iter().enumerate() {
+            let value = metric(&totals);
+            if previous != Some(value) {
+                rank = position as u32 + 1;
+                previous = Some(value);
+            }
+            if rank > top {
+                break;
+            }
+
+            totals.employees.sort_by(|a, b| b.1.total_cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+            top_performers.push(TopPerformers {
+                rank,
+                customer_id,
+                customer: totals.customer,
+                year,
+                sales: totals.sales,
+                order_count: totals.order_count,
+                quantity: totals.quantity,
+                employees: totals.employees.into_iter().map(|(name, _)| name).collect(),
+            });
+        }
+
+        top_performers
+    }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sales(customer_id: i32, customer: &str, employee: &str, order_year: i32, sales: f64, order_count: i64, quantity: i64) -> CustomerEmployeeSales {
+        CustomerEmployeeSales {
+            customer_id,
+            customer_name: customer.to_string(),
+            employee_name: employee.to_string(),
+            order_year,
+            order_count,
+            quantity,
+            sales,
+        }
+    }
+
+    // In 2023 A and B tie on revenue, C has the most orders and B the largest quantity
+    fn rows() -> Vec<CustomerEmployeeSales> {
+        vec![
+            sales(1, "A", "Davis, Sara", 2023, 100.0, 2, 10),
+            sales(1, "A", "King, Russell", 2023, 300.0, 1, 5),
+            sales(2, "B", "Davis, Sara", 2023, 400.0, 1, 40),
+            sales(3, "C", "Peled, Yael", 2023, 25.0, 2, 1),
+            sales(3, "C", "Buck, Sven", 2023, 25.0, 2, 0),
+            sales(4, "D", "Davis, Sara", 2022, 1000.0, 9, 90),
+        ]
+    }
+
+    fn ranks(query: &TopPerformersQuery) -> Vec<(u32, String)> {
+        TopPerformers::rank(rows(), query).into_iter().map(|t| (t.rank, t.customer)).collect()
+    }
+
+    fn ranked(list: &[(u32, &str)]) -> Vec<(u32, String)> {
+        list.iter().map(|&(rank, customer)| (rank, customer.to_string())).collect()
+    }
+
+    #[test]
+    fn customers_are_ranked_by_the_metric() {
+        let by = |metric| TopPerformersQuery { metric, ..Default::default() };
+        assert_eq!(ranks(&by(RankingMetric::Revenue)), ranked(&[(1, "A"), (1, "B"), (3, "C")]));
+        assert_eq!(ranks(&by(RankingMetric::OrderCount)), ranked(&[(1, "C"), (2, "A"), (3, "B")]));
+        assert_eq!(ranks(&by(RankingMetric::Quantity)), ranked(&[(1, "B"), (2, "A"), (3, "C")]));
+    }
+
+    #[test]
+    fn ties_are_all_kept() {
+        let top = |top| TopPerformersQuery { top: Some(top), ..Default::default() };
+        assert_eq!(ranks(&top(1)), ranked(&[(1, "A"), (1, "B")]));
+        assert_eq!(ranks(&top(2)), ranked(&[(1, "A"), (1, "B")]));
+        assert_eq!(ranks(&top(3)).len(), 3);
+    }
+
+    #[test]
+    fn the_latest_year_is_the_default() {
+        let latest = TopPerformers::rank(rows(), &TopPerformersQuery::default());
+        assert!(latest.iter().all(|t| t.year == 2023));
+
+        let earlier = TopPerformers::rank(rows(), &TopPerformersQuery { year: Some(2022), ..Default::default() });
+        assert_eq!(earlier.len(), 1);
+        assert_eq!((earlier[0].customer_id, earlier[0].sales, earlier[0].order_count, earlier[0].quantity), (4, 1000.0, 9, 90));
+
+        assert!(TopPerformers::rank(rows(), &TopPerformersQuery { year: Some(2021), ..Default::default() }).is_empty());
+        assert!(TopPerformers::rank(Vec::new(), &TopPerformersQuery::default()).is_empty());
+    }
+
+    #[test]
+    fn totals_add_up_and_employees_are_ordered_by_sales() {
+        let ranked = TopPerformers::rank(rows(), &TopPerformersQuery::default());
+        let a = &ranked[0];
+        assert_eq!((a.sales, a.order_count, a.quantity), (400.0, 3, 15));
+        assert_eq!(a.employees, vec!["King, Russell", "Davis, Sara"]);
+        // Equal sales fall back to the name
+        assert_eq!(ranked[2].employees, vec!["Buck, Sven", "Peled, Yael"]);
+    }
+}